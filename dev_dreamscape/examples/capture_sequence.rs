@@ -0,0 +1,51 @@
+// Renders a clip of the scene offline into numbered frames.
+//
+//     cargo run --release --example capture_sequence -- --size 3840x2160 --fps 60 --end 5 --ss 2
+//
+// Options (defaults in SequenceSettings):
+//     --out <dir>        output directory
+//     --size <w>x<h>     output resolution
+//     --fps <n>          frame rate
+//     --start <seconds>  scene time of the first frame
+//     --end <seconds>    scene time of the last frame
+//     --ss <n>           supersampling factor
+//     --exr              write the linear hdr buffer as EXR instead of PNG
+//     --panorama <width> render 360° equirectangular frames of the given width
+//     --stereo           top/bottom stereo panoramas (with --panorama)
+//     --fallback         force a software adapter
+
+use dev_dreamscape::{capture_sequence, FrameFormat, PanoramaSettings, SequenceSettings};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut settings = SequenceSettings::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--out" => settings.output_dir = value()?.into(),
+            "--size" => {
+                let size = value()?;
+                let (width, height) = size.split_once('x')
+                    .ok_or_else(|| anyhow::anyhow!("Expected <width>x<height>, got {}", size))?;
+                settings.width = width.parse()?;
+                settings.height = height.parse()?;
+            }
+            "--fps" => settings.fps = value()?.parse()?,
+            "--start" => settings.start_time = value()?.parse()?,
+            "--end" => settings.end_time = value()?.parse()?,
+            "--ss" => settings.supersampling = value()?.parse()?,
+            "--exr" => settings.format = FrameFormat::Exr,
+            "--panorama" => {
+                let panorama = settings.panorama.get_or_insert_with(PanoramaSettings::default);
+                panorama.width = value()?.parse()?;
+            }
+            "--stereo" => settings.panorama.get_or_insert_with(PanoramaSettings::default).stereo = true,
+            "--fallback" => settings.force_fallback_adapter = true,
+            _ => anyhow::bail!("Unknown argument {}", arg),
+        }
+    }
+
+    pollster::block_on(capture_sequence(&settings))
+}
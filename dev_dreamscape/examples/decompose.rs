@@ -0,0 +1,12 @@
+// Writes the convex decompositions of the models that moving bodies collide as, next to the
+// models in res/ (e.g. res/Rock1/RedishRock.parts.obj). The app loads them like the other
+// resources, and decomposes at startup if they're missing or out of date.
+//
+//     cargo run --release --example decompose
+
+use std::path::Path;
+
+fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    dev_dreamscape::write_convex_decompositions(&Path::new(env!("CARGO_MANIFEST_DIR")).join("res"))
+}
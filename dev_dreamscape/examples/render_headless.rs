@@ -0,0 +1,27 @@
+// Renders a single frame without a window and saves it as a PNG.
+//
+//     cargo run --example render_headless -- [output.png] [--fallback]
+//
+// --fallback forces a software adapter (e.g. lavapipe), for machines without a GPU.
+
+use dev_dreamscape::App;
+use std::time::Duration;
+
+fn main() {
+    env_logger::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let force_fallback_adapter = args.iter().any(|arg| arg == "--fallback");
+    let output = args.iter()
+        .find(|arg| !arg.starts_with("--"))
+        .cloned()
+        .unwrap_or_else(|| "headless.png".to_string());
+
+    pollster::block_on(async {
+        let mut app = App::new_headless(1280, 720, force_fallback_adapter).await;
+        app.load_assets().await;
+        let image = app.render_to_image(Duration::from_secs_f32(1.0 / 60.0));
+        image.save(&output).unwrap();
+        println!("Saved {}", output);
+    });
+}
@@ -0,0 +1,54 @@
+// Renders a single frame at a size larger than the GPU's max texture size, tile by tile.
+//
+//     cargo run --release --example render_poster -- --size 16384x16384 --out poster.png
+//
+// Options (defaults in PosterSettings):
+//     --out <file>       output image, .png or .exr (untonemapped)
+//     --size <w>x<h>     poster resolution
+//     --tile <n>         tile size
+//     --margin <n>       extra pixels rendered around each tile
+//     --fallback         force a software adapter
+
+use dev_dreamscape::{App, PosterSettings};
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut settings = PosterSettings::default();
+    let mut output = PathBuf::from("poster.png");
+    let mut force_fallback_adapter = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--out" => output = value()?.into(),
+            "--size" => {
+                let size = value()?;
+                let (width, height) = size.split_once('x')
+                    .ok_or_else(|| anyhow::anyhow!("Expected <width>x<height>, got {}", size))?;
+                settings.width = width.parse()?;
+                settings.height = height.parse()?;
+            }
+            "--tile" => settings.tile_size = value()?.parse()?,
+            "--margin" => settings.margin = value()?.parse()?,
+            "--fallback" => force_fallback_adapter = true,
+            _ => anyhow::bail!("Unknown argument {}", arg),
+        }
+    }
+
+    pollster::block_on(async {
+        // The window size doesn't matter, tiles are rendered into their own textures
+        let mut app = App::new_headless(256, 256, force_fallback_adapter).await;
+        app.load_assets().await;
+        let frame_duration = Duration::from_secs_f32(1.0 / 60.0);
+        if output.extension().is_some_and(|ext| ext == "exr") {
+            app.render_poster_to_hdr_image(&settings, frame_duration).save(&output)?;
+        } else {
+            app.render_poster_to_image(&settings, frame_duration).save(&output)?;
+        }
+        println!("Saved {:?}", output);
+        anyhow::Ok(())
+    })
+}
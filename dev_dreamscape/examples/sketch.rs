@@ -0,0 +1,74 @@
+// Renders a Shadertoy style sketch without a window into numbered PNG frames.
+//
+//     cargo run --release --example sketch -- examples/sketches/trails_image.wgsl \
+//         --buffer examples/sketches/trails_buffer.wgsl --frames 120
+//
+// Options:
+//     <file>             image shader
+//     --buffer <file>    buffer shader, in order for buffer_a to buffer_d (repeatable)
+//     --overlay          draw over the 3D scene instead of replacing it
+//     --out <dir>        output directory
+//     --size <w>x<h>     output resolution
+//     --frames <n>       number of frames
+//     --fps <n>          frame rate
+//     --fallback         force a software adapter
+
+use dev_dreamscape::{App, Sketch, SketchMode};
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut image = None;
+    let mut buffers = vec![];
+    let mut mode = SketchMode::Replace;
+    let mut output_dir = PathBuf::from("sketch");
+    let (mut width, mut height) = (1280, 720);
+    let mut frames = 60;
+    let mut fps = 60.0;
+    let mut force_fallback_adapter = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--buffer" => buffers.push(value()?),
+            "--overlay" => mode = SketchMode::Overlay,
+            "--out" => output_dir = value()?.into(),
+            "--size" => {
+                let size = value()?;
+                let (w, h) = size.split_once('x')
+                    .ok_or_else(|| anyhow::anyhow!("Expected <width>x<height>, got {}", size))?;
+                width = w.parse()?;
+                height = h.parse()?;
+            }
+            "--frames" => frames = value()?.parse()?,
+            "--fps" => fps = value()?.parse()?,
+            "--fallback" => force_fallback_adapter = true,
+            _ if !arg.starts_with("--") => image = Some(arg),
+            _ => anyhow::bail!("Unknown argument {}", arg),
+        }
+    }
+    let image = image.ok_or_else(|| anyhow::anyhow!("Missing image shader"))?;
+
+    pollster::block_on(async {
+        let mut app = App::new_headless(width, height, force_fallback_adapter).await;
+        app.load_assets().await;
+        for path in buffers.iter().chain([&image]) {
+            app.add_shader(path, std::fs::read_to_string(path)?);
+        }
+        let mut sketch = app.world.resource_mut::<Sketch>();
+        sketch.buffers = buffers;
+        sketch.image = Some(image);
+        sketch.mode = mode;
+
+        std::fs::create_dir_all(&output_dir)?;
+        let frame_duration = Duration::from_secs_f32(1.0 / fps);
+        for frame in 0..frames {
+            let path = output_dir.join(format!("frame-{:05}.png", frame));
+            app.render_to_image(frame_duration).save(&path)?;
+        }
+        println!("Saved {} frames to {}", frames, output_dir.display());
+        Ok(())
+    })
+}
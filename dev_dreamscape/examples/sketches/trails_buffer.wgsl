@@ -0,0 +1,16 @@
+// Buffer A: a glowing dot orbiting the centre (or following the mouse while dragging),
+// leaving trails that fade out. Reads its own previous frame for the feedback.
+
+fn main_image(frag_coord: vec2<f32>, resolution: vec2<f32>, time: f32, mouse: vec4<f32>) -> vec4<f32> {
+    let previous = textureSample(buffer_a, buffer_sampler, buffer_uv(frag_coord));
+
+    var center = resolution * 0.5 + vec2<f32>(cos(time), sin(time * 1.3)) * resolution.y * 0.3;
+    if mouse.z > 0.0 {
+        center = mouse.xy;
+    }
+    let distance = length(frag_coord - center) / resolution.y;
+    let glow = smoothstep(0.03, 0.0, distance);
+    let color = vec3<f32>(0.5 + 0.5 * cos(time + vec3<f32>(0.0, 2.0, 4.0)));
+
+    return vec4<f32>(previous.rgb * 0.97 + color * glow * 4.0, 1.0);
+}
@@ -0,0 +1,8 @@
+// Image: shows buffer A over a dark gradient. Values above 1 are fine, the hdr pass tonemaps them.
+
+fn main_image(frag_coord: vec2<f32>, resolution: vec2<f32>, time: f32, mouse: vec4<f32>) -> vec4<f32> {
+    let uv = frag_coord / resolution;
+    let background = vec3<f32>(0.02, 0.02, 0.05) * (1.0 - uv.y);
+    let trails = buffer_texel(buffer_a, frag_coord).rgb;
+    return vec4<f32>(background + trails, 1.0);
+}
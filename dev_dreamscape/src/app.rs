@@ -1,12 +1,17 @@
 use crate::device::{Device, SurfaceSize};
 use crate::events::{KeyboardEvent, MouseEvent, WindowResizeEvent,
-                    FrameTimeEvent, CameraSetEvent, HandUpdateEvent};
+                    FrameTimeEvent, CameraSetEvent, HandUpdateEvent,
+                    CollisionStarted, CollisionEnded, ContactForce,
+                    TriggerEnter, TriggerExit, JointBroken};
 use crate::frame_time::FrameTime;
 use crate::math::{Rect, Vec3f, UnitQuatf, Mat4f};
 use crate::input::Input;
-use crate::physics_world::PhysicsWorld;
+use crate::physics_debug::PhysicsDebugRender;
+use crate::physics_world::{PhysicsSettings, PhysicsTimestep, PhysicsWorld, WorldBounds};
+use crate::renderers::{DebugLines, DebugView, PostProcess, RenderGraph, Sketch};
 use bevy_ecs::prelude::*;
 use bevy_ecs::system::SystemState;
+use image::{ImageBuffer, Pixel, Rgba32FImage, RgbaImage};
 use winit::event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget};
 
 #[cfg(target_arch="wasm32")]
@@ -20,12 +25,13 @@ use winit::event::{DeviceEvent, ElementState, Event, KeyboardInput, WindowEvent}
 
 use crate::systems::*;
 use crate::assets::Assets;
-use crate::components::{Camera, Light, ModelSpec, Player, Skybox, Transform};
+use crate::capture::{image_ops, PanoramaSettings, PosterSettings, Screenshots, TextureReadback};
 
 use crate::logging::{init_logging, printlog};
 
 use std::cell::RefCell;
 use std::rc::Rc;
+use tracing::Instrument;
 
 #[derive(Resource)]
 pub struct AppState {
@@ -36,6 +42,8 @@ pub struct AppState {
 
 pub struct App {
     pub world: World,
+    // Whether the cameras drawing into textures were rendered since the last update_scene
+    texture_targets_rendered: bool,
 }
 
 /*
@@ -62,27 +70,56 @@ struct CachedSystemState {
 
 impl App {
     pub async fn new(window: Window, webxr: bool) -> Self {
+        let device = Device::new(&window).await;
+        Self::with_device(device, Some(window), webxr, webxr)
+    }
+
+    // App without a window, rendering offscreen at the given size. Frames are advanced
+    // manually with update_scene, or with render_to_image which also reads the frame back.
+    pub async fn new_headless(width: u32, height: u32, force_fallback_adapter: bool) -> Self {
+        let device = Device::new_headless(width, height, force_fallback_adapter).await;
+        Self::with_device(device, None, false, true)
+    }
+
+    // Same as new_headless, but None when there's no suitable adapter
+    pub async fn try_new_headless(width: u32, height: u32, force_fallback_adapter: bool) -> Option<Self> {
+        let device = Device::try_new_headless(width, height, force_fallback_adapter).await?;
+        Some(Self::with_device(device, None, false, true))
+    }
+
+    fn with_device(device: Device, window: Option<Window>, webxr: bool, frametime_manual: bool) -> Self {
 
         let mut world = World::default();
         world.init_resource::<Schedules>();
 
         printlog("running run_app - created world");
-        let device = Device::new(&window).await;
         let renderers = Renderers::new(&device, webxr);
 
         world.insert_resource(device);
         world.insert_resource(renderers);
-        world.insert_non_send_resource(window);
+        if let Some(window) = window {
+            world.insert_non_send_resource(window);
+        }
 
         world.insert_resource(AppState {
             running: true,
             webxr: webxr,
-            frametime_manual: webxr
+            frametime_manual
         });
         //NOTE not sure if this ok as just init_resource
         world.insert_resource(FrameTime::new());
         world.insert_resource(Input::new());
         world.insert_resource(PhysicsWorld::new());
+        world.init_resource::<PhysicsTimestep>();
+        world.init_resource::<PhysicsSettings>();
+        // Well below the floor
+        world.insert_resource(WorldBounds::below(-100.0));
+        world.init_resource::<DebugView>();
+        world.init_resource::<DebugLines>();
+        world.init_resource::<PhysicsDebugRender>();
+        world.init_resource::<Screenshots>();
+        world.init_resource::<Sketch>();
+        world.init_resource::<PostProcess>();
 
         // Events
         world.init_resource::<Events<WindowResizeEvent>>();
@@ -91,6 +128,12 @@ impl App {
         world.init_resource::<Events<FrameTimeEvent>>();
         world.init_resource::<Events<HandUpdateEvent>>();
         world.init_resource::<Events<CameraSetEvent>>();
+        world.init_resource::<Events<CollisionStarted>>();
+        world.init_resource::<Events<CollisionEnded>>();
+        world.init_resource::<Events<ContactForce>>();
+        world.init_resource::<Events<TriggerEnter>>();
+        world.init_resource::<Events<TriggerExit>>();
+        world.init_resource::<Events<JointBroken>>();
 
         /*
         let world_systemstate: SystemState<(
@@ -128,17 +171,35 @@ impl App {
 
         Self {
             world,
+            texture_targets_rendered: false,
         }
     }
 
     pub async fn load_assets(&mut self) {
         printlog("Loading assets outside schedule");
-        let assets = Assets::load_and_return(&self.world.resource::<Device>()).await;
+        let assets = Assets::load_and_return(&self.world.resource::<Device>())
+            .instrument(tracing::info_span!("load_assets"))
+            .await;
         printlog("Done loading assets outside schedule");
         self.world.insert_resource(assets);
     }
 
-    fn world_systemstate_get_mut(&mut self) -> (NonSend<Window>,Res<Device>,Res<Assets>,
+    // Replaces the scene and its assets with a fixed one built from primitives, which needs
+    // no files from res/. Used by the golden image tests. Call instead of load_assets.
+    pub fn load_reference_scene(&mut self) {
+        let assets = Assets::builtin(self.world.resource::<Device>());
+        self.world.insert_resource(assets);
+        let spawn_scene_schedule = new_reference_scene_schedule();
+        self.world.add_schedule(spawn_scene_schedule.0, spawn_scene_schedule.1);
+    }
+
+    // Adds a WGSL source to the asset shader store, for shaders that don't ship in res/,
+    // e.g. sketches loaded from the command line. Call after load_assets.
+    pub fn add_shader(&mut self, path: &str, source: String) {
+        self.world.resource_mut::<Assets>().shader_store.insert(path.to_string(), source);
+    }
+
+    fn world_systemstate_get_mut(&mut self) -> (Option<NonSend<Window>>,Res<Device>,Res<Assets>,
                                 ResMut<Renderers>,//NonSendMut<EventLoop<()>>,
                                 ResMut<Input>,
                                 EventWriter<WindowResizeEvent>, EventWriter<KeyboardEvent>,
@@ -146,7 +207,7 @@ impl App {
                                 EventWriter<HandUpdateEvent>, EventWriter<CameraSetEvent>) {
 
         let mut world_systemstate: SystemState<(
-            NonSend<Window>,
+            Option<NonSend<Window>>,
             Res<Device>,
             Res<Assets>,
             ResMut<Renderers>,
@@ -162,21 +223,24 @@ impl App {
         world_systemstate.get_mut(&mut self.world)
     }
 
-    #[allow(dead_code)]
     pub fn device(&self) -> &Device {
         //let (_,device,_,_,_,_,_,_,_,_) = self.world_systemstate_get_mut();
         //&device
         self.world.resource::<Device>()
     }
 
-    #[allow(dead_code)]
     pub fn color_format(&self) -> wgpu::TextureFormat {
         //let (_,device,_,_,_,_,_,_,_,_) = self.world_systemstate_get_mut();
         //device.surface_texture_format()
         self.world.resource::<Device>().surface_texture_format()
     }
 
-    #[allow(dead_code)]
+    // The passes rendering each view, for registering custom ones, e.g. before "tonemap"
+    // to work on the hdr image
+    pub fn render_graph(&mut self) -> Mut<'_, RenderGraph> {
+        self.world.resource_mut::<Renderers>().map_unchanged(|renderers| &mut renderers.graph)
+    }
+
     pub fn update_scene(&mut self, duration: std::time::Duration) {
         //TODO need to set the time via event
         let (_,_,_,_,_,_,_,_,mut frametime_events, _, _) = 
@@ -184,12 +248,15 @@ impl App {
         frametime_events.send(FrameTimeEvent {
             duration,
         });
+        self.world.resource_mut::<Renderers>().end_frame();
+        self.texture_targets_rendered = false;
         self.world.run_schedule(SpawnLabel);
         self.world.run_schedule(PreupdateLabel);
         self.world.run_schedule(UpdateLabel);
+        // Lets RemovedComponents forget the removals the systems have seen
+        self.world.clear_trackers();
     }
 
-    #[allow(dead_code)]
     pub fn update_hand(
         &mut self,
         hand: bool,
@@ -206,7 +273,6 @@ impl App {
         self.world.run_schedule(HandUpdateLabel);
     }
 
-    #[allow(dead_code)]
     pub fn update_camera(&mut self, pos: Vec3f, rot: UnitQuatf, projection_matrix: Mat4f) {
         let (_,_,_,_,_,_,_,_,_,_, mut cameraset_events) = 
                             self.world_systemstate_get_mut();
@@ -218,34 +284,157 @@ impl App {
         self.world.run_schedule(CameraUpdateLabel);
     }
 
-    #[allow(dead_code)]
     pub fn render_to_texture(&mut self, color_texture: &wgpu::Texture, viewport: Option<Rect>, clear: bool) {
+        let _span = tracing::info_span!("render_to_texture").entered();
 
-        let mut world_w_queries_systemstate: SystemState<(
-            Res<Device>,
-            Res<Assets>,
-            ResMut<Renderers>,
-            Query<(&Camera, &Transform), With<Player>>,
-            Query<&Skybox>,
-            Query<(&ModelSpec, &Transform)>,
-            Query<(&Light, &Transform)>,
-        )> = SystemState::from_world(&mut self.world);
-        let (device, assets, renderers, camera_qry, skybox_qry, meshes_qry,light_qry) = 
-                            world_w_queries_systemstate.get_mut(&mut self.world);
-        
-        render_to_texture(
-                &device,
-                assets,
-                renderers,
-                camera_qry,
-                skybox_qry,
-                meshes_qry,
-                light_qry,
-                &color_texture,
-                viewport,
-                clear);
+        let mut world_w_queries_systemstate: SystemState<(ResMut<Renderers>, SceneParams)> = SystemState::from_world(&mut self.world);
+        let (mut renderers, scene) = world_w_queries_systemstate.get_mut(&mut self.world);
 
+        // Once per frame, e.g. not again for the second eye in WebXR
+        if !self.texture_targets_rendered {
+            render_texture_targets(&mut renderers, &scene);
+            self.texture_targets_rendered = true;
+        }
+
+        render_to_texture(&mut renderers, &scene, scene.player.single(), &scene.post_process, color_texture, viewport, clear);
+
+
+    }
+
+    // Advances the scene by frame_duration, renders it offscreen at the device's
+    // surface size and reads the result back. Blocks until the frame is on the CPU.
+    pub fn render_to_image(&mut self, frame_duration: std::time::Duration) -> RgbaImage {
+        let texture = self.render_offscreen(frame_duration);
+        let device = self.world.resource::<Device>();
+        TextureReadback::new(device, &texture).wait(device)
+    }
 
+    // Same as render_to_image, but reads back the linear hdr buffer before tonemapping
+    pub fn render_to_hdr_image(&mut self, frame_duration: std::time::Duration) -> Rgba32FImage {
+        self.render_offscreen(frame_duration);
+        let device = self.world.resource::<Device>();
+        let hdr_texture = self.world.resource::<Renderers>().hdr_pipeline.texture();
+        TextureReadback::new(device, hdr_texture).wait_hdr(device)
+    }
+
+    fn render_offscreen(&mut self, frame_duration: std::time::Duration) -> wgpu::Texture {
+        self.update_scene(frame_duration);
+
+        let size = self.world.resource::<Device>().surface_size();
+        let format = self.world.resource::<Device>().surface_texture_format();
+        let texture = self.world.resource::<Device>().create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        self.render_to_texture(&texture, None, true);
+        texture
+    }
+
+    // Advances the scene by frame_duration and renders a 360° panorama around the player camera
+    pub fn render_panorama_to_image(&mut self, settings: &PanoramaSettings, frame_duration: std::time::Duration) -> RgbaImage {
+        let faces = self.render_panorama_faces(settings, frame_duration, false);
+        let device = self.world.resource::<Device>();
+        let faces: Vec<_> = faces.into_iter()
+            .map(|(color, _)| image_ops::to_linear(&color.wait(device)))
+            .collect();
+        image_ops::to_srgb(&settings.reproject(&faces))
+    }
+
+    // Same as render_panorama_to_image, from the linear hdr buffer before tonemapping
+    pub fn render_panorama_to_hdr_image(&mut self, settings: &PanoramaSettings, frame_duration: std::time::Duration) -> Rgba32FImage {
+        let faces = self.render_panorama_faces(settings, frame_duration, true);
+        let device = self.world.resource::<Device>();
+        let faces: Vec<_> = faces.into_iter()
+            .filter_map(|(_, hdr)| hdr)
+            .map(|hdr| hdr.wait_hdr(device))
+            .collect();
+        settings.reproject(&faces)
+    }
+
+    // Advances the scene by frame_duration and renders the player camera's view into a
+    // poster of any size, one tile at a time
+    pub fn render_poster_to_image(&mut self, settings: &PosterSettings, frame_duration: std::time::Duration) -> RgbaImage {
+        self.render_poster(settings, frame_duration, false, |device, (color, _)| color.wait(device))
+    }
+
+    // Same as render_poster_to_image, from the linear hdr buffer before tonemapping
+    pub fn render_poster_to_hdr_image(&mut self, settings: &PosterSettings, frame_duration: std::time::Duration) -> Rgba32FImage {
+        self.render_poster(settings, frame_duration, true, |device, (_, hdr)| hdr.unwrap().wait_hdr(device))
+    }
+
+    fn render_poster<P: Pixel + 'static>(
+        &mut self,
+        settings: &PosterSettings,
+        frame_duration: std::time::Duration,
+        hdr: bool,
+        wait: impl Fn(&Device, (TextureReadback, Option<TextureReadback>)) -> ImageBuffer<P, Vec<P::Subpixel>>,
+    ) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        self.update_scene(frame_duration);
+        let _span = tracing::info_span!("render_poster").entered();
+
+        let mut world_w_queries_systemstate: SystemState<(ResMut<Renderers>, SceneParams)> = SystemState::from_world(&mut self.world);
+        let (mut renderers, scene) = world_w_queries_systemstate.get_mut(&mut self.world);
+
+        // Keep the tiles within what the device can render
+        let max_tile_size = scene.device.limits().max_texture_dimension_2d.saturating_sub(2 * settings.margin);
+        assert!(max_tile_size > 0, "Poster margin {} is too large for the device", settings.margin);
+        let settings = PosterSettings { tile_size: settings.tile_size.min(max_tile_size), ..*settings };
+
+        render_texture_targets(&mut renderers, &scene);
+        self.texture_targets_rendered = true;
+
+        let (camera, transform) = scene.player.single();
+        let tiles = settings.tiles(camera);
+        let mut poster = ImageBuffer::new(settings.width, settings.height);
+        // One tile at a time, so only a single tile is in flight on the GPU
+        for (i, tile) in tiles.iter().enumerate() {
+            let _tile_span = tracing::info_span!("poster_tile", i).entered();
+            let readback = render_views_for_readback(
+                    &mut renderers,
+                    &scene,
+                    &[(tile.camera.clone(), transform.clone())],
+                    &scene.post_process,
+                    settings.tile_render_size(),
+                    settings.tile_render_size(),
+                    hdr).pop().unwrap();
+            settings.stitch(&mut poster, tile, &wait(&scene.device, readback));
+            log::info!("Rendered poster tile {}/{}", i + 1, tiles.len());
+        }
+        poster
+    }
+
+    fn render_panorama_faces(&mut self, settings: &PanoramaSettings, frame_duration: std::time::Duration, hdr: bool)
+        -> Vec<(TextureReadback, Option<TextureReadback>)> {
+        self.update_scene(frame_duration);
+        let _span = tracing::info_span!("render_panorama").entered();
+
+        let mut world_w_queries_systemstate: SystemState<(ResMut<Renderers>, SceneParams)> = SystemState::from_world(&mut self.world);
+        let (mut renderers, scene) = world_w_queries_systemstate.get_mut(&mut self.world);
+
+        render_texture_targets(&mut renderers, &scene);
+        self.texture_targets_rendered = true;
+
+        let (camera, transform) = scene.player.single();
+        let views = settings.face_views(transform, camera.znear(), camera.zfar());
+        let face_post_process = PanoramaSettings::face_post_process(&scene.post_process);
+        render_views_for_readback(
+                &mut renderers,
+                &scene,
+                &views,
+                &face_post_process,
+                settings.face_size(),
+                settings.face_size(),
+                hdr)
     }
 
 }
@@ -313,7 +502,7 @@ fn setup_window_canvas(window: &Window, surface_size: SurfaceSize) {
 
 pub async fn run_experience(webxr: bool) {
 
-    init_logging();
+    let trace_guard = init_logging();
     printlog("running run_app - starting");
 
     let event_loop = EventLoop::new();
@@ -356,6 +545,7 @@ pub async fn run_experience(webxr: bool) {
             _,
             _
         ) = app.world_systemstate_get_mut();
+        let window = window.expect("Windowed app without a window");
 
 
         input.reset();
@@ -374,6 +564,10 @@ pub async fn run_experience(webxr: bool) {
                 ref event,
                 window_id,
             } if window_id == window.id() => match event {
+                WindowEvent::CursorMoved { position, .. } => {
+                    mouse_events.send(MouseEvent::CursorMoved(position.x as f32, position.y as f32));
+                }
+
                 WindowEvent::MouseInput { state, button, .. } => {
                     mouse_events.send(MouseEvent::Button {
                         button: *button,
@@ -422,10 +616,12 @@ pub async fn run_experience(webxr: bool) {
                 if webxr {
                     return;
                 }
+                let _frame_span = tracing::info_span!("frame").entered();
                 app.world.run_schedule(SpawnLabel);
                 app.world.run_schedule(PreupdateLabel);
                 app.world.run_schedule(UpdateLabel);
                 app.world.run_schedule(RenderLabel);
+                app.world.clear_trackers();
             },
 
             Event::RedrawEventsCleared => {
@@ -441,6 +637,7 @@ pub async fn run_experience(webxr: bool) {
         }
 
         if !app.world.resource::<AppState>().running {
+            trace_guard.flush();
             *control_flow = ControlFlow::Exit;
             //return;
         }
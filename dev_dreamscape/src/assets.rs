@@ -5,14 +5,18 @@ use cfg_if::cfg_if;
 use wgpu::util::DeviceExt;
 use std::io::{BufReader, Cursor};
 use std::collections::HashMap;
+use image::RgbaImage;
 
+use crate::components::PhysicsMaterial;
 use crate::device::Device;
 use crate::texture::Texture;
 use crate::{model, texture};
 use crate::math::{Vec2, Vec3, Vec3f, to_point};
+use rapier3d::parry::transformation::vhacd::{VHACDParameters, VHACD};
 use rapier3d::prelude::{Point,Real};
 
 use crate::logging::printlog;
+use tracing::Instrument;
 
 use std::path::Path;
 
@@ -134,6 +138,23 @@ pub async fn load_model(
         ));
     }
 
+    Ok(model_from_tobj(file_name, models, materials, device, queue))
+}
+
+// Builds the vertex buffers of the parsed OBJ models, with a default material if there are none
+fn model_from_tobj(
+    file_name: &str,
+    models: Vec<tobj::Model>,
+    mut materials: Vec<model::Material>,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> model::Model {
+    let bounding_radius = models
+        .iter()
+        .flat_map(|m| m.mesh.positions.chunks(3))
+        .map(|p| Vec3f::new(p[0], p[1], p[2]).norm())
+        .fold(0.0, f32::max);
+
     let meshes = models
         .into_iter()
         .map(|m| {
@@ -235,11 +256,24 @@ pub async fn load_model(
                 usage: wgpu::BufferUsages::INDEX,
             });
 
+            // Each triangle contributes its three edges as separate lines
+            let edge_indices = indices
+                .chunks(3)
+                .flat_map(|c| [c[0], c[1], c[1], c[2], c[2], c[0]])
+                .collect::<Vec<u32>>();
+            let edge_index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{:?} Edge Index Buffer", file_name)),
+                contents: bytemuck::cast_slice(&edge_indices),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+
             model::Mesh {
                 name: file_name.to_string(),
                 vertex_buffer,
                 index_buffer,
                 num_elements: m.mesh.indices.len() as u32,
+                edge_index_buffer,
+                num_edge_elements: edge_indices.len() as u32,
                 material: m.mesh.material_id.unwrap_or(0),
             }
         })
@@ -251,7 +285,7 @@ pub async fn load_model(
             Texture::default(device, queue),
             Texture::default(device, queue)));
     }
-    Ok(model::Model { meshes, materials })
+    model::Model { meshes, materials, bounding_radius }
 }
 
 
@@ -281,13 +315,172 @@ impl CollisionModel {
         }).collect::<Vec<Point<Real>>>()
     }
 
+    // Indices into get_all_vertices
     pub fn get_all_triangle_indices(&self) -> Vec<[u32; 3]> {
-        self.collision_meshes.clone().into_iter().map(|m| {
-            m.triangle_indices
-        }).collect::<Vec<_>>().into_iter().flatten().collect::<Vec<[u32; 3]>>()
+        let mut offset = 0;
+        let mut indices = vec![];
+        for m in &self.collision_meshes {
+            indices.extend(m.triangle_indices.iter().map(|t| t.map(|i| i + offset)));
+            offset += m.vertices.len() as u32;
+        }
+        indices
+    }
+
+    // Splits the model into roughly convex parts, one mesh each. Moving bodies collide much
+    // better as convex parts than as a trimesh. Works on render meshes too, so models don't
+    // need a hand-made collider.
+    pub fn convex_decomposition(&self, params: &DecompositionParams) -> CollisionModel {
+        let vhacd_params = VHACDParameters {
+            resolution: params.resolution,
+            concavity: params.concavity,
+            max_convex_hulls: params.max_convex_hulls,
+            ..Default::default()
+        };
+        let points = self.get_all_vertices_points();
+        let indices = self.get_all_triangle_indices();
+        if indices.is_empty() {
+            return CollisionModel { collision_meshes: vec![] };
+        }
+        // The hulls are taken around the voxels of each part. Hulls around the model's own
+        // triangles would be tighter, but parry panics on parts that no triangle crosses.
+        let collision_meshes = VHACD::decompose(&vhacd_params, &points, &indices, false)
+            .compute_convex_hulls(vhacd_params.convex_hull_downsampling)
+            .into_iter()
+            .filter(|(_, triangle_indices)| !triangle_indices.is_empty())
+            .map(|(vertices, triangle_indices)| CollisionMesh {
+                vertices: vertices.into_iter().map(|p| p.coords).collect(),
+                triangle_indices,
+            })
+            .collect();
+        CollisionModel { collision_meshes }
+    }
+
+    fn to_obj(&self) -> String {
+        let mut obj = String::new();
+        let mut offset = 1;
+        for (i, m) in self.collision_meshes.iter().enumerate() {
+            obj += &format!("o part{}\n", i);
+            for v in &m.vertices {
+                obj += &format!("v {} {} {}\n", v.x, v.y, v.z);
+            }
+            for [a, b, c] in &m.triangle_indices {
+                obj += &format!("f {} {} {}\n", a + offset, b + offset, c + offset);
+            }
+            offset += m.vertices.len() as u32;
+        }
+        obj
+    }
+
+    // Hash of the model and the decomposition parameters, to tell whether shipped parts are
+    // up to date. FNV-1a rather than std's hasher, which may change between Rust versions.
+    fn decomposition_key(&self, params: &DecompositionParams) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut add = |word: u32| {
+            for byte in word.to_le_bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        };
+        for m in &self.collision_meshes {
+            m.vertices.iter().flat_map(|v| v.iter()).for_each(|c| add(c.to_bits()));
+            m.triangle_indices.iter().flatten().for_each(|&i| add(i));
+        }
+        [params.resolution, params.concavity.to_bits(), params.max_convex_hulls].into_iter().for_each(&mut add);
+        hash
     }
 }
 
+// Parameters of CollisionModel::convex_decomposition, see VHACDParameters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecompositionParams {
+    // Voxels along the longest side of the model. Higher follows the model more closely
+    // but is slower.
+    pub resolution: u32,
+    // How far from convex a part may be, relative to the model's size. Lower gives more parts.
+    pub concavity: f32,
+    pub max_convex_hulls: u32,
+}
+
+impl Default for DecompositionParams {
+    fn default() -> Self {
+        let defaults = VHACDParameters::default();
+        Self {
+            resolution: defaults.resolution,
+            concavity: defaults.concavity,
+            max_convex_hulls: 32,
+        }
+    }
+}
+
+// Render meshes to decompose into convex parts for moving bodies
+pub fn convex_decompositions() -> Vec<(&'static str, DecompositionParams)> {
+    vec![
+        ("Rock1/RedishRock.obj", DecompositionParams::default()),
+        ("Rock2/Rock2.obj", DecompositionParams::default()),
+    ]
+}
+
+// The parts ship next to the model, e.g. Rock1/RedishRock.parts.obj
+fn parts_path(model_path: &str) -> String {
+    format!("{}.parts.obj", model_path.strip_suffix(".obj").unwrap_or(model_path))
+}
+
+// First line of a parts file, so parts of an older model or other parameters are noticed
+fn parts_header(model: &CollisionModel, params: &DecompositionParams) -> String {
+    format!("# decomposition {:016x}", model.decomposition_key(params))
+}
+
+// The convex decomposition of a model, loaded like the other assets from the parts file
+// written by write_convex_decompositions, as decomposing takes a while. Without an up to
+// date parts file the model is decomposed here.
+pub async fn load_convex_decomposition(file_name: &str, model: &CollisionModel, params: &DecompositionParams) -> CollisionModel {
+    let path = parts_path(file_name);
+    let loaded = load_string(&path).await.and_then(|obj_text| {
+        if obj_text.lines().next() != Some(parts_header(model, params).as_str()) {
+            bail!("made from another model or with other parameters");
+        }
+        collision_model_from_obj(&obj_text)
+    });
+    match loaded {
+        std::result::Result::Ok(parts) => parts,
+        Err(e) => {
+            log::warn!("Decomposing {}, as {} can't be used ({}). Run `cargo run --release --example decompose` to update it.", file_name, path, e);
+            model.convex_decomposition(params)
+        }
+    }
+}
+
+// Decomposes the models of convex_decompositions in res_dir and writes the parts next to
+// them, to ship with the other resources. Rerun after changing a model or its parameters.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_convex_decompositions(res_dir: &Path) -> anyhow::Result<()> {
+    for (model_path, params) in convex_decompositions() {
+        let obj_text = std::fs::read_to_string(res_dir.join(model_path))
+            .with_context(|| format!("Couldn't read {}", model_path))?;
+        let model = collision_model_from_obj(&obj_text)?;
+        if model.get_all_triangle_indices().is_empty() {
+            bail!("{} has no triangles. Are the git lfs files checked out?", model_path);
+        }
+        let parts = model.convex_decomposition(&params);
+        let path = res_dir.join(parts_path(model_path));
+        std::fs::write(&path, format!("{}\n{}", parts_header(&model, &params), parts.to_obj()))?;
+        log::info!("Wrote {} convex parts to {:?}", parts.collision_meshes.len(), path);
+    }
+    Ok(())
+}
+
+fn collision_model_from_obj(obj_text: &str) -> anyhow::Result<CollisionModel> {
+    let (models, _) = tobj::load_obj_buf(
+        &mut BufReader::new(Cursor::new(obj_text)),
+        &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        },
+        |_| Err(tobj::LoadError::OpenFileFailed),
+    )?;
+    Ok(CollisionModel { collision_meshes: collision_meshes(models) })
+}
+
 
 
 
@@ -312,7 +505,11 @@ pub async fn load_collision_model(file_name: &str) -> anyhow::Result<CollisionMo
     )
     .await?;
 
-    let collision_meshes = models
+    Ok(CollisionModel { collision_meshes: collision_meshes(models) })
+}
+
+fn collision_meshes(models: Vec<tobj::Model>) -> Vec<CollisionMesh> {
+    models
         .into_iter()
         .map(|m| {
             let vertices = (0..m.mesh.positions.len() / 3)
@@ -331,9 +528,7 @@ pub async fn load_collision_model(file_name: &str) -> anyhow::Result<CollisionMo
                 vertices,
                 triangle_indices,
             }
-        }).collect::<Vec<_>>();
-
-    Ok(CollisionModel { collision_meshes })
+        }).collect::<Vec<_>>()
 }
 
 
@@ -360,6 +555,8 @@ pub async fn load_collision_model(file_name: &str) -> anyhow::Result<CollisionMo
 
 
 
+
+const PHYSICS_MATERIALS_PATH: &str = "physics_materials.txt";
 
 // TODO Load also shaders, meshes, etc.
 #[derive(Resource)]
@@ -367,6 +564,12 @@ pub struct Assets {
     pub texture_store: HashMap<String,texture::Texture>,
     pub model_store: HashMap<String,model::Model>,
     pub collision_model_store: HashMap<String,CollisionModel>,
+    // Convex decompositions of models, by the model's path
+    pub convex_parts_store: HashMap<String,CollisionModel>,
+    // Physics materials declared for collision assets, by path
+    pub physics_material_store: HashMap<String,PhysicsMaterial>,
+    // WGSL sources for ShaderMaterial, by path
+    pub shader_store: HashMap<String,String>,
 }
 
 impl Assets {
@@ -381,48 +584,298 @@ impl Assets {
             "mars_surface/Crater.obj",
             "Rock1/RedishRock.obj",
             "Rock2/Rock2.obj",
-            "Rock1/RedishRock-collider.obj",
-            "Rock2/Rock2-collider.obj",
         ];
 
         let collision_model_paths = vec![
             "mars_surface/Crater_low-collision.obj",
             //"moon_surface/moon_surface-collider.obj",
-            "Rock1/RedishRock-collider.obj",
-            "Rock2/Rock2-collider.obj",
         ];
 
         let cubemap_paths = vec![
             "skyboxes/planet_atmosphere" // dir of pngs
         ];
 
+        // Shaders used by ShaderMaterial components
+        let shader_paths: Vec<&str> = vec![
+        ];
+
         let mut model_store = HashMap::new();
         for model_path in model_paths {
-            let model = load_model(model_path, &device, &device.queue()).await.unwrap();
+            let model = load_model(model_path, &device, &device.queue())
+                .instrument(tracing::info_span!("load_model", name = model_path))
+                .await.unwrap();
             model_store.insert(model_path.to_string(), model);
         }
 
         let mut collision_model_store = HashMap::new();
         for collision_model_path in collision_model_paths {
-            let model = load_collision_model(collision_model_path).await.unwrap();
+            let model = load_collision_model(collision_model_path)
+                .instrument(tracing::info_span!("load_collision_model", name = collision_model_path))
+                .await.unwrap();
             collision_model_store.insert(collision_model_path.to_string(), model);
         }
 
+        let mut convex_parts_store = HashMap::new();
+        for (model_path, params) in convex_decompositions() {
+            let span = tracing::info_span!("load_convex_decomposition", name = model_path);
+            let parts = async {
+                let model = load_collision_model(model_path).await.unwrap();
+                load_convex_decomposition(model_path, &model, &params).await
+            }.instrument(span).await;
+            convex_parts_store.insert(model_path.to_string(), parts);
+        }
+
+        let physics_material_store = load_string(PHYSICS_MATERIALS_PATH)
+            .instrument(tracing::info_span!("load_physics_materials"))
+            .await
+            .and_then(|text| PhysicsMaterial::parse_declarations(&text))
+            .map(|materials| materials.into_iter().collect())
+            .unwrap_or_else(|e| {
+                log::warn!("Couldn't load {}, using default physics materials: {}", PHYSICS_MATERIALS_PATH, e);
+                HashMap::new()
+            });
+
         let mut texture_store = HashMap::new();
         for cubemap_path in cubemap_paths {
             let texture = texture::Texture::load_cubemap_from_pngs(
                 cubemap_path,
                 &device,
-                &device.queue()).await;
+                &device.queue())
+                .instrument(tracing::info_span!("load_cubemap", name = cubemap_path))
+                .await;
                 texture_store.insert(cubemap_path.to_string(), texture);
         }
 
+        let mut shader_store = HashMap::new();
+        for shader_path in shader_paths {
+            let source = load_string(shader_path)
+                .instrument(tracing::info_span!("load_shader", name = shader_path))
+                .await.unwrap();
+            shader_store.insert(shader_path.to_string(), source);
+        }
+
         Self {
             texture_store,
             model_store,
-            collision_model_store
+            collision_model_store,
+            convex_parts_store,
+            physics_material_store,
+            shader_store,
         }
     }
 
+    // Primitives that need no files from res/, for the reference scene (App::load_reference_scene):
+    // a cube and a sphere of radius 1 in plain grey, and a gradient sky
+    pub fn builtin(device: &Device) -> Self {
+        let queue = device.queue();
+        let material = || model::Material::new(
+            "Builtin",
+            single_color_texture(device, queue, [180, 180, 180, 255], false),
+            // Flat, pointing along the surface normal
+            single_color_texture(device, queue, [128, 128, 255, 255], true),
+        );
+
+        let mut model_store = HashMap::new();
+        for (name, obj_text) in [(BUILTIN_CUBE, cube_obj()), (BUILTIN_SPHERE, sphere_obj(32, 16))] {
+            let (models, _) = tobj::load_obj_buf(
+                &mut BufReader::new(Cursor::new(obj_text)),
+                &tobj::LoadOptions {
+                    triangulate: true,
+                    single_index: true,
+                    ..Default::default()
+                },
+                |_| Err(tobj::LoadError::OpenFileFailed),
+            ).unwrap();
+            model_store.insert(name.to_string(), model_from_tobj(name, models, vec![material()], device, queue));
+        }
+
+        let mut texture_store = HashMap::new();
+        texture_store.insert(BUILTIN_SKY.to_string(), Texture::cubemap_from_images(sky_faces(64), device, queue));
+
+        Self {
+            texture_store,
+            model_store,
+            collision_model_store: HashMap::new(),
+            convex_parts_store: HashMap::new(),
+            physics_material_store: HashMap::new(),
+            shader_store: HashMap::new(),
+        }
+    }
+
+    // The material declared for a collision asset, or the default one
+    pub fn physics_material(&self, path: &str) -> PhysicsMaterial {
+        self.physics_material_store.get(path).copied().unwrap_or_default()
+    }
+
+    // Adds a texture that cameras can render into (RenderTarget::Texture) and models can
+    // use as their diffuse texture. It has the surface format, as that's what the final
+    // tonemapping pass writes.
+    pub fn add_render_target(&mut self, device: &Device, name: &str, width: u32, height: u32) {
+        let texture = texture::Texture::create_2d_texture(
+            device,
+            width,
+            height,
+            device.surface_texture_format(),
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::FilterMode::Linear,
+            Some(name),
+        );
+        self.texture_store.insert(name.to_string(), texture);
+    }
+
+}
+
+// Stand-ins for the models in res/ of the same names, which the renderer and FreeBox rely on
+pub const BUILTIN_CUBE: &str = "cube.obj";
+pub const BUILTIN_SPHERE: &str = "sphere.obj";
+pub const BUILTIN_SKY: &str = "builtin/sky";
+
+fn single_color_texture(device: &Device, queue: &wgpu::Queue, color: [u8; 4], is_normal_map: bool) -> Texture {
+    let image = image::DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, image::Rgba(color)));
+    Texture::from_image(device, queue, &image, Some("Builtin"), is_normal_map).unwrap()
+}
 
+// Cube from -1 to 1, with its own vertices per face so the normals are flat
+fn cube_obj() -> String {
+    let mut obj = String::from("vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n");
+    // Normal of each face, then the two axes it spans, counter-clockwise seen from outside
+    let faces = [
+        ([1, 0, 0], [0, 0, -1], [0, 1, 0]),
+        ([-1, 0, 0], [0, 0, 1], [0, 1, 0]),
+        ([0, 1, 0], [1, 0, 0], [0, 0, -1]),
+        ([0, -1, 0], [1, 0, 0], [0, 0, 1]),
+        ([0, 0, 1], [1, 0, 0], [0, 1, 0]),
+        ([0, 0, -1], [-1, 0, 0], [0, 1, 0]),
+    ];
+    for (face, (n, u, v)) in faces.iter().enumerate() {
+        obj += &format!("vn {} {} {}\n", n[0], n[1], n[2]);
+        for (su, sv) in [(-1, -1), (1, -1), (1, 1), (-1, 1)] {
+            let p = [0, 1, 2].map(|i| n[i] + su * u[i] + sv * v[i]);
+            obj += &format!("v {} {} {}\n", p[0], p[1], p[2]);
+        }
+        let (first, normal) = (face * 4 + 1, face + 1);
+        obj += &format!(
+            "f {a}/1/{n} {b}/2/{n} {c}/3/{n} {d}/4/{n}\n",
+            a = first, b = first + 1, c = first + 2, d = first + 3, n = normal,
+        );
+    }
+    obj
+}
+
+// Sphere of radius 1 with longitude and latitude lines
+fn sphere_obj(segments: u32, rings: u32) -> String {
+    let mut obj = String::new();
+    for ring in 0..=rings {
+        let theta = ring as f32 / rings as f32 * std::f32::consts::PI;
+        for segment in 0..=segments {
+            let phi = segment as f32 / segments as f32 * std::f32::consts::TAU;
+            let p = Vec3f::new(theta.sin() * phi.cos(), theta.cos(), -theta.sin() * phi.sin());
+            obj += &format!("v {} {} {}\nvn {} {} {}\n", p.x, p.y, p.z, p.x, p.y, p.z);
+            obj += &format!("vt {} {}\n", segment as f32 / segments as f32, ring as f32 / rings as f32);
+        }
+    }
+    let index = |ring: u32, segment: u32| ring * (segments + 1) + segment + 1;
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let (a, b) = (index(ring, segment), index(ring, segment + 1));
+            let (c, d) = (index(ring + 1, segment + 1), index(ring + 1, segment));
+            // Triangles at the poles would be degenerate as quads
+            if ring != 0 {
+                obj += &format!("f {a}/{a}/{a} {d}/{d}/{d} {b}/{b}/{b}\n");
+            }
+            if ring != rings - 1 {
+                obj += &format!("f {b}/{b}/{b} {d}/{d}/{d} {c}/{c}/{c}\n");
+            }
+        }
+    }
+    obj
+}
+
+// Cube map faces fading from a pale horizon to a deep blue zenith, darker below
+fn sky_faces(size: u32) -> Vec<RgbaImage> {
+    let horizon = Vec3f::new(200.0, 210.0, 225.0);
+    let zenith = Vec3f::new(40.0, 80.0, 160.0);
+    let ground = Vec3f::new(60.0, 55.0, 50.0);
+    // Height of the direction through each pixel, by face in the order px, nx, py, ny, pz, nz
+    let height = |face: usize, t: f32| match face {
+        2 => 1.0,
+        3 => -1.0,
+        _ => -t,
+    };
+    (0..6)
+        .map(|face| RgbaImage::from_fn(size, size, |x, y| {
+            let (s, t) = ((x as f32 + 0.5) / size as f32 * 2.0 - 1.0, (y as f32 + 0.5) / size as f32 * 2.0 - 1.0);
+            let dir_len = (1.0 + s * s + t * t).sqrt();
+            let h = height(face, t) / dir_len;
+            let color = if h >= 0.0 { horizon.lerp(&zenith, h) } else { horizon.lerp(&ground, (-h * 4.0).min(1.0)) };
+            image::Rgba([color.x as u8, color.y as u8, color.z as u8, 255])
+        }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rapier3d::prelude::SharedShape;
+
+    use super::*;
+
+    // Unit cube with two triangles per face
+    fn cube() -> CollisionModel {
+        let vertices = (0..8)
+            .map(|i| Vec3f::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32))
+            .collect();
+        let triangle_indices = vec![
+            [0, 2, 1], [1, 2, 3], [4, 5, 6], [5, 7, 6],
+            [0, 1, 4], [1, 5, 4], [2, 6, 3], [3, 6, 7],
+            [0, 4, 2], [2, 4, 6], [1, 3, 5], [3, 7, 5],
+        ];
+        CollisionModel { collision_meshes: vec![CollisionMesh { vertices, triangle_indices }] }
+    }
+
+    // Coarse, as decomposing is slow in debug builds
+    fn params() -> DecompositionParams {
+        DecompositionParams { resolution: 8, ..Default::default() }
+    }
+
+    #[test]
+    fn decomposes_into_closed_hulls() {
+        let parts = cube().convex_decomposition(&params());
+        assert!(!parts.collision_meshes.is_empty());
+        for part in &parts.collision_meshes {
+            assert!(part.vertices.len() >= 4);
+            assert!(SharedShape::convex_mesh(
+                part.vertices.iter().map(|v| to_point(*v)).collect(),
+                &part.triangle_indices,
+            ).is_some());
+        }
+    }
+
+    #[test]
+    fn empty_model_has_no_parts() {
+        let empty = CollisionModel { collision_meshes: vec![] };
+        assert!(empty.convex_decomposition(&params()).collision_meshes.is_empty());
+    }
+
+    #[test]
+    fn decomposition_survives_obj_round_trip() {
+        let parts = cube().convex_decomposition(&params());
+        let loaded = collision_model_from_obj(&parts.to_obj()).unwrap();
+        // The loader may reorder the vertices of each part
+        assert_eq!(loaded.collision_meshes.len(), parts.collision_meshes.len());
+        for (loaded, part) in loaded.collision_meshes.iter().zip(&parts.collision_meshes) {
+            assert_eq!(loaded.vertices.len(), part.vertices.len());
+            assert_eq!(loaded.triangle_indices.len(), part.triangle_indices.len());
+        }
+    }
+
+    #[test]
+    fn parts_files_name_their_model_and_parameters() {
+        assert_eq!(parts_path("Rock1/RedishRock.obj"), "Rock1/RedishRock.parts.obj");
+        let header = parts_header(&cube(), &params());
+        assert_eq!(header, parts_header(&cube(), &params()));
+        assert_ne!(header, parts_header(&cube(), &DecompositionParams { max_convex_hulls: 4, ..params() }));
+        let mut moved = cube();
+        moved.collision_meshes[0].vertices[0].x += 0.5;
+        assert_ne!(header, parts_header(&moved, &params()));
+    }
 }
@@ -0,0 +1,57 @@
+// CPU side image processing for captures
+
+use image::{Rgba32FImage, RgbaImage};
+
+// Box filter for supersampled captures. Each output pixel is the average
+// of a factor x factor block of input pixels.
+pub fn downsample(image: &Rgba32FImage, factor: u32) -> Rgba32FImage {
+    if factor == 1 {
+        return image.clone();
+    }
+    let (width, height) = (image.width() / factor, image.height() / factor);
+    let weight = 1.0 / (factor * factor) as f32;
+    let mut out = Rgba32FImage::new(width, height);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if x / factor >= width || y / factor >= height {
+            continue;
+        }
+        let out_pixel = out.get_pixel_mut(x / factor, y / factor);
+        for c in 0..4 {
+            out_pixel[c] += pixel[c] * weight;
+        }
+    }
+    out
+}
+
+// Same as downsample for tonemapped 8 bit images. Averages in linear space so edges don't get darkened.
+pub fn downsample_srgb(image: &RgbaImage, factor: u32) -> RgbaImage {
+    if factor == 1 {
+        return image.clone();
+    }
+    to_srgb(&downsample(&to_linear(image), factor))
+}
+
+pub fn to_linear(image: &RgbaImage) -> Rgba32FImage {
+    let pixels = image.pixels()
+        .flat_map(|p| [srgb_to_linear(p[0]), srgb_to_linear(p[1]), srgb_to_linear(p[2]), p[3] as f32 / 255.0])
+        .collect();
+    Rgba32FImage::from_raw(image.width(), image.height(), pixels).unwrap()
+}
+
+pub fn to_srgb(image: &Rgba32FImage) -> RgbaImage {
+    let pixels = image.pixels()
+        .flat_map(|p| [linear_to_srgb(p[0]), linear_to_srgb(p[1]), linear_to_srgb(p[2]), (p[3].clamp(0.0, 1.0) * 255.0).round() as u8])
+        .collect();
+    RgbaImage::from_raw(image.width(), image.height(), pixels).unwrap()
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let v = value as f32 / 255.0;
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let v = if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 };
+    (v * 255.0).round() as u8
+}
@@ -0,0 +1,12 @@
+pub(crate) mod image_ops;
+mod panorama;
+mod poster;
+mod readback;
+mod screenshot;
+mod sequence;
+
+pub use panorama::PanoramaSettings;
+pub use poster::PosterSettings;
+pub use readback::TextureReadback;
+pub use screenshot::Screenshots;
+pub use sequence::{capture_sequence, FrameFormat, SequenceSettings};
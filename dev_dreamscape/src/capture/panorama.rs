@@ -0,0 +1,189 @@
+use image::{Rgba, Rgba32FImage};
+
+use crate::components::{Camera, Transform};
+use crate::math::{UnitQuatf, Vec3f};
+use crate::renderers::PostProcess;
+
+// 360° capture. The scene is rendered into the six faces of a cube around the eye,
+// which are then reprojected into an equirectangular image (longitude along x, latitude along y).
+#[derive(Debug, Copy, Clone)]
+pub struct PanoramaSettings {
+    // Width of the equirectangular image. The height is width / 2, or width for stereo.
+    pub width: u32,
+    // Left eye on top, right eye at the bottom
+    pub stereo: bool,
+    // Distance between the eyes in world units
+    pub eye_separation: f32,
+}
+
+impl Default for PanoramaSettings {
+    fn default() -> Self {
+        Self {
+            width: 4096,
+            stereo: false,
+            eye_separation: 0.064,
+        }
+    }
+}
+
+// Forward and up vectors of the cube faces, in the usual cubemap order (+x, -x, +y, -y, +z, -z)
+const FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+];
+
+impl PanoramaSettings {
+    pub fn height(&self) -> u32 {
+        if self.stereo { self.width } else { self.width / 2 }
+    }
+
+    // Four faces cover the horizon, so this keeps roughly one texel per output pixel
+    pub fn face_size(&self) -> u32 {
+        (self.width / 4).max(1)
+    }
+
+    // Cameras for the cube faces around the eye (six, or twelve for stereo with the left eye first).
+    // The faces are oriented in the eye's local frame, so the centre of the panorama is the eye's forward.
+    pub fn face_views(&self, eye: &Transform, znear: f32, zfar: f32) -> Vec<(Camera, Transform)> {
+        let eye_offsets: &[f32] = if self.stereo { &[-0.5, 0.5] } else { &[0.0] };
+        let size = self.face_size();
+
+        eye_offsets.iter().flat_map(|&eye_offset| {
+            FACES.iter().map(move |&(forward, up)| {
+                let (forward, up) = (Vec3f::from(forward), Vec3f::from(up));
+                // Cameras look down their local -z
+                let local_rotation = UnitQuatf::face_towards(&-forward, &up);
+                let rotation = eye.rotation() * local_rotation;
+                // Offset each horizontal face sideways relative to its own view direction.
+                // It's an approximation of omni-directional stereo that is exact at the centre
+                // of each face. The poles get no offset, as they would in true ODS.
+                let offset = if forward.y == 0.0 {
+                    rotation * Vec3f::x() * eye_offset * self.eye_separation
+                } else {
+                    Vec3f::zeros()
+                };
+                let camera = Camera::new(size, size, std::f32::consts::FRAC_PI_2, znear, zfar);
+                let transform = Transform::new(eye.position() + offset, rotation, Vec3f::from_element(1.0));
+                (camera, transform)
+            })
+        }).collect()
+    }
+
+    // Post effects for the faces. The effects work in screen space, so they would repeat on
+    // every face and leave a grid in the panorama. The faces are rendered without them.
+    pub fn face_post_process(post_process: &PostProcess) -> PostProcess {
+        static WARN: std::sync::Once = std::sync::Once::new();
+        if !post_process.is_empty() {
+            WARN.call_once(|| log::warn!("Post effects are skipped in panoramas"));
+        }
+        PostProcess::default()
+    }
+
+    // Reprojects faces rendered with face_views into the equirectangular image. The faces
+    // have to be linear (or hdr) for the filtering to be right.
+    pub fn reproject(&self, faces: &[Rgba32FImage]) -> Rgba32FImage {
+        let eye_height = self.width / 2;
+        let mut panorama = Rgba32FImage::new(self.width, self.height());
+        for (y, row) in panorama.enumerate_rows_mut() {
+            let eye = (y / eye_height) as usize;
+            let eye_faces = &faces[eye * 6..eye * 6 + 6];
+            let latitude = std::f32::consts::FRAC_PI_2
+                - ((y % eye_height) as f32 + 0.5) / eye_height as f32 * std::f32::consts::PI;
+            for (x, _, pixel) in row {
+                let longitude = (x as f32 + 0.5) / self.width as f32 * std::f32::consts::TAU
+                    - std::f32::consts::PI;
+                // Longitude 0 is the eye's forward (-z), increasing to the right (+x)
+                let dir = Vec3f::new(
+                    longitude.sin() * latitude.cos(),
+                    latitude.sin(),
+                    -longitude.cos() * latitude.cos(),
+                );
+                *pixel = sample_cube(eye_faces, dir);
+            }
+        }
+        panorama
+    }
+}
+
+// Bilinear lookup in the face the direction points at. Uses the cubemap face conventions,
+// except that u is flipped: cubemaps are left handed, so our rendered faces are mirrored.
+fn sample_cube(faces: &[Rgba32FImage], dir: Vec3f) -> Rgba<f32> {
+    let abs = dir.abs();
+    let (face, sc, tc, ma) = if abs.x >= abs.y && abs.x >= abs.z {
+        if dir.x > 0.0 { (0, -dir.z, -dir.y, abs.x) } else { (1, dir.z, -dir.y, abs.x) }
+    } else if abs.y >= abs.z {
+        if dir.y > 0.0 { (2, dir.x, dir.z, abs.y) } else { (3, dir.x, -dir.z, abs.y) }
+    } else if dir.z > 0.0 {
+        (4, dir.x, -dir.y, abs.z)
+    } else {
+        (5, -dir.x, -dir.y, abs.z)
+    };
+    let image = &faces[face];
+    let u = (1.0 - sc / ma) / 2.0 * image.width() as f32 - 0.5;
+    let v = (tc / ma + 1.0) / 2.0 * image.height() as f32 - 0.5;
+    bilinear(image, u, v)
+}
+
+fn bilinear(image: &Rgba32FImage, u: f32, v: f32) -> Rgba<f32> {
+    let max_x = image.width() as i64 - 1;
+    let max_y = image.height() as i64 - 1;
+    let (x0, y0) = (u.floor() as i64, v.floor() as i64);
+    let (fx, fy) = (u - u.floor(), v - v.floor());
+    let texel = |x: i64, y: i64| image.get_pixel(x.clamp(0, max_x) as u32, y.clamp(0, max_y) as u32).0;
+    let (a, b, c, d) = (texel(x0, y0), texel(x0 + 1, y0), texel(x0, y0 + 1), texel(x0 + 1, y0 + 1));
+    let mut out = [0.0; 4];
+    for i in 0..4 {
+        let top = a[i] + (b[i] - a[i]) * fx;
+        let bottom = c[i] + (d[i] - c[i]) * fx;
+        out[i] = top + (bottom - top) * fy;
+    }
+    Rgba(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Faces of a single colour each, whose red channel is the face index
+    fn solid_faces(count: usize, size: u32) -> Vec<Rgba32FImage> {
+        (0..count).map(|i| Rgba32FImage::from_pixel(size, size, Rgba([i as f32, 0.0, 0.0, 1.0]))).collect()
+    }
+
+    fn face_at(panorama: &Rgba32FImage, x: u32, y: u32) -> f32 {
+        panorama.get_pixel(x, y)[0]
+    }
+
+    #[test]
+    fn reprojection_finds_the_faces() {
+        let settings = PanoramaSettings { width: 64, ..Default::default() };
+        let panorama = settings.reproject(&solid_faces(6, settings.face_size()));
+        let (width, height) = (panorama.width(), panorama.height());
+        assert_eq!((width, height), (64, 32));
+        // The centre is forward (-z), +x is to the right and +z behind, at both edges
+        assert_eq!(face_at(&panorama, width / 2, height / 2), 5.0);
+        assert_eq!(face_at(&panorama, width * 3 / 4, height / 2), 0.0);
+        assert_eq!(face_at(&panorama, width / 4, height / 2), 1.0);
+        assert_eq!(face_at(&panorama, 0, height / 2), 4.0);
+        assert_eq!(face_at(&panorama, width - 1, height / 2), 4.0);
+        // The top and bottom rows are the poles
+        for x in 0..width {
+            assert_eq!(face_at(&panorama, x, 0), 2.0);
+            assert_eq!(face_at(&panorama, x, height - 1), 3.0);
+        }
+    }
+
+    #[test]
+    fn stereo_reprojection_puts_the_right_eye_at_the_bottom() {
+        let settings = PanoramaSettings { width: 64, stereo: true, ..Default::default() };
+        let panorama = settings.reproject(&solid_faces(12, settings.face_size()));
+        assert_eq!(panorama.height(), 64);
+        assert_eq!(face_at(&panorama, 32, 16), 5.0);
+        assert_eq!(face_at(&panorama, 32, 48), 11.0);
+        assert_eq!(face_at(&panorama, 32, 31), 3.0);
+        assert_eq!(face_at(&panorama, 32, 32), 8.0);
+    }
+}
@@ -0,0 +1,187 @@
+use image::{GenericImage, GenericImageView, ImageBuffer, Pixel};
+
+use crate::components::Camera;
+
+// Renders images larger than the device can, e.g. for print. The camera's view is split
+// into a grid of tiles, each rendered with a camera that sees only its part of the frustum,
+// and the tiles are stitched on the CPU.
+//
+// Each tile is rendered with a margin of extra pixels around it that is cropped away,
+// so screen space effects near the tile edges see the same neighbourhood as in a
+// single render and don't leave seams.
+#[derive(Debug, Copy, Clone)]
+pub struct PosterSettings {
+    pub width: u32,
+    pub height: u32,
+    // Size of the stitched part of each tile. Limited by the device's max texture size.
+    pub tile_size: u32,
+    // Extra pixels rendered on each side of a tile. Should cover the largest
+    // screen space filter radius of the post effects.
+    pub margin: u32,
+}
+
+impl Default for PosterSettings {
+    fn default() -> Self {
+        Self {
+            width: 16384,
+            height: 16384,
+            tile_size: 2048,
+            margin: 64,
+        }
+    }
+}
+
+// Part of the poster covered by one tile
+pub struct PosterTile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub camera: Camera,
+}
+
+impl PosterSettings {
+    // Width and height of the texture each tile is rendered into
+    pub fn tile_render_size(&self) -> u32 {
+        self.tile_size + 2 * self.margin
+    }
+
+    // Tiles in row major order, with cameras looking through their part of the given camera.
+    // The camera's aspect ratio is changed to the poster's.
+    pub fn tiles(&self, camera: &Camera) -> Vec<PosterTile> {
+        let mut camera = camera.clone();
+        camera.resize(self.width, self.height);
+
+        let render_size = self.tile_render_size() as f32;
+        let mut tiles = vec![];
+        for y in (0..self.height).step_by(self.tile_size as usize) {
+            for x in (0..self.width).step_by(self.tile_size as usize) {
+                // All tiles are rendered at the same size, so the last ones in each row
+                // and column just see past the edge of the poster
+                let left = x as f32 - self.margin as f32;
+                let top = y as f32 - self.margin as f32;
+                tiles.push(PosterTile {
+                    x,
+                    y,
+                    width: self.tile_size.min(self.width - x),
+                    height: self.tile_size.min(self.height - y),
+                    camera: camera.sub_view(
+                        left / self.width as f32,
+                        top / self.height as f32,
+                        render_size / self.width as f32,
+                        render_size / self.height as f32,
+                    ),
+                });
+            }
+        }
+        tiles
+    }
+
+    // Copies a rendered tile, without its margin, into the poster
+    pub fn stitch<P: Pixel>(
+        &self,
+        poster: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+        tile: &PosterTile,
+        rendered: &ImageBuffer<P, Vec<P::Subpixel>>,
+    ) {
+        let visible = rendered.view(self.margin, self.margin, tile.width, tile.height);
+        poster.copy_from(&*visible, tile.x, tile.y)
+            .expect("Tile is outside of the poster");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma};
+
+    use super::*;
+    use crate::math::{Rect, Vec2f, Vec4f};
+
+    fn settings(width: u32, height: u32, tile_size: u32, margin: u32) -> PosterSettings {
+        PosterSettings { width, height, tile_size, margin }
+    }
+
+    fn camera() -> Camera {
+        Camera::new(16, 9, 45.0, 0.1, 100.0)
+    }
+
+    // Pixel a view space point lands on when rendered at the given size
+    fn pixel(camera: &Camera, point: Vec4f, width: u32, height: u32) -> Vec2f {
+        let clip = camera.projection_matrix() * point;
+        let ndc = clip.xy() / clip.w;
+        Vec2f::new((ndc.x + 1.0) / 2.0 * width as f32, (1.0 - ndc.y) / 2.0 * height as f32)
+    }
+
+    #[test]
+    fn tiles_cover_the_poster_once() {
+        let settings = settings(5000, 3000, 2048, 64);
+        let tiles = settings.tiles(&camera());
+        assert_eq!(tiles.len(), 3 * 2);
+        let widths: Vec<u32> = tiles.iter().take(3).map(|t| t.width).collect();
+        assert_eq!(widths, [2048, 2048, 904]);
+        let heights: Vec<u32> = tiles.iter().step_by(3).map(|t| t.height).collect();
+        assert_eq!(heights, [2048, 952]);
+        let area: u32 = tiles.iter().map(|t| t.width * t.height).sum();
+        assert_eq!(area, 5000 * 3000);
+    }
+
+    #[test]
+    fn single_tile_when_poster_fits() {
+        let tiles = settings(300, 200, 512, 8).tiles(&camera());
+        assert_eq!(tiles.len(), 1);
+        assert_eq!((tiles[0].x, tiles[0].y, tiles[0].width, tiles[0].height), (0, 0, 300, 200));
+    }
+
+    #[test]
+    fn tile_cameras_see_their_part_of_the_poster() {
+        let settings = settings(1000, 600, 256, 16);
+        let mut poster_camera = camera();
+        poster_camera.resize(settings.width, settings.height);
+        let render_size = settings.tile_render_size();
+        let points = [Vec4f::new(0.3, -0.2, -2.0, 1.0), Vec4f::new(-1.5, 0.7, -3.0, 1.0)];
+        for tile in settings.tiles(&camera()) {
+            for point in points {
+                let in_poster = pixel(&poster_camera, point, settings.width, settings.height);
+                let in_tile = pixel(&tile.camera, point, render_size, render_size);
+                // The tile's render starts a margin before the tile
+                let origin = Vec2f::new(tile.x as f32, tile.y as f32) - Vec2f::from_element(settings.margin as f32);
+                assert!((in_tile - (in_poster - origin)).norm() < 1e-2, "{:?} vs {:?}", in_tile, in_poster - origin);
+            }
+        }
+    }
+
+    #[test]
+    fn tile_sub_view_rects_locate_the_tiles() {
+        let settings = settings(1000, 600, 256, 16);
+        let render_size = settings.tile_render_size() as f32;
+        assert_eq!(camera().sub_view_rect(), Rect { x: 0.0, y: 0.0, w: 1.0, h: 1.0 });
+        for tile in settings.tiles(&camera()) {
+            let rect = tile.camera.sub_view_rect();
+            let frame = Vec2f::new(render_size / rect.w, render_size / rect.h);
+            let offset = Vec2f::new(rect.x, rect.y).component_mul(&frame);
+            let expected = Vec2f::new(tile.x as f32, tile.y as f32) - Vec2f::from_element(settings.margin as f32);
+            assert!((frame - Vec2f::new(1000.0, 600.0)).norm() < 1e-2, "{:?}", frame);
+            assert!((offset - expected).norm() < 1e-2, "{:?} vs {:?}", offset, expected);
+        }
+    }
+
+    #[test]
+    fn stitch_crops_the_margins() {
+        let settings = settings(5, 3, 2, 1);
+        let render_size = settings.tile_render_size();
+        let value = |x: u32, y: u32| (y * settings.width + x) as u8;
+        let mut poster = GrayImage::new(settings.width, settings.height);
+        for tile in settings.tiles(&camera()) {
+            // Margins and the part past the poster's edge are filled with 255
+            let rendered = GrayImage::from_fn(render_size, render_size, |x, y| {
+                let (px, py) = ((tile.x + x) as i32 - 1, (tile.y + y) as i32 - 1);
+                let inside_tile = x >= 1 && y >= 1 && x < 1 + tile.width && y < 1 + tile.height;
+                Luma([if inside_tile { value(px as u32, py as u32) } else { 255 }])
+            });
+            settings.stitch(&mut poster, &tile, &rendered);
+        }
+        for (x, y, pixel) in poster.enumerate_pixels() {
+            assert_eq!(pixel[0], value(x, y));
+        }
+    }
+}
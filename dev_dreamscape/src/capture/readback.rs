@@ -0,0 +1,137 @@
+use std::sync::{Arc, Mutex};
+
+use image::{Rgba32FImage, RgbaImage};
+
+use crate::device::Device;
+
+// Copies a texture into a mappable buffer so it can be read on the CPU.
+// The copy is submitted in `new`, `wait` then blocks until the data is mapped.
+// To avoid blocking, check `is_ready` once a frame instead and only call `wait` when it returns true.
+pub struct TextureReadback {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    format: wgpu::TextureFormat,
+    // Set by the map_async callback
+    map_result: Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>,
+}
+
+impl TextureReadback {
+    pub fn new(device: &Device, texture: &wgpu::Texture) -> Self {
+        let (width, height) = (texture.width(), texture.height());
+        let format = texture.format();
+        let bytes_per_pixel = format.block_size(None)
+            .expect("Can't read back depth/stencil textures");
+        // Rows in the buffer have to be aligned to 256 bytes
+        let unpadded_bytes_per_row = width * bytes_per_pixel;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        device.queue().submit([encoder.finish()]);
+
+        let map_result = Arc::new(Mutex::new(None));
+        let callback_result = map_result.clone();
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            *callback_result.lock().unwrap() = Some(result);
+        });
+
+        Self {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            format,
+            map_result,
+        }
+    }
+
+    // Doesn't block. The device has to be polled (e.g. with Maintain::Poll) for the mapping to progress.
+    pub fn is_ready(&self) -> bool {
+        self.map_result.lock().unwrap().is_some()
+    }
+
+    // Blocks until the copy has finished. Only 8 bit rgba/bgra textures can be
+    // converted, which covers the surface formats and the headless target.
+    pub fn wait(self, device: &Device) -> RgbaImage {
+        let bgra = match self.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            format => panic!("Unsupported readback format {:?}", format),
+        };
+        let (width, height) = (self.width, self.height);
+
+        let mut pixels = self.wait_for_bytes(device);
+        if bgra {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        RgbaImage::from_raw(width, height, pixels).unwrap()
+    }
+
+    // Same as wait, for float textures such as the Rgba16Float hdr buffer.
+    // The values are linear and not tonemapped.
+    pub fn wait_hdr(self, device: &Device) -> Rgba32FImage {
+        let format = self.format;
+        let (width, height) = (self.width, self.height);
+
+        let bytes = self.wait_for_bytes(device);
+        let pixels: Vec<f32> = match format {
+            wgpu::TextureFormat::Rgba16Float => bytes
+                .chunks_exact(2)
+                .map(|b| half::f16::from_le_bytes([b[0], b[1]]).to_f32())
+                .collect(),
+            wgpu::TextureFormat::Rgba32Float => bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            format => panic!("Unsupported hdr readback format {:?}", format),
+        };
+        Rgba32FImage::from_raw(width, height, pixels).unwrap()
+    }
+
+    // Tightly packed rows of the texture
+    fn wait_for_bytes(self, device: &Device) -> Vec<u8> {
+        if !self.is_ready() {
+            device.poll(wgpu::Maintain::Wait);
+        }
+        self.map_result.lock().unwrap()
+            .take()
+            .expect("Readback buffer wasn't mapped after waiting")
+            .expect("Couldn't map readback buffer");
+
+        let row_bytes = (self.width * self.format.block_size(None).unwrap()) as usize;
+        let mut bytes = Vec::with_capacity(row_bytes * self.height as usize);
+        {
+            let data = self.buffer.slice(..).get_mapped_range();
+            for row in data.chunks(self.padded_bytes_per_row as usize) {
+                bytes.extend_from_slice(&row[..row_bytes]);
+            }
+        }
+        self.buffer.unmap();
+        bytes
+    }
+}
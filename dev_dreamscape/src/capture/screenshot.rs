@@ -0,0 +1,181 @@
+use std::path::PathBuf;
+
+use bevy_ecs::prelude::*;
+use winit::event::VirtualKeyCode;
+
+use crate::device::Device;
+use crate::events::KeyboardEvent;
+use super::{image_ops, PanoramaSettings, TextureReadback};
+
+// Saves the presented frame as screenshot-<timestamp>.png, plus the untonemapped
+// hdr buffer as screenshot-<timestamp>.exr when save_hdr is set.
+// Take one with the key (F12 by default) or by calling `request` from a system.
+//
+// The copy is recorded by the render system right before presenting, and the files are
+// written once the GPU has finished with it, so taking a screenshot doesn't stall the frame.
+//
+// With panorama set, the screenshot is a 360° equirectangular image around the player
+// instead of the presented frame.
+#[derive(Resource)]
+pub struct Screenshots {
+    pub key: VirtualKeyCode,
+    pub output_dir: PathBuf,
+    pub save_hdr: bool,
+    pub panorama: Option<PanoramaSettings>,
+    requested: bool,
+    pending: Vec<PendingScreenshot>,
+}
+
+// One readback for a frame, or one per cube face for a panorama
+struct PendingScreenshot {
+    path: PathBuf,
+    color: Vec<TextureReadback>,
+    hdr: Vec<TextureReadback>,
+    panorama: Option<PanoramaSettings>,
+}
+
+impl Default for Screenshots {
+    fn default() -> Self {
+        Self {
+            key: VirtualKeyCode::F12,
+            output_dir: PathBuf::from("."),
+            save_hdr: false,
+            panorama: None,
+            requested: false,
+            pending: vec![],
+        }
+    }
+}
+
+impl Screenshots {
+    // Captures the next rendered frame
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested
+    }
+
+    // Starts copying the frame. The color texture needs COPY_SRC usage.
+    pub fn capture(&mut self, device: &Device, color_texture: &wgpu::Texture, hdr_texture: &wgpu::Texture) {
+        self.requested = false;
+        if !color_texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            log::error!("Can't take a screenshot, the surface doesn't support copying");
+            return;
+        }
+        self.pending.push(PendingScreenshot {
+            path: self.output_dir.join(format!("screenshot-{}", timestamp())),
+            color: vec![TextureReadback::new(device, color_texture)],
+            hdr: self.save_hdr.then(|| TextureReadback::new(device, hdr_texture)).into_iter().collect(),
+            panorama: None,
+        });
+    }
+
+    // Takes the readbacks of the cube faces rendered for settings.face_views
+    pub fn capture_panorama(&mut self, settings: PanoramaSettings, faces: Vec<(TextureReadback, Option<TextureReadback>)>) {
+        self.requested = false;
+        let (color, hdr): (Vec<_>, Vec<_>) = faces.into_iter().unzip();
+        self.pending.push(PendingScreenshot {
+            path: self.output_dir.join(format!("panorama-{}", timestamp())),
+            color,
+            hdr: hdr.into_iter().flatten().collect(),
+            panorama: Some(settings),
+        });
+    }
+
+    pub fn request_on_key(mut screenshots: ResMut<Screenshots>, mut keyboard_events: EventReader<KeyboardEvent>) {
+        let key = screenshots.key;
+        if keyboard_events.iter().any(|e| e.code == key && e.pressed) {
+            screenshots.request();
+        }
+    }
+
+    // Writes the screenshots whose readback has finished
+    pub fn save_finished(mut screenshots: ResMut<Screenshots>, device: Res<Device>) {
+        if screenshots.pending.is_empty() {
+            return;
+        }
+        device.poll(wgpu::Maintain::Poll);
+
+        for screenshot in std::mem::take(&mut screenshots.pending) {
+            if !screenshot.is_ready() {
+                screenshots.pending.push(screenshot);
+                continue;
+            }
+            let PendingScreenshot { path, color, hdr, panorama } = screenshot;
+            let color: Vec<_> = color.into_iter().map(|readback| readback.wait(&device)).collect();
+            let hdr: Vec<_> = hdr.into_iter().map(|readback| readback.wait_hdr(&device)).collect();
+            // Encoding a large png (and reprojecting a panorama) takes a while, so do it
+            // off the main thread where we can
+            save_in_background(move || {
+                let (color, hdr) = match panorama {
+                    Some(settings) => {
+                        let faces: Vec<_> = color.iter().map(image_ops::to_linear).collect();
+                        let color = image_ops::to_srgb(&settings.reproject(&faces));
+                        let hdr = (!hdr.is_empty()).then(|| settings.reproject(&hdr));
+                        (color, hdr)
+                    }
+                    None => (color.into_iter().next().unwrap(), hdr.into_iter().next()),
+                };
+                let color_path = path.with_extension("png");
+                match color.save(&color_path) {
+                    Ok(()) => log::warn!("Saved screenshot {:?}", color_path),
+                    Err(e) => log::error!("Couldn't save screenshot {:?}: {}", color_path, e),
+                }
+                if let Some(hdr) = hdr {
+                    let hdr_path = path.with_extension("exr");
+                    if let Err(e) = hdr.save(&hdr_path) {
+                        log::error!("Couldn't save screenshot {:?}: {}", hdr_path, e);
+                    }
+                }
+            });
+        }
+    }
+}
+
+impl PendingScreenshot {
+    fn is_ready(&self) -> bool {
+        self.color.iter().chain(&self.hdr).all(TextureReadback::is_ready)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_in_background(save: impl FnOnce() + Send + 'static) {
+    std::thread::spawn(save);
+}
+
+#[cfg(target_arch = "wasm32")]
+fn save_in_background(save: impl FnOnce() + Send + 'static) {
+    save();
+}
+
+// UTC date and time, e.g. 20231018-142501-123
+fn timestamp() -> String {
+    let since_epoch = web_time::SystemTime::now()
+        .duration_since(web_time::SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let time_of_day = secs % 86400;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        year, month, day,
+        time_of_day / 3600, time_of_day / 60 % 60, time_of_day % 60,
+        since_epoch.subsec_millis(),
+    )
+}
+
+// Days since 1970-01-01 to (year, month, day), from Howard Hinnant's date algorithms
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::app::App;
+use crate::device::Device;
+use super::{image_ops, PanoramaSettings};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FrameFormat {
+    // Tonemapped, as shown on screen
+    Png,
+    // Linear hdr buffer before tonemapping, for grading in other tools
+    Exr,
+}
+
+impl FrameFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            FrameFormat::Png => "png",
+            FrameFormat::Exr => "exr",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SequenceSettings {
+    pub output_dir: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f32,
+    // Scene time in seconds of the first and last frame. The scene is simulated
+    // (but not rendered) from 0 up to start_time.
+    pub start_time: f32,
+    pub end_time: f32,
+    // Each frame is rendered at width * supersampling x height * supersampling
+    // and box filtered down to the output size
+    pub supersampling: u32,
+    pub format: FrameFormat,
+    // Renders 360° equirectangular frames instead, ignoring width and height
+    pub panorama: Option<PanoramaSettings>,
+    pub force_fallback_adapter: bool,
+}
+
+impl Default for SequenceSettings {
+    fn default() -> Self {
+        Self {
+            output_dir: PathBuf::from("capture"),
+            width: 1920,
+            height: 1080,
+            fps: 60.0,
+            start_time: 0.0,
+            end_time: 10.0,
+            supersampling: 1,
+            format: FrameFormat::Png,
+            panorama: None,
+            force_fallback_adapter: false,
+        }
+    }
+}
+
+// Renders the scene offline into numbered frames (frame_00000.png, ...) in output_dir.
+// Time advances by exactly 1/fps per frame through the manual frame time, so the result
+// doesn't depend on how long each frame takes to render.
+pub async fn capture_sequence(settings: &SequenceSettings) -> anyhow::Result<()> {
+    anyhow::ensure!(settings.fps > 0.0, "fps must be positive");
+    anyhow::ensure!(settings.supersampling > 0, "supersampling must be at least 1");
+    anyhow::ensure!(settings.end_time >= settings.start_time, "end_time is before start_time");
+
+    std::fs::create_dir_all(&settings.output_dir)
+        .with_context(|| format!("Couldn't create {:?}", settings.output_dir))?;
+
+    // Panoramas are rendered into their own face textures, so the surface size only matters for flat frames
+    let panorama = settings.panorama.map(|panorama| PanoramaSettings {
+        width: panorama.width * settings.supersampling,
+        ..panorama
+    });
+    let (width, height) = match &panorama {
+        Some(panorama) => (panorama.face_size(), panorama.face_size()),
+        None => (settings.width * settings.supersampling, settings.height * settings.supersampling),
+    };
+    let max_size = Device::headless_max_texture_size(settings.force_fallback_adapter).await
+        .context("No suitable adapter found for headless rendering")?;
+    anyhow::ensure!(
+        width <= max_size && height <= max_size,
+        "{}x{} frames (with supersampling) are larger than the adapter's maximum texture size of {}",
+        width, height, max_size,
+    );
+    let mut app = App::new_headless(width, height, settings.force_fallback_adapter).await;
+    app.load_assets().await;
+
+    let timestep = Duration::from_secs_f32(1.0 / settings.fps);
+    let preroll_frames = (settings.start_time * settings.fps).round() as u32;
+    // Frames up to end_time, allowing for rounding errors in the frame times
+    let num_frames = ((settings.end_time - settings.start_time) * settings.fps + 1e-3).floor() as u32 + 1;
+
+    // Rendering a frame advances the scene first, so the preroll stops one frame short of
+    // start_time. When that's 0, the first frame advances by nothing instead.
+    for _ in 1..preroll_frames {
+        app.update_scene(timestep);
+    }
+
+    for frame in 0..num_frames {
+        let _span = tracing::info_span!("capture_frame", frame).entered();
+        let frame_duration = if frame == 0 && preroll_frames == 0 { Duration::ZERO } else { timestep };
+        let path = settings.output_dir
+            .join(format!("frame_{:05}.{}", frame, settings.format.extension()));
+        match (settings.format, &panorama) {
+            (FrameFormat::Png, None) => {
+                let image = image_ops::downsample_srgb(&app.render_to_image(frame_duration), settings.supersampling);
+                image.save(&path)
+            }
+            (FrameFormat::Exr, None) => {
+                let image = image_ops::downsample(&app.render_to_hdr_image(frame_duration), settings.supersampling);
+                image.save(&path)
+            }
+            (FrameFormat::Png, Some(panorama)) => {
+                let image = app.render_panorama_to_image(panorama, frame_duration);
+                image_ops::downsample_srgb(&image, settings.supersampling).save(&path)
+            }
+            (FrameFormat::Exr, Some(panorama)) => {
+                let image = app.render_panorama_to_hdr_image(panorama, frame_duration);
+                image_ops::downsample(&image, settings.supersampling).save(&path)
+            }
+        }.with_context(|| format!("Couldn't write {:?}", path))?;
+        log::info!("Captured {:?} ({}/{})", path, frame + 1, num_frames);
+    }
+    Ok(())
+}
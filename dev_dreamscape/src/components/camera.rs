@@ -2,8 +2,10 @@ use rapier3d::na;
 use bevy_ecs::prelude::*;
 #[allow(unused_imports)]
 use log::error;
-use crate::math::{Mat4, Mat4f};
-use crate::components::Transform;
+use crate::assets::Assets;
+use crate::device::Device;
+use crate::math::{Mat4, Mat4f, Rect};
+use crate::components::{Frustum, Projection, Transform};
 
 
 #[rustfmt::skip]
@@ -15,18 +17,108 @@ pub const OPENGL_TO_WGPU_MATRIX: Mat4f = Mat4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
-#[derive(Debug,Component)]
+// Where a camera renders to
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub enum RenderTarget {
+    #[default]
+    Window,
+    // Texture in the asset texture store, see Assets::add_render_target.
+    // Other models can use it as their diffuse texture through ModelSpec.
+    Texture(String),
+}
+
+#[derive(Debug,Clone,Component)]
 pub struct Camera {
-    perspective: na::Perspective3<f32>,
+    projection: Projection,
+    // Clip space scale and offset that zooms into a part of the view, for tiled rendering.
+    // Identity for a normal camera.
+    sub_view: Mat4f,
+    pub target: RenderTarget,
+    // Part of the target to render to, as fractions of its size from the top left corner.
+    // None covers the whole target.
+    pub viewport: Option<Rect>,
+    // Cameras render in increasing priority order each frame, and later cameras draw over
+    // earlier ones on the same target. Cameras rendering into a texture should come before
+    // the ones that see it, or the texture shows the previous frame.
+    pub priority: i32,
 }
 
 impl Camera {
+    // Perspective camera
     pub fn new(width: u32, height: u32, fovy: f32, znear: f32, zfar: f32) -> Self {
+        Self::from_projection(Projection::Perspective(
+            na::Perspective3::new(width as f32 / height as f32, fovy, znear, zfar)
+        ))
+    }
+
+    // Orthographic camera showing view_height world units vertically
+    #[allow(dead_code)]
+    pub fn orthographic(width: u32, height: u32, view_height: f32, znear: f32, zfar: f32) -> Self {
+        Self::from_projection(Projection::orthographic(
+            width as f32 / height as f32, view_height, znear, zfar
+        ))
+    }
+
+    pub fn from_projection(projection: Projection) -> Self {
+        Self {
+            projection,
+            sub_view: Mat4f::identity(),
+            target: RenderTarget::Window,
+            viewport: None,
+            priority: 0,
+        }
+    }
+
+    pub fn with_target(mut self, target: RenderTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn with_viewport(mut self, viewport: Rect) -> Self {
+        self.viewport = Some(viewport);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    // Camera that sees only the given part of this camera's view, as fractions of the
+    // full view from the top left corner. The rectangle may extend past the view.
+    pub fn sub_view(&self, x: f32, y: f32, width: f32, height: f32) -> Self {
+        // Rectangle in normalized device coordinates, where y points up
+        let (left, right) = (x * 2.0 - 1.0, (x + width) * 2.0 - 1.0);
+        let (top, bottom) = (1.0 - y * 2.0, 1.0 - (y + height) * 2.0);
+        #[rustfmt::skip]
+        let scale_offset = Mat4::new(
+            2.0 / (right - left), 0.0, 0.0, -(right + left) / (right - left),
+            0.0, 2.0 / (top - bottom), 0.0, -(top + bottom) / (top - bottom),
+            0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        );
         Self {
-            perspective: na::Perspective3::new(width as f32 / height as f32, fovy, znear, zfar)
+            sub_view: scale_offset * self.sub_view,
+            ..self.clone()
         }
     }
     
+    // The part of the full view this camera sees, as passed to sub_view.
+    // (0, 0, 1, 1) for a normal camera.
+    pub fn sub_view_rect(&self) -> Rect {
+        let (scale_x, offset_x) = (self.sub_view[(0, 0)], self.sub_view[(0, 3)]);
+        let (scale_y, offset_y) = (self.sub_view[(1, 1)], self.sub_view[(1, 3)]);
+        // Edges of this view in the normalized device coordinates of the full view
+        let left = (-1.0 - offset_x) / scale_x;
+        let top = (1.0 - offset_y) / scale_y;
+        Rect {
+            x: (left + 1.0) / 2.0,
+            y: (1.0 - top) / 2.0,
+            w: 1.0 / scale_x,
+            h: 1.0 / scale_y,
+        }
+    }
+
     pub fn view_proj(&self, transform: &Transform) -> Mat4f {
         // Removed premultiply by OPENGL_TO_WGPU_MATRIX as it seems
         // to cause a sliding effect relative to the skybox
@@ -41,25 +133,82 @@ impl Camera {
     }
 
     pub fn projection_matrix(&self) -> Mat4f {
-        self.perspective.as_matrix().clone()
+        self.sub_view * self.projection.matrix()
     }
 
-    // Perspective3.inverse is faster than general matrix inverse
     pub fn inv_projection_matrix(&self) -> Mat4f {
-        self.perspective.inverse()
+        self.projection.inverse() * self.sub_view.try_inverse().unwrap()
+    }
+
+    pub fn frustum(&self, transform: &Transform) -> Frustum {
+        Frustum::from_view_proj(&self.view_proj(transform))
+    }
+
+    pub fn projection(&self) -> &Projection {
+        &self.projection
+    }
+
+    #[allow(dead_code)]
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        self.sub_view = Mat4f::identity();
     }
 
     // Using in WebXR where the projection matrix is provided directly.
     // rather than decomposed aspect fovy, znear zfar.
-    // There's a github discussion about why, but the TLDR is there could 
-    // potentially be non-standard projection matrices (e.g. with shear),
-    // https://github.com/immersive-web/webxr/issues/461
     #[allow(dead_code)]
     pub fn set_projection_matrix(&mut self, matrix: Mat4f) {
-        self.perspective = na::Perspective3::from_matrix_unchecked(matrix);
+        self.set_projection(Projection::Matrix(matrix));
+    }
+
+    pub fn znear(&self) -> f32 {
+        self.projection.znear()
+    }
+
+    pub fn zfar(&self) -> f32 {
+        self.projection.zfar()
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
-        self.perspective.set_aspect(width as f32 / height as f32);
+        self.projection.set_aspect(width as f32 / height as f32);
+    }
+
+    // Size in pixels of the target, or None if the target texture doesn't exist
+    pub fn target_size(&self, device: &Device, assets: &Assets) -> Option<(u32, u32)> {
+        match &self.target {
+            RenderTarget::Window => Some((device.surface_size().width, device.surface_size().height)),
+            RenderTarget::Texture(name) => assets.texture_store.get(name)
+                .map(|texture| (texture.texture.width(), texture.texture.height())),
+        }
+    }
+
+    // Viewport in pixels for a target of the given size
+    pub fn viewport_rect(&self, width: u32, height: u32) -> Option<Rect> {
+        self.viewport.map(|vp| Rect {
+            x: vp.x * width as f32,
+            y: vp.y * height as f32,
+            w: vp.w * width as f32,
+            h: vp.h * height as f32,
+        })
+    }
+
+    // Keeps the aspect ratio of each camera matching its viewport, e.g. after the window is resized
+    pub fn update_aspect(device: Res<Device>, assets: Res<Assets>, mut cameras: Query<&mut Camera>) {
+        for mut camera in cameras.iter_mut() {
+            let Some((width, height)) = camera.target_size(&device, &assets) else {
+                continue;
+            };
+            let (width, height) = match camera.viewport_rect(width, height) {
+                Some(vp) => (vp.w, vp.h),
+                None => (width as f32, height as f32),
+            };
+            if width == 0.0 || height == 0.0 {
+                continue;
+            }
+            let aspect = width / height;
+            if camera.projection.aspect().is_some_and(|current| (current - aspect).abs() > 1e-6) {
+                camera.projection.set_aspect(aspect);
+            }
+        }
     }
 }
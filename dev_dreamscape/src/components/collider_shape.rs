@@ -0,0 +1,191 @@
+use crate::assets::CollisionModel;
+use crate::math::{to_point, Vec3f};
+use rapier3d::na::DMatrix;
+use rapier3d::prelude::*;
+
+// Shape of a physics body or trigger volume, before scaling. Shapes are scaled by the
+// entity's scale, round ones by its largest component in the directions they are round in.
+#[derive(Clone)]
+pub enum ColliderShape<'a> {
+    Ball { radius: f32 },
+    Cuboid { half_extents: Vec3f },
+    // Along the y axis
+    Capsule { half_height: f32, radius: f32 },
+    Cylinder { half_height: f32, radius: f32 },
+    // Better than a trimesh for moving bodies, which rapier collides poorly
+    ConvexHull(&'a CollisionModel),
+    // Convex hulls of each of the model's meshes, e.g. from CollisionModel::convex_decomposition
+    ConvexParts(&'a CollisionModel),
+    // The model's triangles. Best for fixed bodies such as the ground. It has no inside, so
+    // as a trigger volume it only reports crossing its surface.
+    Trimesh(&'a CollisionModel),
+    // Heights on a grid in the xz plane, with rows along z. Size is the grid's extent and the
+    // height scale.
+    Heightfield { heights: DMatrix<Real>, size: Vec3f },
+    // Several shapes at offsets from the body. Nested compounds and convex parts are flattened
+    // into it. Trimeshes and heightfields can't be part of a compound and are skipped.
+    Compound(Vec<(Isometry<Real>, ColliderShape<'a>)>),
+}
+
+impl ColliderShape<'_> {
+    pub fn shared_shape(&self, scale: Vec3f) -> SharedShape {
+        match self {
+            Self::Ball { radius } => SharedShape::ball(radius * scale.max()),
+            Self::Cuboid { half_extents } => {
+                let half_extents = half_extents.component_mul(&scale);
+                SharedShape::cuboid(half_extents.x, half_extents.y, half_extents.z)
+            }
+            Self::Capsule { half_height, radius } => {
+                SharedShape::capsule_y(half_height * scale.y, radius * scale.x.max(scale.z))
+            }
+            Self::Cylinder { half_height, radius } => {
+                SharedShape::cylinder(half_height * scale.y, radius * scale.x.max(scale.z))
+            }
+            Self::ConvexHull(collision_model) => {
+                SharedShape::convex_hull(&scaled_points(collision_model, scale)).unwrap_or_else(|| {
+                    log::warn!("No convex hull for collision model, using its triangles");
+                    Self::Trimesh(collision_model).shared_shape(scale)
+                })
+            }
+            Self::ConvexParts(collision_model) => {
+                let mut parts = vec![];
+                self.add_compound_parts(Isometry::identity(), scale, &mut parts);
+                if parts.is_empty() {
+                    log::warn!("No convex parts for collision model, using its triangles");
+                    return Self::Trimesh(collision_model).shared_shape(scale);
+                }
+                SharedShape::compound(parts)
+            }
+            Self::Trimesh(collision_model) => {
+                let indices = collision_model.get_all_triangle_indices();
+                if indices.is_empty() {
+                    return empty_shape();
+                }
+                SharedShape::trimesh(scaled_points(collision_model, scale), indices)
+            }
+            // Parry needs at least one cell, i.e. 2x2 heights
+            Self::Heightfield { heights, .. } if heights.nrows() < 2 || heights.ncols() < 2 => {
+                log::warn!("Heightfield needs at least 2x2 heights, got {}x{}", heights.nrows(), heights.ncols());
+                empty_shape()
+            }
+            Self::Heightfield { heights, size } => SharedShape::heightfield(heights.clone(), size.component_mul(&scale)),
+            Self::Compound(_) => {
+                let mut parts = vec![];
+                self.add_compound_parts(Isometry::identity(), scale, &mut parts);
+                if parts.is_empty() {
+                    return empty_shape();
+                }
+                SharedShape::compound(parts)
+            }
+        }
+    }
+
+    // Adds the shape to a compound at the offset, flattening nested compounds and convex parts,
+    // as rapier only takes convex and primitive shapes in a compound
+    fn add_compound_parts(&self, offset: Isometry<Real>, scale: Vec3f, parts: &mut Vec<(Isometry<Real>, SharedShape)>) {
+        match self {
+            Self::Compound(shapes) => {
+                for (shape_offset, shape) in shapes {
+                    let mut shape_offset = *shape_offset;
+                    shape_offset.translation.vector.component_mul_assign(&scale);
+                    shape.add_compound_parts(offset * shape_offset, scale, parts);
+                }
+            }
+            Self::ConvexParts(collision_model) => {
+                let hulls = collision_model.collision_meshes.iter().filter_map(|mesh| {
+                    let points = mesh.vertices.iter().map(|v| to_point(v.component_mul(&scale))).collect::<Vec<_>>();
+                    SharedShape::convex_hull(&points)
+                });
+                parts.extend(hulls.map(|hull| (offset, hull)));
+            }
+            Self::ConvexHull(collision_model) => match SharedShape::convex_hull(&scaled_points(collision_model, scale)) {
+                Some(hull) => parts.push((offset, hull)),
+                None => log::warn!("No convex hull for collision model, skipping it in the compound shape"),
+            },
+            Self::Trimesh(_) | Self::Heightfield { .. } => {
+                log::warn!("Skipping trimesh or heightfield in a compound shape");
+            }
+            _ => parts.push((offset, self.shared_shape(scale))),
+        }
+    }
+}
+
+// Stands in for a shape without any parts or triangles, as rapier can't build those
+fn empty_shape() -> SharedShape {
+    log::warn!("Collider shape is empty, using a point");
+    SharedShape::ball(0.0)
+}
+
+fn scaled_points(collision_model: &CollisionModel, scale: Vec3f) -> Vec<Point<Real>> {
+    collision_model.get_all_vertices()
+        .into_iter()
+        .map(|v| to_point(v.component_mul(&scale)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::CollisionMesh;
+
+    fn tetrahedron(offset: f32) -> CollisionMesh {
+        CollisionMesh {
+            vertices: vec![
+                Vec3f::new(offset, 0.0, 0.0),
+                Vec3f::new(offset + 1.0, 0.0, 0.0),
+                Vec3f::new(offset, 1.0, 0.0),
+                Vec3f::new(offset, 0.0, 1.0),
+            ],
+            triangle_indices: vec![[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]],
+        }
+    }
+
+    fn parts(shape: &SharedShape) -> usize {
+        shape.as_compound().expect("Not a compound").shapes().len()
+    }
+
+    #[test]
+    fn nested_compounds_and_convex_parts_are_flattened() {
+        let model = CollisionModel { collision_meshes: vec![tetrahedron(0.0), tetrahedron(2.0)] };
+        let shape = ColliderShape::Compound(vec![
+            (Isometry::identity(), ColliderShape::Ball { radius: 1.0 }),
+            (Isometry::translation(0.0, 2.0, 0.0), ColliderShape::ConvexParts(&model)),
+            (Isometry::translation(0.0, -2.0, 0.0), ColliderShape::Compound(vec![
+                (Isometry::translation(1.0, 0.0, 0.0), ColliderShape::Cuboid { half_extents: Vec3f::from_element(0.5) }),
+                (Isometry::identity(), ColliderShape::ConvexHull(&model)),
+            ])),
+        ]).shared_shape(Vec3f::from_element(2.0));
+        assert_eq!(parts(&shape), 5);
+        // Offsets compose and are scaled
+        let cuboid = shape.as_compound().unwrap().shapes()[3].0;
+        assert_eq!(cuboid.translation.vector, Vector::new(2.0, -4.0, 0.0));
+    }
+
+    #[test]
+    fn trimeshes_and_heightfields_are_skipped_in_compounds() {
+        let model = CollisionModel { collision_meshes: vec![tetrahedron(0.0)] };
+        let shape = ColliderShape::Compound(vec![
+            (Isometry::identity(), ColliderShape::Trimesh(&model)),
+            (Isometry::identity(), ColliderShape::Heightfield { heights: DMatrix::zeros(3, 3), size: Vec3f::from_element(1.0) }),
+            (Isometry::identity(), ColliderShape::Capsule { half_height: 1.0, radius: 0.5 }),
+        ]).shared_shape(Vec3f::from_element(1.0));
+        assert_eq!(parts(&shape), 1);
+    }
+
+    #[test]
+    fn empty_shapes_dont_panic() {
+        let empty = CollisionModel { collision_meshes: vec![] };
+        let scale = Vec3f::from_element(1.0);
+        assert!(ColliderShape::Compound(vec![]).shared_shape(scale).as_ball().is_some());
+        assert!(ColliderShape::Compound(vec![(Isometry::identity(), ColliderShape::Compound(vec![]))]).shared_shape(scale).as_ball().is_some());
+        assert!(ColliderShape::ConvexParts(&empty).shared_shape(scale).as_ball().is_some());
+        assert!(ColliderShape::Trimesh(&empty).shared_shape(scale).as_ball().is_some());
+        assert!(ColliderShape::ConvexHull(&empty).shared_shape(scale).as_ball().is_some());
+        for (rows, cols) in [(0, 0), (1, 5), (5, 1)] {
+            let heightfield = ColliderShape::Heightfield { heights: DMatrix::zeros(rows, cols), size: Vec3f::from_element(1.0) };
+            assert!(heightfield.shared_shape(scale).as_ball().is_some());
+        }
+        let heightfield = ColliderShape::Heightfield { heights: DMatrix::zeros(2, 2), size: Vec3f::from_element(1.0) };
+        assert!(heightfield.shared_shape(scale).as_heightfield().is_some());
+    }
+}
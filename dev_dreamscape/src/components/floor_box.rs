@@ -1,5 +1,5 @@
 use crate::components::transform::Transform;
-use crate::components::{PhysicsBody, PhysicsBodyParams};
+use crate::components::{ColliderShape, PhysicsBody, PhysicsBodyParams};
 use crate::components::ModelSpec;
 use crate::math::{Vec3f,UnitQuatf};
 use crate::assets::Assets;
@@ -35,11 +35,13 @@ impl FloorBox {
                 rotation_axis: Vec3f::from_element(0.0),
                 rotation_angle: 0.0,
                 movable: false,
-                collision_model: Some(&collision_model),
-                collision_ball: None,
+                shape: ColliderShape::Trimesh(&collision_model),
+                material: assets.physics_material("mars_surface/Crater_low-collision.obj"),
                 gravity_scale: None,
                 lin_vel: None,
                 ang_vel: None,
+                contact_force_threshold: None,
+                ccd: None,
             },
             &mut physics,
         );
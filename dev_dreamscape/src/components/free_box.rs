@@ -1,6 +1,7 @@
 use crate::components::transform::Transform;
-use crate::components::{PhysicsBody, PhysicsBodyParams, Player};
+use crate::components::{ColliderShape, PhysicsBody, PhysicsBodyParams, Player};
 use crate::components::ModelSpec;
+use crate::assets::Assets;
 use crate::input::Input;
 use crate::math::{Vec3f,UnitQuatf};
 use crate::physics_world::PhysicsWorld;
@@ -13,9 +14,10 @@ impl FreeBox {
     pub fn spawn(
         mut commands: Commands,
         mut physics: ResMut<PhysicsWorld>,
+        assets: Res<Assets>,
     ) {
         let pos = Vec3f::new(0., 10., 0.);
-        commands.spawn(Self::new_components(pos, &mut physics));
+        commands.spawn(Self::new_components(pos, &mut physics, &assets));
     }
 
     pub fn spawn_by_player(
@@ -23,17 +25,19 @@ impl FreeBox {
         mut commands: Commands,
         mut physics: ResMut<PhysicsWorld>,
         input: Res<Input>,
+        assets: Res<Assets>,
     ) {
         if input.space_just_pressed {
             let player_transform = player.single();
             let pos = player_transform.position() + player_transform.forward().xyz() * 5.0;
-            commands.spawn(Self::new_components(pos, &mut physics));
+            commands.spawn(Self::new_components(pos, &mut physics, &assets));
         }
     }
 
     fn new_components(
         pos: Vec3f,
         physics: &mut PhysicsWorld,
+        assets: &Assets,
     ) -> (FreeBox, PhysicsBody, Transform, ModelSpec) {
         let rot = UnitQuatf::identity();
         let scale = Vec3f::from_element(1.0);
@@ -44,11 +48,13 @@ impl FreeBox {
                 rotation_axis: Vec3f::identity(),
                 rotation_angle: 0.0,
                 movable: true,
-                collision_model: None,
-                collision_ball: None,
+                shape: ColliderShape::Cuboid { half_extents: Vec3f::from_element(1.0) },
+                material: assets.physics_material("cube.obj"),
                 gravity_scale: Some(0.6),
                 lin_vel: None,
                 ang_vel: None,
+                contact_force_threshold: None,
+                ccd: None,
             },
             physics,
         );
@@ -0,0 +1,90 @@
+use bevy_ecs::prelude::*;
+
+use crate::components::Transform;
+use crate::math::{to_point, Vec3f};
+use crate::physics_world::{PhysicsSettings, PhysicsWorld};
+
+// Region of the zone around its Transform, which also rotates and scales it
+#[derive(Debug, Clone, Copy)]
+pub enum ZoneShape {
+    Sphere { radius: f32 },
+    Box { half_extents: Vec3f },
+    // Everywhere, e.g. for a zero-g scene
+    Everywhere,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ZoneGravity {
+    // The same everywhere in the zone. Zero for weightlessness.
+    Uniform(Vec3f),
+    // Towards the zone's position, like a planet. Negative pushes away.
+    Radial { acceleration: f32 },
+}
+
+// Overrides the world gravity for the dynamic bodies inside it. Where zones overlap the
+// one with the highest priority wins.
+#[derive(Debug, Clone, Component)]
+pub struct GravityZone {
+    pub shape: ZoneShape,
+    pub gravity: ZoneGravity,
+    pub priority: i32,
+}
+
+impl GravityZone {
+    pub fn zero_g(shape: ZoneShape) -> Self {
+        Self {
+            shape,
+            gravity: ZoneGravity::Uniform(Vec3f::zeros()),
+            priority: 0,
+        }
+    }
+
+    pub fn radial(radius: f32, acceleration: f32) -> Self {
+        Self {
+            shape: ZoneShape::Sphere { radius },
+            gravity: ZoneGravity::Radial { acceleration },
+            priority: 0,
+        }
+    }
+
+    fn contains(&self, transform: &Transform, pos: &Vec3f) -> bool {
+        let Some(inverse) = transform.matrix().try_inverse() else {
+            return false;
+        };
+        let local = inverse.transform_point(&to_point(*pos)).coords;
+        match self.shape {
+            ZoneShape::Sphere { radius } => local.norm() <= radius,
+            ZoneShape::Box { half_extents } => local.abs().iter().zip(half_extents.iter()).all(|(p, h)| p <= h),
+            ZoneShape::Everywhere => true,
+        }
+    }
+
+    fn gravity_at(&self, transform: &Transform, pos: &Vec3f) -> Vec3f {
+        match self.gravity {
+            ZoneGravity::Uniform(gravity) => gravity,
+            ZoneGravity::Radial { acceleration } => {
+                let to_center = transform.position() - pos;
+                // No direction at the center itself
+                if to_center.norm() < 1e-6 {
+                    Vec3f::zeros()
+                } else {
+                    to_center.normalize() * acceleration
+                }
+            }
+        }
+    }
+
+    pub fn apply(
+        zones: Query<(&GravityZone, &Transform)>,
+        settings: Res<PhysicsSettings>,
+        mut physics: ResMut<PhysicsWorld>,
+    ) {
+        let mut zones: Vec<_> = zones.iter().collect();
+        zones.sort_by_key(|(zone, _)| -zone.priority);
+        physics.override_gravity(settings.gravity, |pos| {
+            zones.iter()
+                .find(|(zone, transform)| zone.contains(transform, pos))
+                .map(|(zone, transform)| zone.gravity_at(transform, pos))
+        });
+    }
+}
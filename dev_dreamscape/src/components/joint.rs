@@ -0,0 +1,345 @@
+use crate::components::PhysicsBody;
+use crate::events::JointBroken;
+use crate::math::{to_point, Vec3f};
+use crate::physics_world::{PhysicsTimestep, PhysicsWorld};
+use bevy_ecs::prelude::*;
+use rapier3d::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+pub enum JointKind {
+    // Keeps the bodies' relative pose
+    Fixed,
+    // Rotation about the axis only, e.g. a hinge or a pendulum
+    Revolute { axis: Vec3f },
+    // Sliding along the axis only
+    Prismatic { axis: Vec3f },
+    // Free rotation about the anchors, like a ball and socket
+    Spherical,
+    // Keeps the anchors at most max_length apart
+    Rope { max_length: f32 },
+    // Pulls the anchors towards rest_length apart
+    Spring { rest_length: f32, stiffness: f32, damping: f32 },
+}
+
+// Drives the joint's free axis: the angle of revolute joints and the distance of the others
+#[derive(Clone, Copy, Debug)]
+pub enum JointMotor {
+    Velocity { target: f32, factor: f32 },
+    Position { target: f32, stiffness: f32, damping: f32 },
+}
+
+// Connects two PhysicsBody entities. Joints usually get an entity of their own, so that a
+// body can have several. Changes to the component apply to the simulation, and removing it
+// removes the joint.
+#[derive(Component, Clone, Debug)]
+pub struct Joint {
+    pub body1: Entity,
+    pub body2: Entity,
+    pub kind: JointKind,
+    // Where the joint attaches to each body, in the body's space
+    pub anchor1: Vec3f,
+    pub anchor2: Vec3f,
+    // Range of the free axis as for the motor. Ropes ignore it and use max_length.
+    pub limits: Option<[f32; 2]>,
+    pub motor: Option<JointMotor>,
+    // The joint breaks when it has to pull harder than this, sending JointBroken
+    pub break_force: Option<f32>,
+    // Whether the connected bodies still collide with each other
+    pub contacts_enabled: bool,
+}
+
+impl Joint {
+    pub fn new(body1: Entity, body2: Entity, kind: JointKind) -> Self {
+        Self {
+            body1,
+            body2,
+            kind,
+            anchor1: Vec3f::zeros(),
+            anchor2: Vec3f::zeros(),
+            limits: None,
+            motor: None,
+            break_force: None,
+            contacts_enabled: true,
+        }
+    }
+
+    fn free_axis(&self) -> Option<JointAxis> {
+        match self.kind {
+            JointKind::Fixed | JointKind::Spherical => None,
+            JointKind::Revolute { .. } => Some(JointAxis::AngX),
+            JointKind::Prismatic { .. } | JointKind::Rope { .. } | JointKind::Spring { .. } => Some(JointAxis::X),
+        }
+    }
+
+    fn generic_joint(&self) -> GenericJoint {
+        let mut joint: GenericJoint = match self.kind {
+            JointKind::Fixed => FixedJointBuilder::new().build().into(),
+            JointKind::Revolute { axis } => RevoluteJointBuilder::new(UnitVector::new_normalize(axis)).build().into(),
+            JointKind::Prismatic { axis } => PrismaticJointBuilder::new(UnitVector::new_normalize(axis)).build().into(),
+            JointKind::Spherical => SphericalJointBuilder::new().build().into(),
+            JointKind::Rope { max_length } => RopeJointBuilder::new().limits([0.0, max_length]).build().into(),
+            // A rope without a limit, whose length is driven by a spring
+            JointKind::Spring { rest_length, stiffness, damping } => RopeJointBuilder::new()
+                .motor_model(MotorModel::ForceBased)
+                .motor_position(rest_length, stiffness, damping)
+                .build()
+                .into(),
+        };
+        joint
+            .set_local_anchor1(to_point(self.anchor1))
+            .set_local_anchor2(to_point(self.anchor2))
+            .set_contacts_enabled(self.contacts_enabled);
+
+        match (self.free_axis(), self.limits, self.motor) {
+            (None, Some(_), _) | (None, _, Some(_)) => {
+                log::warn!("{:?} joints have no limits or motors", self.kind);
+            }
+            (Some(axis), limits, motor) => {
+                if let (Some(limits), false) = (limits, matches!(self.kind, JointKind::Rope { .. })) {
+                    joint.set_limits(axis, limits);
+                }
+                match motor {
+                    Some(JointMotor::Velocity { target, factor }) => {
+                        joint.set_motor_velocity(axis, target, factor);
+                    }
+                    Some(JointMotor::Position { target, stiffness, damping }) => {
+                        joint.set_motor_position(axis, target, stiffness, damping);
+                    }
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+        joint
+    }
+
+    // Creates the new joints once both bodies exist, and updates the changed ones. Joints
+    // whose bodies changed are removed and created again between the new bodies.
+    pub fn sync(
+        joints: Query<(Entity, Ref<Joint>)>,
+        bodies: Query<&PhysicsBody>,
+        mut physics: ResMut<PhysicsWorld>,
+    ) {
+        for (entity, joint) in joints.iter() {
+            let handles = (
+                bodies.get(joint.body1).ok().map(PhysicsBody::body_handle),
+                bodies.get(joint.body2).ok().map(PhysicsBody::body_handle),
+            );
+            if let Some(existing) = physics.joint_mut(entity) {
+                if !joint.is_changed() {
+                    continue;
+                }
+                if handles == (Some(existing.body1), Some(existing.body2)) {
+                    existing.data = joint.generic_joint();
+                    // Changing the joint doesn't wake the bodies, e.g. for a new motor target
+                    let (body1, body2) = (existing.body1, existing.body2);
+                    for handle in [body1, body2] {
+                        if let Some(body) = physics.bodies.get_mut(handle) {
+                            body.wake_up(true);
+                        }
+                    }
+                    continue;
+                }
+                physics.remove_joint(entity);
+            }
+            let (Some(body1), Some(body2)) = handles else {
+                if joint.is_changed() {
+                    log::warn!("Joint {:?} waiting for its physics bodies", entity);
+                }
+                continue;
+            };
+            physics.add_joint(entity, body1, body2, joint.generic_joint());
+        }
+    }
+
+    pub fn remove_deleted(mut removed: RemovedComponents<Joint>, mut physics: ResMut<PhysicsWorld>) {
+        for entity in removed.iter() {
+            physics.remove_joint(entity);
+        }
+    }
+
+    // Removes the joints that pulled harder than their break force in the last step
+    pub fn break_overloaded(
+        joints: Query<(Entity, &Joint)>,
+        timestep: Res<PhysicsTimestep>,
+        physics: Res<PhysicsWorld>,
+        mut commands: Commands,
+        mut broken_events: EventWriter<JointBroken>,
+    ) {
+        for (entity, joint) in joints.iter() {
+            let Some(break_force) = joint.break_force else {
+                continue;
+            };
+            let Some(force) = physics.joint_force(entity, timestep.dt()) else {
+                continue;
+            };
+            if force > break_force {
+                commands.entity(entity).remove::<Joint>();
+                broken_events.send(JointBroken {
+                    joint: entity,
+                    body1: joint.body1,
+                    body2: joint.body2,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{ColliderShape, PhysicsBodyParams, PhysicsMaterial};
+    use crate::frame_time::FrameTime;
+    use crate::physics_world::PhysicsSettings;
+    use crate::systems::update_physics;
+
+    // The joint systems around the physics step
+    fn world_and_schedule() -> (World, Schedule) {
+        let mut world = World::new();
+        world.insert_resource(PhysicsWorld::new());
+        world.insert_resource(PhysicsTimestep::default());
+        world.insert_resource(PhysicsSettings::default());
+        world.insert_resource(FrameTime::new());
+        world.init_resource::<Events<JointBroken>>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems((
+            (Joint::sync, Joint::remove_deleted).before(update_physics),
+            update_physics,
+            Joint::break_overloaded.after(update_physics),
+        ));
+        (world, schedule)
+    }
+
+    // Runs frames of one physics step each
+    fn run_frames(world: &mut World, schedule: &mut Schedule, frames: u32) {
+        for _ in 0..frames {
+            let dt = 1.0 / world.resource::<PhysicsTimestep>().rate;
+            world.resource_mut::<FrameTime>().update(Some(std::time::Duration::from_secs_f32(dt * 1.01)));
+            schedule.run(world);
+        }
+    }
+
+    fn spawn_body(world: &mut World, pos: Vec3f, movable: bool, density: f32) -> (Entity, RigidBodyHandle) {
+        let body = PhysicsBody::new(PhysicsBodyParams {
+            pos,
+            scale: Vec3f::from_element(1.0),
+            rotation_angle: 0.0,
+            rotation_axis: Vec3f::y(),
+            movable,
+            shape: ColliderShape::Ball { radius: 0.5 },
+            material: PhysicsMaterial { density, ..Default::default() },
+            gravity_scale: None,
+            lin_vel: None,
+            ang_vel: None,
+            contact_force_threshold: None,
+            ccd: None,
+        }, &mut world.resource_mut::<PhysicsWorld>());
+        let handle = body.body_handle();
+        (world.spawn(body).id(), handle)
+    }
+
+    fn ball(world: &mut World, pos: Vec3f) -> (Entity, RigidBodyHandle) {
+        spawn_body(world, pos, true, 1.0)
+    }
+
+    fn anchor(world: &mut World, pos: Vec3f) -> (Entity, RigidBodyHandle) {
+        spawn_body(world, pos, false, 1.0)
+    }
+
+    #[test]
+    fn changing_the_bodies_moves_the_joint() {
+        let (mut world, mut schedule) = world_and_schedule();
+        let (a, handle_a) = ball(&mut world, Vec3f::zeros());
+        let (b, handle_b) = ball(&mut world, Vec3f::zeros());
+        let (c, handle_c) = ball(&mut world, Vec3f::zeros());
+        let joint = world.spawn(Joint::new(a, b, JointKind::Spherical)).id();
+        schedule.run(&mut world);
+        let existing = world.resource_mut::<PhysicsWorld>().joint_mut(joint).map(|j| (j.body1, j.body2));
+        assert_eq!(existing, Some((handle_a, handle_b)));
+
+        world.get_mut::<Joint>(joint).unwrap().body2 = c;
+        schedule.run(&mut world);
+        let existing = world.resource_mut::<PhysicsWorld>().joint_mut(joint).map(|j| (j.body1, j.body2));
+        assert_eq!(existing, Some((handle_a, handle_c)));
+
+        // Waits for a body that has no PhysicsBody yet
+        let d = world.spawn_empty().id();
+        world.get_mut::<Joint>(joint).unwrap().body1 = d;
+        schedule.run(&mut world);
+        assert!(world.resource_mut::<PhysicsWorld>().joint_mut(joint).is_none());
+    }
+
+    #[test]
+    fn overloaded_joint_breaks() {
+        let (mut world, mut schedule) = world_and_schedule();
+        let (ceiling, _) = anchor(&mut world, Vec3f::new(0.0, 10.0, 0.0));
+        // Weighs about 5000 N
+        let (weight, _) = spawn_body(&mut world, Vec3f::new(0.0, 8.0, 0.0), true, 1000.0);
+        let joint = world.spawn(Joint {
+            anchor1: Vec3f::new(0.0, -2.0, 0.0),
+            break_force: Some(100.0),
+            ..Joint::new(ceiling, weight, JointKind::Spherical)
+        }).id();
+        run_frames(&mut world, &mut schedule, 5);
+
+        assert!(world.get::<Joint>(joint).is_none());
+        assert!(world.resource_mut::<PhysicsWorld>().joint_mut(joint).is_none());
+        let broken: Vec<JointBroken> = world.resource_mut::<Events<JointBroken>>().drain().collect();
+        assert_eq!(broken.len(), 1);
+        assert_eq!((broken[0].joint, broken[0].body1, broken[0].body2), (joint, ceiling, weight));
+    }
+
+    #[test]
+    fn joint_below_its_break_force_holds() {
+        let (mut world, mut schedule) = world_and_schedule();
+        let (ceiling, _) = anchor(&mut world, Vec3f::new(0.0, 10.0, 0.0));
+        // Weighs about 5 N
+        let (weight, _) = ball(&mut world, Vec3f::new(0.0, 8.0, 0.0));
+        let joint = world.spawn(Joint {
+            anchor1: Vec3f::new(0.0, -2.0, 0.0),
+            break_force: Some(100.0),
+            ..Joint::new(ceiling, weight, JointKind::Spherical)
+        }).id();
+        run_frames(&mut world, &mut schedule, 30);
+        assert!(world.get::<Joint>(joint).is_some());
+        assert!(world.resource::<Events<JointBroken>>().is_empty());
+    }
+
+    #[test]
+    fn limits_stop_the_free_axis() {
+        let (mut world, mut schedule) = world_and_schedule();
+        let (ceiling, _) = anchor(&mut world, Vec3f::new(0.0, 10.0, 0.0));
+        let (weight, handle) = ball(&mut world, Vec3f::new(0.0, 10.0, 0.0));
+        // Slides down from the anchor, by at most 1
+        world.spawn(Joint {
+            limits: Some([-1.0, 0.0]),
+            contacts_enabled: false,
+            ..Joint::new(ceiling, weight, JointKind::Prismatic { axis: Vec3f::y() })
+        });
+        run_frames(&mut world, &mut schedule, 60);
+        let y = world.resource::<PhysicsWorld>().bodies[handle].translation().y;
+        assert!((y - 9.0).abs() < 0.05, "{}", y);
+    }
+
+    #[test]
+    fn motor_target_change_wakes_the_bodies() {
+        let (mut world, mut schedule) = world_and_schedule();
+        world.resource_mut::<PhysicsSettings>().gravity = Vec3f::zeros();
+        let (hub, _) = anchor(&mut world, Vec3f::zeros());
+        let (wheel, handle) = ball(&mut world, Vec3f::zeros());
+        let joint = world.spawn(Joint {
+            motor: Some(JointMotor::Velocity { target: 0.0, factor: 100.0 }),
+            contacts_enabled: false,
+            ..Joint::new(hub, wheel, JointKind::Revolute { axis: Vec3f::z() })
+        }).id();
+        // Resting bodies fall asleep after a while
+        run_frames(&mut world, &mut schedule, 240);
+        assert!(world.resource::<PhysicsWorld>().bodies[handle].is_sleeping());
+
+        world.get_mut::<Joint>(joint).unwrap().motor = Some(JointMotor::Velocity { target: 2.0, factor: 100.0 });
+        run_frames(&mut world, &mut schedule, 30);
+        let body = &world.resource::<PhysicsWorld>().bodies[handle];
+        assert!(!body.is_sleeping());
+        assert!((body.angvel().z - 2.0).abs() < 0.1, "{}", body.angvel());
+    }
+}
@@ -1,27 +1,43 @@
 mod camera;
+mod collider_shape;
 mod floor_box;
 mod free_box;
+mod joint;
+mod gravity_zone;
 mod rock;
 mod light;
 mod model_spec;
 mod physics_body;
+mod physics_material;
 mod player;
+mod projection;
+mod reference_scene;
+mod shader_material;
 mod player_hands;
 mod skybox;
 //mod player_target;
 mod transform;
+mod trigger_volume;
 //mod grab;
 
-pub use camera::Camera;
+pub use camera::{Camera, RenderTarget};
+pub use collider_shape::ColliderShape;
 pub use floor_box::FloorBox;
 pub use free_box::FreeBox;
+pub use joint::{Joint, JointKind, JointMotor};
+pub use gravity_zone::{GravityZone, ZoneGravity, ZoneShape};
 pub use rock::Rock;
 pub use light::Light;
 pub use model_spec::ModelSpec;
 pub use physics_body::{PhysicsBody, PhysicsBodyParams};
+pub use physics_material::PhysicsMaterial;
 pub use player::Player;
+pub use projection::{Frustum, Projection};
+pub use reference_scene::ReferenceScene;
+pub use shader_material::{ShaderMaterial, ShaderParam};
 pub use player_hands::PlayerHands;
 pub use transform::Transform;
+pub use trigger_volume::TriggerVolume;
 pub use skybox::Skybox;
 
 //pub use player_target::PlayerTarget;
@@ -3,13 +3,23 @@ use bevy_ecs::prelude::*;
 
 #[derive(Component)]
 pub struct ModelSpec {
-    pub modelname: String
+    pub modelname: String,
+    // Replaces the model's diffuse texture with one from the asset texture store,
+    // e.g. a camera's render target
+    pub diffuse_texture: Option<String>,
 }
 
 impl ModelSpec {
     pub fn new(modelname: String) -> ModelSpec {
         Self {
-            modelname
+            modelname,
+            diffuse_texture: None,
         }
     }
+
+    #[allow(dead_code)]
+    pub fn with_diffuse_texture(mut self, texture_name: String) -> ModelSpec {
+        self.diffuse_texture = Some(texture_name);
+        self
+    }
 }
@@ -1,14 +1,17 @@
 use crate::components::Transform;
-use crate::physics_world::PhysicsWorld;
+use crate::physics_world::{PhysicsTimestep, PhysicsWorld, WorldBounds};
 use bevy_ecs::prelude::*;
 use rapier3d::prelude::*;
-use crate::assets::{CollisionModel};
+use crate::components::{ColliderShape, Joint, PhysicsMaterial};
 //use crate::components::grab::Grab;
-use crate::math::{Vec3f, to_point};
+use crate::math::Vec3f;
 
 #[derive(Component)]
 pub struct PhysicsBody {
     handle: RigidBodyHandle,
+    collider: ColliderHandle,
+    // Pose before the last physics step, to interpolate the rendered transform from
+    previous_pose: Isometry<Real>,
     //movable: bool
 }
 
@@ -18,11 +21,15 @@ pub struct PhysicsBodyParams<'a> {
     pub rotation_angle: f32,
     pub rotation_axis: Vec3f,
     pub movable: bool,
-    pub collision_model:Option<&'a CollisionModel>,
-    pub collision_ball:Option<f32>,
+    pub shape: ColliderShape<'a>,
+    pub material: PhysicsMaterial,
     pub gravity_scale:Option<f32>, 
     pub lin_vel:Option<Vec3f>, 
     pub ang_vel:Option<Vec3f>, 
+    // Sends ContactForce events when contacts push harder than this
+    pub contact_force_threshold: Option<f32>,
+    // Continuous collision detection for this body. None follows PhysicsSettings::ccd.
+    pub ccd: Option<bool>,
 }
 
 impl PhysicsBody {
@@ -33,11 +40,13 @@ impl PhysicsBody {
             rotation_axis,
             rotation_angle,
             movable,
-            collision_model,
-            collision_ball,
+            shape,
+            material,
             gravity_scale,
             lin_vel,
             ang_vel,
+            contact_force_threshold,
+            ccd,
         } = params;
 
 
@@ -47,75 +56,95 @@ impl PhysicsBody {
             .gravity_scale(gravity_scale.unwrap_or(1.0))
             .linvel(lin_vel.unwrap_or(Vec3f::new(0.0,0.0,0.0)))
             .angvel(ang_vel.unwrap_or(Vec3f::new(0.0,0.0,0.0)))
+            .ccd_enabled(ccd.unwrap_or(false))
             .build();
+        let previous_pose = *body.position();
 
 
-        match (collision_model,collision_ball) {
-            (Some(cm),_) => {
-
-                let scaled_points = cm.get_all_vertices_points().
-                        into_iter().map(|v| {
-                            to_point(Vec3f::new(v.x * scale.x , v.y * scale.y,  v.z * scale.z))                                       
-                        }).collect::<Vec<_>>();
+        let collider = material.apply_to(ColliderBuilder::new(shape.shared_shape(scale)))
+            .active_events(active_events(contact_force_threshold))
+            .contact_force_event_threshold(contact_force_threshold.unwrap_or(Real::MAX))
+            .build();
+        let (handle, collider) = physics.add_body(body, collider);
+        if ccd.is_some() {
+            physics.keep_ccd(handle);
+        }
 
+        Self {
+            handle,
+            collider,
+            previous_pose,
+            //movable
+        }
+    }
 
-                // TODO Other shapes
-                let collider = ColliderBuilder::trimesh(
-                            scaled_points,
-                            cm.get_all_triangle_indices(),
-                    )
-                    .restitution(0.2)
-                    .friction(0.1)
-                    //.translation(vector![pos.x, pos.y, pos.z])
-                    //.rotation(rotation_axis * rotation_angle)
-                    .build();
-                let (handle, _) = physics.add_body(body, collider);
+    pub fn body_handle(&self) -> RigidBodyHandle {
+        self.handle
+    }
 
-                return Self {
-                    handle,
-                    //movable
-                }
-            }
-            (None,Some(ball_radius)) => {
-
-                let collider = ColliderBuilder::ball(ball_radius)
-                    .mass(0.05)
-                    .restitution(0.8)
-                    .friction(0.1)
-                    .build();
-                let (handle, _) = physics.add_body(body, collider);
-
-                return Self {
-                    handle,
-                    //movable
-                }
+    pub fn collider(&self) -> ColliderHandle {
+        self.collider
+    }
 
+    // Maps the new bodies and their colliders to their entities, for the collision events
+    // and for removal
+    pub fn register_entities(q: Query<(Entity, &PhysicsBody), Added<PhysicsBody>>, mut physics: ResMut<PhysicsWorld>) {
+        for (entity, body) in q.iter() {
+            physics.set_body_entity(body.handle, entity);
+            physics.set_collider_entity(body.collider, entity);
+        }
+    }
 
-            }
-            _ => {
-                // TODO Other shapes
-                let collider = ColliderBuilder::cuboid(scale.x, scale.y, scale.z)
-                    .restitution(0.2)
-                    .friction(0.7)
-                    .build();
-                let (handle, _) = physics.add_body(body, collider);
-
-                return Self {
-                    handle,
-                    //movable
+    // Removes the bodies of despawned entities and of removed components from the simulation.
+    // Joints attached to them go too, along with their Joint components.
+    pub fn remove_deleted(
+        mut removed: RemovedComponents<PhysicsBody>,
+        mut physics: ResMut<PhysicsWorld>,
+        mut commands: Commands,
+    ) {
+        for entity in removed.iter() {
+            for joint in physics.remove_body(entity) {
+                if let Some(mut joint) = commands.get_entity(joint) {
+                    joint.remove::<Joint>();
                 }
             }
-        };
+        }
+    }
 
+    pub fn despawn_out_of_bounds(
+        q: Query<(Entity, &PhysicsBody)>,
+        bounds: Option<Res<WorldBounds>>,
+        physics: Res<PhysicsWorld>,
+        mut commands: Commands,
+    ) {
+        let Some(bounds) = bounds else {
+            return;
+        };
+        for (entity, body) in q.iter() {
+            let body = physics.bodies.get(body.handle).unwrap();
+            if body.is_dynamic() && !bounds.contains(body.translation()) {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
 
+    pub fn save_previous_pose(&mut self, physics: &PhysicsWorld) {
+        self.previous_pose = *physics.bodies.get(self.handle).unwrap().position();
     }
 
-    pub fn sync(mut q: Query<(&mut Transform, &PhysicsBody)>, physics: Res<PhysicsWorld>) {
+    // Sets the transforms in between the last two physics steps, by how far the frame is
+    // past the last one
+    pub fn sync(
+        mut q: Query<(&mut Transform, &PhysicsBody)>,
+        physics: Res<PhysicsWorld>,
+        timestep: Res<PhysicsTimestep>,
+    ) {
+        let alpha = timestep.alpha();
         for (mut transform, body) in q.iter_mut() {
-            let body = physics.bodies.get(body.handle).unwrap();
-            let phys_pos = body.translation();
-            let phys_rot = body.rotation().inverse(); // Not sure why inverse is needed
-            transform.set(*phys_pos, *phys_rot.quaternion());
+            let pose = body.previous_pose.lerp_slerp(physics.bodies.get(body.handle).unwrap().position(), alpha);
+            let phys_pos = pose.translation.vector;
+            let phys_rot = pose.rotation.inverse(); // Not sure why inverse is needed
+            transform.set(phys_pos, *phys_rot.quaternion());
         }
     }
 
@@ -160,6 +189,14 @@ impl PhysicsBody {
 
 }
 
+fn active_events(contact_force_threshold: Option<f32>) -> ActiveEvents {
+    if contact_force_threshold.is_some() {
+        ActiveEvents::COLLISION_EVENTS | ActiveEvents::CONTACT_FORCE_EVENTS
+    } else {
+        ActiveEvents::COLLISION_EVENTS
+    }
+}
+
 fn orig_type(movable: bool) -> RigidBodyType {
     if movable { RigidBodyType::Dynamic } else { RigidBodyType::Fixed }
 }
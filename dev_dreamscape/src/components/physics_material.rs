@@ -0,0 +1,151 @@
+use crate::components::PhysicsBody;
+use crate::physics_world::PhysicsWorld;
+use bevy_ecs::prelude::*;
+use rapier3d::prelude::*;
+
+// Surface and mass properties of a physics body. Bodies get theirs from PhysicsBodyParams,
+// usually the one declared for their collision asset (Assets::physics_material). Adding or
+// changing the component on an entity overrides it.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct PhysicsMaterial {
+    pub friction: f32,
+    // Bounciness, from 0 to 1
+    pub restitution: f32,
+    // Mass per unit volume, giving the body's mass from its shape
+    pub density: f32,
+    // How the coefficients of two touching colliders are combined. Where their rules
+    // differ, the one coming last in Average, Min, Multiply, Max is used.
+    pub friction_combine: CoefficientCombineRule,
+    pub restitution_combine: CoefficientCombineRule,
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> Self {
+        Self {
+            friction: 0.5,
+            restitution: 0.0,
+            density: 1.0,
+            friction_combine: CoefficientCombineRule::Average,
+            restitution_combine: CoefficientCombineRule::Average,
+        }
+    }
+}
+
+impl PhysicsMaterial {
+    pub fn apply_to(&self, collider: ColliderBuilder) -> ColliderBuilder {
+        collider
+            .friction(self.friction)
+            .restitution(self.restitution)
+            .density(self.density)
+            .friction_combine_rule(self.friction_combine)
+            .restitution_combine_rule(self.restitution_combine)
+    }
+
+    pub fn update(
+        q: Query<(&PhysicsBody, &PhysicsMaterial), Changed<PhysicsMaterial>>,
+        mut physics: ResMut<PhysicsWorld>,
+    ) {
+        for (body, material) in q.iter() {
+            if let Some(collider) = physics.colliders.get_mut(body.collider()) {
+                collider.set_friction(material.friction);
+                collider.set_restitution(material.restitution);
+                collider.set_density(material.density);
+                collider.set_friction_combine_rule(material.friction_combine);
+                collider.set_restitution_combine_rule(material.restitution_combine);
+            }
+        }
+    }
+
+    // Parses "<asset path> friction=0.5 restitution=0.1 density=1 friction_combine=max ..."
+    // lines. Missing values are the defaults, # starts a comment.
+    pub fn parse_declarations(text: &str) -> anyhow::Result<Vec<(String, PhysicsMaterial)>> {
+        let mut materials = vec![];
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            let mut words = line.split_whitespace();
+            let Some(path) = words.next() else {
+                continue;
+            };
+            let mut material = PhysicsMaterial::default();
+            for word in words {
+                let parsed = word.split_once('=').and_then(|(key, value)| {
+                    match key {
+                        "friction" => material.friction = value.parse().ok()?,
+                        "restitution" => material.restitution = value.parse().ok()?,
+                        "density" => material.density = value.parse().ok()?,
+                        "friction_combine" => material.friction_combine = parse_combine_rule(value)?,
+                        "restitution_combine" => material.restitution_combine = parse_combine_rule(value)?,
+                        _ => return None,
+                    }
+                    Some(())
+                });
+                if parsed.is_none() {
+                    anyhow::bail!("Line {}: can't parse {}", line_number + 1, word);
+                }
+            }
+            materials.push((path.to_string(), material));
+        }
+        Ok(materials)
+    }
+}
+
+fn parse_combine_rule(name: &str) -> Option<CoefficientCombineRule> {
+    match name {
+        "average" => Some(CoefficientCombineRule::Average),
+        "min" => Some(CoefficientCombineRule::Min),
+        "multiply" => Some(CoefficientCombineRule::Multiply),
+        "max" => Some(CoefficientCombineRule::Max),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_values_and_keeps_defaults() {
+        let materials = PhysicsMaterial::parse_declarations(
+            "Rock1/RedishRock.obj friction=0.9 density=2.5\nCrater_low-collision.obj restitution=0.25"
+        ).unwrap();
+        assert_eq!(materials, vec![
+            ("Rock1/RedishRock.obj".to_string(), PhysicsMaterial { friction: 0.9, density: 2.5, ..Default::default() }),
+            ("Crater_low-collision.obj".to_string(), PhysicsMaterial { restitution: 0.25, ..Default::default() }),
+        ]);
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let materials = PhysicsMaterial::parse_declarations(
+            "# Rocks\n\n   \nrock.obj friction=0.1 # slippery\n# other.obj friction=1"
+        ).unwrap();
+        assert_eq!(materials, vec![
+            ("rock.obj".to_string(), PhysicsMaterial { friction: 0.1, ..Default::default() }),
+        ]);
+    }
+
+    #[test]
+    fn parses_combine_rules() {
+        let materials = PhysicsMaterial::parse_declarations(
+            "a.obj friction_combine=max restitution_combine=min\nb.obj friction_combine=multiply restitution_combine=average"
+        ).unwrap();
+        assert_eq!(materials[0].1.friction_combine, CoefficientCombineRule::Max);
+        assert_eq!(materials[0].1.restitution_combine, CoefficientCombineRule::Min);
+        assert_eq!(materials[1].1.friction_combine, CoefficientCombineRule::Multiply);
+        assert_eq!(materials[1].1.restitution_combine, CoefficientCombineRule::Average);
+    }
+
+    #[test]
+    fn reports_the_line_of_bad_values() {
+        for (text, word) in [
+            ("a.obj friction=0.5\nb.obj friction=slippery", "friction=slippery"),
+            ("a.obj\nb.obj\nc.obj bounciness=1", "bounciness=1"),
+            ("a.obj\nb.obj\n\nd.obj friction_combine=sum", "friction_combine=sum"),
+            ("a.obj density", "density"),
+        ] {
+            let error = PhysicsMaterial::parse_declarations(text).unwrap_err().to_string();
+            let line = text.lines().count();
+            assert_eq!(error, format!("Line {}: can't parse {}", line, word));
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use std::f32::consts::PI;
 use crate::components::camera::Camera;
 use crate::components::Transform;
-use crate::device::{Device, SurfaceSize};
-use crate::events::{WindowResizeEvent, CameraSetEvent};
+use crate::device::Device;
+use crate::events::CameraSetEvent;
 use crate::input::Input;
 use crate::math::Vec3f;
 use crate::physics_world::PhysicsWorld;
@@ -50,7 +50,7 @@ impl Player {
             .build();
         let collider_handle = physics.colliders.insert(collider);
 
-        commands.spawn((
+        let entity = commands.spawn((
             Player {
                 collider_handle,
                 /*
@@ -63,7 +63,9 @@ impl Player {
             },
             camera,
             transform
-        ));
+        )).id();
+        // So trigger volumes can report the player
+        physics.set_collider_entity(collider_handle, entity);
     }
 
     /*
@@ -90,19 +92,12 @@ impl Player {
 
     pub fn update(
         frame_time: Res<FrameTime>,
-        device: Res<Device>,
         input: Res<Input>,
-        mut player: Query<(&mut Self, &mut Camera, &mut Transform)>,
+        mut player: Query<(&mut Self, &mut Transform)>,
         mut physics: ResMut<PhysicsWorld>,
-        mut resize_events: EventReader<WindowResizeEvent>,
     ) {
-        let (mut player, mut camera, mut transform) = player.single_mut();
-
-        // Update camera aspect
-        let last_resize = resize_events.iter().last();
-        if let Some(last_resize) = last_resize {
-            update_cam_aspect(&mut camera, last_resize.new_size, &device);
-        }
+        // The camera's aspect follows the window in Camera::update_aspect
+        let (mut player, mut transform) = player.single_mut();
 
         // Move and rotate
         let dt = frame_time.delta;
@@ -213,17 +208,3 @@ fn update_target(player: (&mut Player, &Transform), physics: &PhysicsWorld) {
     }
 }
 */
-
-fn update_cam_aspect(camera: &mut Camera, new_surface_size: SurfaceSize, _device: &Device) {
-    //camera.Projection.set_aspect(new_surface_size.width as f32 / new_surface_size.height as f32);
-    camera.resize(new_surface_size.width as u32 , new_surface_size.height as u32);
-    //TODO handle camera resize!
-    /*
-    if let Some(target) = camera.target_mut() {
-        target.resize(
-            (new_surface_size.width, new_surface_size.height),
-            device,
-        )
-    }
-    */
-}
@@ -0,0 +1,203 @@
+use rapier3d::na;
+use crate::math::{Mat4f, Vec3f, Vec4f};
+
+// Camera projection. All of them use nalgebra's (OpenGL style) clip space.
+#[derive(Debug, Clone)]
+pub enum Projection {
+    Perspective(na::Perspective3<f32>),
+    Orthographic(na::Orthographic3<f32>),
+    // Provided directly, e.g. by WebXR, where the matrix may not decompose into the other
+    // kinds (https://github.com/immersive-web/webxr/issues/461). It isn't changed on resize.
+    Matrix(Mat4f),
+}
+
+impl Projection {
+    // Orthographic projection showing `height` world units vertically, centered on the view direction
+    pub fn orthographic(aspect: f32, height: f32, znear: f32, zfar: f32) -> Self {
+        let (half_width, half_height) = (height * aspect / 2.0, height / 2.0);
+        Projection::Orthographic(na::Orthographic3::new(
+            -half_width, half_width, -half_height, half_height, znear, zfar,
+        ))
+    }
+
+    pub fn matrix(&self) -> Mat4f {
+        match self {
+            Projection::Perspective(perspective) => *perspective.as_matrix(),
+            Projection::Orthographic(orthographic) => *orthographic.as_matrix(),
+            Projection::Matrix(matrix) => *matrix,
+        }
+    }
+
+    // The closed form inverses are faster than a general matrix inverse
+    pub fn inverse(&self) -> Mat4f {
+        match self {
+            Projection::Perspective(perspective) => perspective.inverse(),
+            Projection::Orthographic(orthographic) => orthographic.inverse(),
+            Projection::Matrix(matrix) => matrix.try_inverse()
+                .expect("Projection matrix isn't invertible"),
+        }
+    }
+
+    // None for a raw matrix, which has no aspect ratio to change
+    pub fn aspect(&self) -> Option<f32> {
+        match self {
+            Projection::Perspective(perspective) => Some(perspective.aspect()),
+            Projection::Orthographic(orthographic) =>
+                Some((orthographic.right() - orthographic.left()) / (orthographic.top() - orthographic.bottom())),
+            Projection::Matrix(_) => None,
+        }
+    }
+
+    // Orthographic projections keep their height and center and change their width
+    pub fn set_aspect(&mut self, aspect: f32) {
+        match self {
+            Projection::Perspective(perspective) => perspective.set_aspect(aspect),
+            Projection::Orthographic(orthographic) => {
+                let center = (orthographic.left() + orthographic.right()) / 2.0;
+                let half_width = (orthographic.top() - orthographic.bottom()) * aspect / 2.0;
+                orthographic.set_left_and_right(center - half_width, center + half_width);
+            }
+            Projection::Matrix(_) => {}
+        }
+    }
+
+    pub fn znear(&self) -> f32 {
+        match self {
+            Projection::Perspective(perspective) => perspective.znear(),
+            Projection::Orthographic(orthographic) => orthographic.znear(),
+            Projection::Matrix(_) => self.view_depth(-1.0),
+        }
+    }
+
+    pub fn zfar(&self) -> f32 {
+        match self {
+            Projection::Perspective(perspective) => perspective.zfar(),
+            Projection::Orthographic(orthographic) => orthographic.zfar(),
+            Projection::Matrix(_) => self.view_depth(1.0),
+        }
+    }
+
+    // Distance in front of the camera of the centre of the view at the given clip space depth
+    fn view_depth(&self, ndc_z: f32) -> f32 {
+        let point = self.inverse() * Vec4f::new(0.0, 0.0, ndc_z, 1.0);
+        -point.z / point.w
+    }
+}
+
+// View volume as six planes (left, right, bottom, top, near, far) facing inwards,
+// each stored as (normal, distance) so that inside points have normal.dot(p) + distance >= 0
+#[derive(Debug, Clone)]
+pub struct Frustum {
+    planes: [Vec4f; 6],
+}
+
+impl Frustum {
+    // Extracts the planes from a view projection matrix (Gribb & Hartmann), so it works for
+    // any kind of projection. Uses wgpu's 0..1 depth range, which is what gets rendered.
+    pub fn from_view_proj(view_proj: &Mat4f) -> Self {
+        let row = |i: usize| view_proj.row(i).transpose();
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ].map(|plane| plane / plane.xyz().norm());
+        Self { planes }
+    }
+
+    pub fn intersects_sphere(&self, center: &Vec3f, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.xyz().dot(center) + plane.w >= -radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn inverse_inverts_the_matrix() {
+        let projections = [
+            Projection::Perspective(na::Perspective3::new(1.5, 1.0, 0.1, 100.0)),
+            Projection::Orthographic(na::Orthographic3::new(-1.0, 3.0, -2.0, 2.0, 0.5, 20.0)),
+            // Off-center, as WebXR gives them
+            Projection::Matrix(Mat4f::new_translation(&Vec3f::new(0.1, -0.2, 0.0)) * na::Perspective3::new(0.8, 1.2, 0.1, 100.0).as_matrix()),
+        ];
+        for projection in projections {
+            let product = projection.matrix() * projection.inverse();
+            assert!((product - Mat4f::identity()).abs().max() < 1e-4, "{:?}: {}", projection, product);
+        }
+    }
+
+    #[test]
+    fn orthographic_resize_keeps_height_and_center() {
+        let mut projection = Projection::Orthographic(na::Orthographic3::new(-1.0, 3.0, -2.0, 2.0, 0.5, 20.0));
+        assert_close(projection.aspect().unwrap(), 1.0);
+        projection.set_aspect(2.0);
+        assert_close(projection.aspect().unwrap(), 2.0);
+        let Projection::Orthographic(orthographic) = projection else { unreachable!() };
+        assert_close(orthographic.left(), -3.0);
+        assert_close(orthographic.right(), 5.0);
+        assert_close(orthographic.bottom(), -2.0);
+        assert_close(orthographic.top(), 2.0);
+    }
+
+    #[test]
+    fn raw_matrix_depth_range() {
+        let perspective = Projection::Matrix(*na::Perspective3::new(1.5, 1.0, 0.5, 50.0).as_matrix());
+        assert_close(perspective.znear(), 0.5);
+        assert_close(perspective.zfar(), 50.0);
+        let orthographic = Projection::Matrix(*na::Orthographic3::new(-1.0, 1.0, -1.0, 1.0, 2.0, 30.0).as_matrix());
+        assert_close(orthographic.znear(), 2.0);
+        assert_close(orthographic.zfar(), 30.0);
+        assert!(perspective.aspect().is_none());
+    }
+
+    #[test]
+    fn frustum_culls_spheres_outside_each_plane() {
+        let projection = Projection::Orthographic(na::Orthographic3::new(-1.0, 1.0, -2.0, 2.0, 1.0, 10.0));
+        let frustum = Frustum::from_view_proj(&projection.matrix());
+        // Where wgpu clips the near plane, at depth 0
+        let near = projection.view_depth(0.0);
+        let middle = (near + 10.0) / 2.0;
+        let radius = 0.5;
+        let outside = radius + 0.01;
+        let straddling = radius / 2.0;
+        for (center, distance) in [
+            (Vec3f::new(-1.0, 0.0, -middle), Vec3f::new(-1.0, 0.0, 0.0)),
+            (Vec3f::new(1.0, 0.0, -middle), Vec3f::new(1.0, 0.0, 0.0)),
+            (Vec3f::new(0.0, -2.0, -middle), Vec3f::new(0.0, -1.0, 0.0)),
+            (Vec3f::new(0.0, 2.0, -middle), Vec3f::new(0.0, 1.0, 0.0)),
+            (Vec3f::new(0.0, 0.0, -near), Vec3f::new(0.0, 0.0, 1.0)),
+            (Vec3f::new(0.0, 0.0, -10.0), Vec3f::new(0.0, 0.0, -1.0)),
+        ] {
+            assert!(!frustum.intersects_sphere(&(center + distance * outside), radius), "{}", center);
+            assert!(frustum.intersects_sphere(&(center + distance * straddling), radius), "{}", center);
+        }
+        assert!(frustum.intersects_sphere(&Vec3f::new(0.0, 0.0, -middle), radius));
+    }
+
+    #[test]
+    fn frustum_follows_the_perspective_sides() {
+        let (aspect, fovy) = (1.5, 1.0);
+        let view_proj = na::Perspective3::new(aspect, fovy, 0.1, 100.0).as_matrix()
+            * Mat4f::new_translation(&Vec3f::new(0.0, 0.0, -5.0));
+        let frustum = Frustum::from_view_proj(&view_proj);
+        // The left plane passes through the eye with the inward normal (cos h, 0, -sin h)
+        let half_fovx = (aspect * (fovy / 2.0).tan()).atan();
+        let depth = 20.0;
+        let radius = 1.0;
+        let at_distance = |distance: f32| Vec3f::new(
+            (distance - depth * half_fovx.sin()) / half_fovx.cos(), 0.0, -depth + 5.0,
+        );
+        assert!(!frustum.intersects_sphere(&at_distance(-radius - 0.01), radius));
+        assert!(frustum.intersects_sphere(&at_distance(-radius / 2.0), radius));
+        // Behind the camera
+        assert!(!frustum.intersects_sphere(&Vec3f::new(0.0, 0.0, 5.0 + radius + 0.01), radius));
+    }
+}
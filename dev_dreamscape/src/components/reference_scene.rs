@@ -0,0 +1,68 @@
+use crate::assets::{Assets, BUILTIN_CUBE, BUILTIN_SKY, BUILTIN_SPHERE};
+use crate::components::{ColliderShape, ModelSpec, PhysicsBody, PhysicsBodyParams, Skybox, Transform};
+use crate::math::{UnitQuatf, Vec3f};
+use crate::physics_world::PhysicsWorld;
+use bevy_ecs::prelude::*;
+
+// Fixed scene of builtin primitives in front of the player (who faces +z): a ground slab, a resting sphere
+// and a cube and ball that drop onto the ground in the first second. Needs no files from
+// res/, see App::load_reference_scene.
+#[derive(Component)]
+pub struct ReferenceScene;
+
+impl ReferenceScene {
+    pub fn spawn(mut commands: Commands, mut physics: ResMut<PhysicsWorld>, assets: Res<Assets>) {
+        commands.spawn(Skybox { texture_name: BUILTIN_SKY.to_string() });
+
+        // Top at y = 0
+        let ground = Self::new_components(
+            BUILTIN_CUBE, Vec3f::new(0.0, -0.25, 8.0), Vec3f::new(8.0, 0.25, 8.0), 0.0, false, &assets, &mut physics,
+        );
+        let sphere = Self::new_components(
+            BUILTIN_SPHERE, Vec3f::new(2.0, 1.0, 7.0), Vec3f::from_element(1.0), 0.0, false, &assets, &mut physics,
+        );
+        let cube = Self::new_components(
+            BUILTIN_CUBE, Vec3f::new(-2.0, 3.0, 8.0), Vec3f::from_element(0.75), 0.6, true, &assets, &mut physics,
+        );
+        let ball = Self::new_components(
+            BUILTIN_SPHERE, Vec3f::new(0.0, 4.0, 10.0), Vec3f::from_element(0.5), 0.0, true, &assets, &mut physics,
+        );
+        commands.spawn_batch([ground, sphere, cube, ball]);
+    }
+
+    fn new_components(
+        model: &str,
+        pos: Vec3f,
+        scale: Vec3f,
+        // About the y axis
+        rotation_angle: f32,
+        movable: bool,
+        assets: &Assets,
+        physics: &mut PhysicsWorld,
+    ) -> (ReferenceScene, PhysicsBody, Transform, ModelSpec) {
+        let shape = if model == BUILTIN_SPHERE {
+            ColliderShape::Ball { radius: 1.0 }
+        } else {
+            ColliderShape::Cuboid { half_extents: Vec3f::from_element(1.0) }
+        };
+        let physics_body = PhysicsBody::new(
+            PhysicsBodyParams {
+                pos,
+                scale,
+                rotation_axis: Vec3f::y(),
+                rotation_angle,
+                movable,
+                shape,
+                material: assets.physics_material(model),
+                gravity_scale: None,
+                lin_vel: None,
+                ang_vel: None,
+                contact_force_threshold: None,
+                ccd: None,
+            },
+            physics,
+        );
+        let rot = UnitQuatf::from_axis_angle(&Vec3f::y_axis(), rotation_angle);
+        (ReferenceScene, physics_body, Transform::new(pos, rot, scale), ModelSpec::new(model.to_string()))
+    }
+}
@@ -1,5 +1,5 @@
 use crate::components::transform::Transform;
-use crate::components::{PhysicsBody, PhysicsBodyParams};
+use crate::components::{ColliderShape, GravityZone, PhysicsBody, PhysicsBodyParams, ZoneShape};
 use crate::components::ModelSpec;
 use crate::math::{Vec3f,UnitQuatf,UnitVec3f};
 use crate::assets::Assets;
@@ -26,18 +26,20 @@ impl Rock {
 
         let prng = ChaCha20Core::from_entropy();
         let mut reseeding_rng = ReseedingRng::new(prng, 0, OsRng);
+        // The rocks float in a zero-g zone around the field, and fall once they drift out of it
+        commands.spawn((
+            GravityZone::zero_g(ZoneShape::Box { half_extents: Vec3f::new(55.0, 10.0, 55.0) }),
+            Transform::from_position(Vec3f::new(0.0, 12.5, 0.0)),
+        ));
+
         for _x in 0..1000 {
         
             let rock_choice = reseeding_rng.gen_range(0.0..1.0) > 0.25; 
+            // Collides as the convex decomposition of the render mesh
             let model_label = if rock_choice { 
-                        String::from("Rock1/RedishRock-collider.obj") 
-                    } else {
-                        String::from("Rock2/Rock2-collider.obj")
-                    };
-            let collision_model_label = if rock_choice {
-                        String::from("Rock1/RedishRock-collider.obj") 
+                        String::from("Rock1/RedishRock.obj") 
                     } else {
-                        String::from("Rock2/Rock2-collider.obj")
+                        String::from("Rock2/Rock2.obj")
                     };
 
 
@@ -77,18 +79,21 @@ impl Rock {
                 None
             };
 
-            commands.spawn(Self::new_component(
-                    model_label,
-                    collision_model_label,
-                    pos,
-                    scale,
-                    rotation_axis,
-                    rotation_angle,
-                    lv,
-                    av,
-                    &assets,
-                    &mut physics)
-            );
+            let params = PhysicsBodyParams {
+                pos,
+                scale,
+                rotation_axis,
+                rotation_angle,
+                movable: true,
+                shape: ColliderShape::ConvexParts(assets.convex_parts_store.get(&model_label).unwrap()),
+                material: assets.physics_material(&model_label),
+                gravity_scale: None,
+                lin_vel: lv,
+                ang_vel: av,
+                contact_force_threshold: None,
+                ccd: None,
+            };
+            commands.spawn(Self::new_component(model_label, params, &mut physics));
 
         } 
 
@@ -97,44 +102,17 @@ impl Rock {
 
     fn new_component(
         model_label: String,
-        _collision_model_label: String,
-        pos: Vec3f,
-        scale: Vec3f,
-        rotation_axis: Vec3f,
-        rotation_angle: f32,
-        lin_vel: Option<Vec3f>,
-        ang_vel: Option<Vec3f>,
-        _assets: &Assets,
+        params: PhysicsBodyParams,
         physics: &mut PhysicsWorld,
     ) -> (Rock, PhysicsBody, Transform, ModelSpec) {
 
         
-        let rot = UnitQuatf::from_axis_angle(&UnitVec3f::new_normalize(rotation_axis), 
-                                             rotation_angle);
-
-        let transform = Transform::new(pos, rot, scale);
-        
-        //println!("looking for collision_model {}", &collision_model_label);
+        let rot = UnitQuatf::from_axis_angle(&UnitVec3f::new_normalize(params.rotation_axis), 
+                                             params.rotation_angle);
 
-        //Collider circle works just fine
-        //let collision_model =  assets.collision_model_store.get(
-        //                            &collision_model_label).unwrap();
+        let transform = Transform::new(params.pos, rot, params.scale);
 
-        let physics_body = PhysicsBody::new(
-            PhysicsBodyParams {
-                pos,
-                scale,
-                rotation_axis,
-                rotation_angle,
-                movable: true,
-                collision_model: None,//Some(&collision_model),
-                collision_ball: Some(scale.norm()) , //None,
-                gravity_scale: Some(0.0),
-                lin_vel,
-                ang_vel
-            },
-            physics,
-        );
+        let physics_body = PhysicsBody::new(params, physics);
 
         let modelspec = ModelSpec::new(model_label);
         (Rock, physics_body, transform, modelspec)
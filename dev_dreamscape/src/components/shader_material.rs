@@ -0,0 +1,92 @@
+use bevy_ecs::prelude::*;
+use crate::math::{Vec2f, Vec3f, Vec4f};
+
+// Draws the entity's model with a custom WGSL shader instead of phong. The shader gets
+// the standard camera, globals (time) and per instance transform bindings, plus a uniform
+// with the params and one binding per texture slot. See MaterialPass for the details.
+#[derive(Component, Debug, Clone)]
+pub struct ShaderMaterial {
+    // Path of the WGSL file under res/, which needs to be in the asset shader store
+    pub shader: String,
+    // Available in the shader as material.<name>
+    pub params: Vec<(String, ShaderParam)>,
+    // (slot, texture) pairs. Each slot is a texture_2d<f32> named after it in the shader,
+    // bound to the named texture from the asset texture store.
+    pub textures: Vec<(String, String)>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShaderParam {
+    Float(f32),
+    Vec2(Vec2f),
+    Vec3(Vec3f),
+    Vec4(Vec4f),
+}
+
+impl ShaderParam {
+    pub fn wgsl_type(&self) -> &'static str {
+        match self {
+            ShaderParam::Float(_) => "f32",
+            ShaderParam::Vec2(_) => "vec2<f32>",
+            ShaderParam::Vec3(_) => "vec3<f32>",
+            ShaderParam::Vec4(_) => "vec4<f32>",
+        }
+    }
+
+    // Every param takes a 16 byte slot in the uniform, which keeps the layout simple
+    pub fn to_slot(self) -> [f32; 4] {
+        match self {
+            ShaderParam::Float(x) => [x, 0.0, 0.0, 0.0],
+            ShaderParam::Vec2(v) => [v.x, v.y, 0.0, 0.0],
+            ShaderParam::Vec3(v) => [v.x, v.y, v.z, 0.0],
+            ShaderParam::Vec4(v) => [v.x, v.y, v.z, v.w],
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl ShaderMaterial {
+    pub fn new(shader: &str) -> Self {
+        Self {
+            shader: shader.to_string(),
+            params: vec![],
+            textures: vec![],
+        }
+    }
+
+    pub fn with_param(mut self, name: &str, value: ShaderParam) -> Self {
+        self.set_param(name, value);
+        self
+    }
+
+    pub fn with_texture(mut self, slot: &str, texture_name: &str) -> Self {
+        self.textures.push((slot.to_string(), texture_name.to_string()));
+        self
+    }
+
+    // Changes the value of a param, e.g. to animate it, or adds it. Changing its type
+    // builds a new pipeline, so keep the type fixed when animating.
+    pub fn set_param(&mut self, name: &str, value: ShaderParam) {
+        match self.params.iter_mut().find(|(param_name, _)| param_name == name) {
+            Some((_, param)) => *param = value,
+            None => self.params.push((name.to_string(), value)),
+        }
+    }
+
+    // Identifies the pipeline: the shader and the names and types of its bindings
+    pub fn layout_key(&self) -> String {
+        let params = self.params.iter()
+            .map(|(name, param)| format!("{}:{}", name, param.wgsl_type()))
+            .collect::<Vec<_>>()
+            .join(",");
+        let slots = self.textures.iter()
+            .map(|(slot, _)| slot.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{}|{}|{}", self.shader, params, slots)
+    }
+
+    pub fn uniform_data(&self) -> Vec<[f32; 4]> {
+        self.params.iter().map(|(_, param)| param.to_slot()).collect()
+    }
+}
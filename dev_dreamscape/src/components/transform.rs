@@ -4,7 +4,7 @@ use rapier3d::na;
 use bevy_ecs::prelude::*;
 use crate::math::{Mat4, Mat4f, Vec3, Vec3f, Quatf, UnitQuat, UnitQuatf, UnitVec3f};
 
-#[derive(Component,Debug)]
+#[derive(Component,Debug,Clone)]
 pub struct Transform {
     // Individual components
     pos: Vec3f,
@@ -0,0 +1,60 @@
+use crate::components::{ColliderShape, Transform};
+use crate::physics_world::PhysicsWorld;
+use bevy_ecs::prelude::*;
+use rapier3d::prelude::*;
+
+// Invisible region that sends TriggerEnter and TriggerExit events when the player or a
+// physics body enters or leaves it. Follows its Transform when that changes. Removing the
+// component or despawning the entity removes the volume, which sends TriggerExit for what
+// was inside.
+#[derive(Component)]
+pub struct TriggerVolume {
+    collider: ColliderHandle,
+}
+
+impl TriggerVolume {
+    // The shape is scaled by the transform. Use a convex hull rather than a trimesh for
+    // collision models, as a trimesh only reports crossing its surface.
+    pub fn new(shape: ColliderShape, transform: &Transform, physics: &mut PhysicsWorld) -> Self {
+        let collider = ColliderBuilder::new(shape.shared_shape(transform.scale()))
+            .sensor(true)
+            .active_events(ActiveEvents::COLLISION_EVENTS)
+            // The player collider has no body, so it counts as fixed
+            .active_collision_types(ActiveCollisionTypes::all())
+            .position(pose(transform))
+            .build();
+
+        Self {
+            collider: physics.colliders.insert(collider),
+        }
+    }
+
+    // Maps the new triggers' colliders to their entities, for the trigger events
+    pub fn register_entities(q: Query<(Entity, &TriggerVolume), Added<TriggerVolume>>, mut physics: ResMut<PhysicsWorld>) {
+        for (entity, trigger) in q.iter() {
+            physics.set_trigger_entity(trigger.collider, entity);
+        }
+    }
+
+    // Removes the colliders of despawned entities and of removed components
+    pub fn remove_deleted(mut removed: RemovedComponents<TriggerVolume>, mut physics: ResMut<PhysicsWorld>) {
+        for entity in removed.iter() {
+            physics.remove_trigger(entity);
+        }
+    }
+
+    pub fn follow_transform(
+        q: Query<(&TriggerVolume, &Transform), Changed<Transform>>,
+        mut physics: ResMut<PhysicsWorld>,
+    ) {
+        for (trigger, transform) in q.iter() {
+            if let Some(collider) = physics.colliders.get_mut(trigger.collider) {
+                collider.set_position(pose(transform));
+            }
+        }
+    }
+}
+
+fn pose(transform: &Transform) -> Isometry<Real> {
+    Isometry::from_parts(transform.position().into(), transform.rotation())
+}
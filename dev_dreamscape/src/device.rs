@@ -7,7 +7,9 @@ pub type SurfaceSize = winit::dpi::PhysicalSize<u32>;
 
 #[derive(Resource)]
 pub struct Device {
-    surface: wgpu::Surface,
+    // None when rendering headless
+    surface: Option<wgpu::Surface>,
+    // Also holds the size and format of the offscreen target when headless
     surface_config: wgpu::SurfaceConfiguration,
     device: wgpu::Device,
     queue: wgpu::Queue,
@@ -15,10 +17,7 @@ pub struct Device {
 
 impl Device {
     pub async fn new(window: &winit::window::Window) -> Self {
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            dx12_shader_compiler: Default::default(),
-        });
+        let instance = new_instance();
 
         let surface = unsafe { instance.create_surface(&window) }.unwrap();
 
@@ -31,32 +30,7 @@ impl Device {
             .await
             .unwrap();
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: wgpu::Features::empty(),
-                    // WebGL doesn't support all of wgpu's features, so if
-                    // we're building for the web we'll have to disable some.
-                    limits: if cfg!(target_arch = "wasm32") {
-                        Limits {
-                            // Max texture dimension is limited to 2048 for downlevel, but
-                            // the WebXR frame buffer for stereo can be larger (at least on desktop in Chrome)
-                            // TODO: What about other types of texture?
-                            max_texture_dimension_2d: 4096,    
-                            ..wgpu::Limits::downlevel_webgl2_defaults()
-                        }
-                    } else {
-                        Limits {
-                            max_texture_dimension_2d: 4096,     
-                            ..wgpu::Limits::default()
-                        }
-                    },
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        let (device, queue) = request_device(&adapter, 4096).await;
 
         let surface_size = window.inner_size();
 
@@ -71,7 +45,8 @@ impl Device {
 
 
             wgpu::SurfaceConfiguration {
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                // Copying is needed for screenshots, but not supported everywhere
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | (caps.usages & wgpu::TextureUsages::COPY_SRC),
                 format,
                 width: surface_size.width,
                 height: surface_size.height,
@@ -84,17 +59,75 @@ impl Device {
 
         Self {
             surface_config,
-            surface,
+            surface: Some(surface),
             device,
             queue,
         }
     }
 
+    // Device without a window or surface, for rendering offscreen (see App::render_to_image).
+    // Set force_fallback_adapter to pick a software rasterizer such as lavapipe or WARP,
+    // e.g. on CI machines without a GPU.
+    pub async fn new_headless(width: u32, height: u32, force_fallback_adapter: bool) -> Self {
+        Self::try_new_headless(width, height, force_fallback_adapter).await
+            .expect("No suitable adapter found for headless rendering")
+    }
+
+    // Largest texture the headless device would support, or None without a suitable adapter.
+    // The renderer creates textures of the surface size right away, so check sizes with this first.
+    pub async fn headless_max_texture_size(force_fallback_adapter: bool) -> Option<u32> {
+        let adapter = new_instance()
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            })
+            .await?;
+        Some(adapter.limits().max_texture_dimension_2d)
+    }
+
+    // None when there's no suitable adapter, e.g. no software rasterizer installed
+    pub async fn try_new_headless(width: u32, height: u32, force_fallback_adapter: bool) -> Option<Self> {
+        let instance = new_instance();
+
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            })
+            .await?;
+
+        // Offscreen captures can be larger than any window (e.g. supersampled 4k frames)
+        let max_texture_dimension_2d = adapter.limits().max_texture_dimension_2d;
+        let (device, queue) = request_device(&adapter, max_texture_dimension_2d).await;
+
+        // Never used to configure a surface, only to report the target size and format
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+
+        Some(Self {
+            surface_config,
+            surface: None,
+            device,
+            queue,
+        })
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
         if width > 0 && height > 0 {
             self.surface_config.width = width;
             self.surface_config.height = height;
-            self.surface.configure(&self.device, &self.surface_config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.surface_config);
+            }
         }
     }
 
@@ -111,7 +144,11 @@ impl Device {
     }
 
     pub fn surface(&self) -> &wgpu::Surface {
-        &self.surface
+        self.surface.as_ref().expect("Headless device has no surface")
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.surface.is_none()
     }
 }
 
@@ -122,3 +159,41 @@ impl Deref for Device {
         &self.device
     }
 }
+
+fn new_instance() -> wgpu::Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        dx12_shader_compiler: Default::default(),
+    })
+}
+
+async fn request_device(adapter: &wgpu::Adapter, max_texture_dimension_2d: u32) -> (wgpu::Device, wgpu::Queue) {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                // Line polygon mode is only used for the wireframe debug view, so
+                // only enable it where available.
+                features: adapter.features() & wgpu::Features::POLYGON_MODE_LINE,
+                // WebGL doesn't support all of wgpu's features, so if
+                // we're building for the web we'll have to disable some.
+                limits: if cfg!(target_arch = "wasm32") {
+                    Limits {
+                        // Max texture dimension is limited to 2048 for downlevel, but
+                        // the WebXR frame buffer for stereo can be larger (at least on desktop in Chrome)
+                        // TODO: What about other types of texture?
+                        max_texture_dimension_2d: 4096,    
+                        ..wgpu::Limits::downlevel_webgl2_defaults()
+                    }
+                } else {
+                    Limits {
+                        max_texture_dimension_2d,
+                        ..wgpu::Limits::default()
+                    }
+                },
+            },
+            None,
+        )
+        .await
+        .unwrap()
+}
@@ -17,6 +17,8 @@ pub struct KeyboardEvent {
 #[derive(Event)]
 pub enum MouseEvent {
     Move(f32, f32),
+    // Cursor position in the window
+    CursorMoved(f32, f32),
     Button { button: MouseButton, pressed: bool },
 }
 
@@ -40,3 +42,51 @@ pub struct HandUpdateEvent {
     pub joint_transforms: Vec<Mat4f>,
     pub joint_radii: Vec<f32>,
 }
+
+// Two colliders started touching. Trigger volumes send TriggerEnter instead.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CollisionStarted {
+    pub a: Entity,
+    pub b: Entity,
+}
+
+// Two colliders stopped touching, or one of them was removed. Trigger volumes send
+// TriggerExit instead.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CollisionEnded {
+    pub a: Entity,
+    pub b: Entity,
+}
+
+// Two colliders pushed each other harder than the contact force threshold of one of them
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ContactForce {
+    pub a: Entity,
+    pub b: Entity,
+    // Sum of the forces at all contact points
+    pub total_force: Vec3f,
+    // The strongest force at a single contact point
+    pub max_force_magnitude: f32,
+    pub max_force_direction: Vec3f,
+}
+
+// The other entity's collider started overlapping the trigger volume
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TriggerEnter {
+    pub trigger: Entity,
+    pub other: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TriggerExit {
+    pub trigger: Entity,
+    pub other: Entity,
+}
+
+// A joint pulled harder than its break force and was removed
+#[derive(Event, Debug, Clone, Copy)]
+pub struct JointBroken {
+    pub joint: Entity,
+    pub body1: Entity,
+    pub body2: Entity,
+}
@@ -6,7 +6,13 @@ use web_time::Instant;
 
 #[derive(Resource)]
 pub struct FrameTime {
+    // Smoothed over the last few frames
     pub delta: f32,
+    // Unfiltered time since the last frame
+    pub raw_delta: f32,
+    // Unfiltered time since the first frame, and the number of frames so far
+    pub elapsed: f32,
+    pub frame_count: u32,
     queue: VecDeque<f32>,
     last_frame_instant: Instant,
 }
@@ -22,6 +28,9 @@ impl FrameTime {
             queue,
             last_frame_instant,
             delta: 0.0,
+            raw_delta: 0.0,
+            elapsed: 0.0,
+            frame_count: 0,
         }
     }
 
@@ -38,6 +47,9 @@ impl FrameTime {
         }
         self.queue.push_back(raw);
         self.delta = self.queue.iter().copied().sum::<f32>() / self.queue.len() as f32;
+        self.raw_delta = raw;
+        self.elapsed += raw;
+        self.frame_count += 1;
 
         //println!("frame time change {}", &self.delta)
     }
@@ -0,0 +1,241 @@
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::SystemParam;
+
+use crate::components::Transform;
+use crate::math::{Vec3f, UnitQuatf};
+use crate::renderers::DebugLines;
+
+pub const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+pub const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+pub const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+pub const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+pub const GREY: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+
+// Line segments used to approximate circles
+const CIRCLE_SEGMENTS: usize = 32;
+
+// Immediate mode line drawing. Any system can take a `Gizmos` param and draw shapes
+// that are shown for the current frame only, e.g.
+//
+//     fn draw(mut gizmos: Gizmos) {
+//         gizmos.axes(&Transform::from_position(Vec3f::zeros()), 1.0);
+//         gizmos.overlay().ray(from, dir, gizmos::YELLOW);
+//     }
+//
+// Shapes are hidden behind scene geometry unless drawn through `overlay()`.
+// Everything ends up in the DebugLines resource and is drawn in one LinePass after the phong pass.
+#[derive(SystemParam)]
+pub struct Gizmos<'w> {
+    lines: ResMut<'w, DebugLines>,
+}
+
+impl Gizmos<'_> {
+    pub fn depth_tested(&mut self) -> GizmoPainter<'_> {
+        GizmoPainter { lines: &mut self.lines, depth_test: true }
+    }
+
+    // Drawn on top of the scene
+    pub fn overlay(&mut self) -> GizmoPainter<'_> {
+        GizmoPainter { lines: &mut self.lines, depth_test: false }
+    }
+
+    // Shorthands for the depth tested shapes
+
+    pub fn line(&mut self, a: Vec3f, b: Vec3f, color: [f32; 4]) {
+        self.depth_tested().line(a, b, color);
+    }
+
+    pub fn ray(&mut self, origin: Vec3f, vector: Vec3f, color: [f32; 4]) {
+        self.depth_tested().ray(origin, vector, color);
+    }
+
+    pub fn aabb(&mut self, min: Vec3f, max: Vec3f, color: [f32; 4]) {
+        self.depth_tested().aabb(min, max, color);
+    }
+
+    pub fn sphere(&mut self, center: Vec3f, radius: f32, color: [f32; 4]) {
+        self.depth_tested().sphere(center, radius, color);
+    }
+
+    pub fn axes(&mut self, transform: &Transform, length: f32) {
+        self.depth_tested().axes(transform, length);
+    }
+
+    pub fn grid(&mut self, center: Vec3f, cell_count: u32, spacing: f32, color: [f32; 4]) {
+        self.depth_tested().grid(center, cell_count, spacing, color);
+    }
+}
+
+pub struct GizmoPainter<'a> {
+    lines: &'a mut DebugLines,
+    depth_test: bool,
+}
+
+impl GizmoPainter<'_> {
+    pub fn line(&mut self, a: Vec3f, b: Vec3f, color: [f32; 4]) -> &mut Self {
+        self.lines.add(a, b, color, self.depth_test);
+        self
+    }
+
+    // Arrow from origin to origin + vector
+    pub fn ray(&mut self, origin: Vec3f, vector: Vec3f, color: [f32; 4]) -> &mut Self {
+        let tip = origin + vector;
+        self.line(origin, tip, color);
+
+        let length = vector.norm();
+        if length <= f32::EPSILON {
+            return self;
+        }
+        // Arrow head is a small pyramid pointing along the ray
+        let dir = vector / length;
+        let (u, v) = orthonormal_basis(dir);
+        let head_length = length * 0.15;
+        let head_base = tip - dir * head_length;
+        let head_radius = head_length * 0.4;
+        for side in [u, -u, v, -v] {
+            self.line(tip, head_base + side * head_radius, color);
+        }
+        self
+    }
+
+    pub fn aabb(&mut self, min: Vec3f, max: Vec3f, color: [f32; 4]) -> &mut Self {
+        let corner = |i: usize| Vec3f::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        );
+        // Corners are indexed by bits (x, y, z), edges join corners that differ in one bit
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+        self
+    }
+
+    // Drawn as three great circles around the x, y and z axes
+    pub fn sphere(&mut self, center: Vec3f, radius: f32, color: [f32; 4]) -> &mut Self {
+        self.circle(center, Vec3f::x(), Vec3f::y(), radius, color);
+        self.circle(center, Vec3f::y(), Vec3f::z(), radius, color);
+        self.circle(center, Vec3f::z(), Vec3f::x(), radius, color);
+        self
+    }
+
+    // Circle in the plane spanned by the unit vectors u and v
+    pub fn circle(&mut self, center: Vec3f, u: Vec3f, v: Vec3f, radius: f32, color: [f32; 4]) -> &mut Self {
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+        self
+    }
+
+    // Local x, y and z axes of the transform in red, green and blue. Ignores the scale.
+    pub fn axes(&mut self, transform: &Transform, length: f32) -> &mut Self {
+        let origin = transform.position();
+        let rotation: UnitQuatf = transform.rotation();
+        self.line(origin, origin + rotation * Vec3f::x() * length, RED);
+        self.line(origin, origin + rotation * Vec3f::y() * length, GREEN);
+        self.line(origin, origin + rotation * Vec3f::z() * length, BLUE);
+        self
+    }
+
+    // Square grid on the xz plane with cell_count cells along each side
+    pub fn grid(&mut self, center: Vec3f, cell_count: u32, spacing: f32, color: [f32; 4]) -> &mut Self {
+        let half_size = cell_count as f32 * spacing / 2.0;
+        for i in 0..=cell_count {
+            let offset = i as f32 * spacing - half_size;
+            self.line(
+                center + Vec3f::new(offset, 0.0, -half_size),
+                center + Vec3f::new(offset, 0.0, half_size),
+                color,
+            );
+            self.line(
+                center + Vec3f::new(-half_size, 0.0, offset),
+                center + Vec3f::new(half_size, 0.0, offset),
+                color,
+            );
+        }
+        self
+    }
+}
+
+// Two unit vectors perpendicular to dir and each other
+fn orthonormal_basis(dir: Vec3f) -> (Vec3f, Vec3f) {
+    let helper = if dir.y.abs() < 0.9 { Vec3f::y() } else { Vec3f::x() };
+    let u = dir.cross(&helper).normalize();
+    let v = dir.cross(&u);
+    (u, v)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::system::SystemState;
+
+    use super::*;
+
+    fn draw(depth_test: bool, f: impl FnOnce(&mut GizmoPainter)) -> DebugLines {
+        let mut lines = DebugLines::default();
+        f(&mut GizmoPainter { lines: &mut lines, depth_test });
+        lines
+    }
+
+    #[test]
+    fn aabb_has_twelve_edges() {
+        let lines = draw(true, |p| { p.aabb(Vec3f::new(-1.0, -2.0, -3.0), Vec3f::new(1.0, 2.0, 3.0), WHITE); });
+        assert_eq!(lines.depth_tested.len(), 12 * 2);
+        // Each edge runs along one axis
+        for edge in lines.depth_tested.chunks(2) {
+            let a = Vec3f::from(edge[0].position);
+            let b = Vec3f::from(edge[1].position);
+            assert_eq!((a - b).iter().filter(|d| d.abs() > 0.0).count(), 1);
+        }
+    }
+
+    #[test]
+    fn grid_has_two_lines_per_cell_boundary() {
+        let lines = draw(true, |p| { p.grid(Vec3f::zeros(), 4, 1.0, GREY); });
+        assert_eq!(lines.depth_tested.len(), 2 * (4 + 1) * 2);
+        let xs: Vec<f32> = lines.depth_tested.iter().map(|v| v.position[0]).collect();
+        assert_eq!(xs.iter().cloned().fold(f32::MAX, f32::min), -2.0);
+        assert_eq!(xs.iter().cloned().fold(f32::MIN, f32::max), 2.0);
+    }
+
+    #[test]
+    fn ray_has_arrow_head_unless_empty() {
+        let lines = draw(true, |p| { p.ray(Vec3f::zeros(), Vec3f::x(), YELLOW); });
+        assert_eq!(lines.depth_tested.len(), 5 * 2);
+        let lines = draw(true, |p| { p.ray(Vec3f::zeros(), Vec3f::zeros(), YELLOW); });
+        assert_eq!(lines.depth_tested.len(), 2);
+    }
+
+    #[test]
+    fn sphere_is_three_circles() {
+        let lines = draw(true, |p| { p.sphere(Vec3f::zeros(), 2.0, RED); });
+        assert_eq!(lines.depth_tested.len(), 3 * CIRCLE_SEGMENTS * 2);
+        for v in &lines.depth_tested {
+            assert!((Vec3f::from(v.position).norm() - 2.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn system_param_writes_to_debug_lines() {
+        let mut world = World::new();
+        world.init_resource::<DebugLines>();
+        let mut state = SystemState::<Gizmos>::new(&mut world);
+        let mut gizmos = state.get_mut(&mut world);
+        gizmos.line(Vec3f::zeros(), Vec3f::x(), WHITE);
+        gizmos.overlay().axes(&Transform::from_position(Vec3f::zeros()), 1.0);
+        state.apply(&mut world);
+
+        let lines = world.resource::<DebugLines>();
+        assert_eq!(lines.depth_tested.len(), 2);
+        assert_eq!(lines.overlay.len(), 3 * 2);
+    }
+}
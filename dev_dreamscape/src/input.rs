@@ -13,6 +13,8 @@ pub struct Input {
     pub down_down: bool,
     pub space_just_pressed: bool,
     pub mouse_delta: (f32, f32),
+    // Cursor position in the window in pixels, from the top left
+    pub cursor_position: (f32, f32),
 }
 
 impl Input {
@@ -28,6 +30,7 @@ impl Input {
             down_down: false,
             space_just_pressed: false,
             mouse_delta: (0.0, 0.0),
+            cursor_position: (0.0, 0.0),
         }
     }
 
@@ -40,6 +43,10 @@ impl Input {
         self.mouse_delta = delta;
     }
 
+    pub fn on_cursor_move(&mut self, position: (f32, f32)) {
+        self.cursor_position = position;
+    }
+
     pub fn on_mouse_button(&mut self, btn: MouseButton, pressed: bool) {
         if btn == MouseButton::Left { self.lmb_down = pressed; }
         if btn == MouseButton::Right { self.rmb_down = pressed; }
@@ -1,21 +1,38 @@
 mod app;
 mod assets;
+mod capture;
 mod components;
 mod device;
 mod events;
 mod frame_time;
+pub mod gizmos;
 mod input;
 mod logging; 
 mod math;
+mod physics_debug;
 mod physics_world;
 mod systems;
 mod model;
 mod texture;
+// Test fixtures, also used by the golden tests
+#[doc(hidden)]
+pub mod testing;
 mod utils;
 mod renderers;
 #[cfg(target_arch="wasm32")]
 mod xr;
 
+pub use app::App;
+#[cfg(not(target_arch="wasm32"))]
+pub use assets::write_convex_decompositions;
+pub use capture::{capture_sequence, FrameFormat, PanoramaSettings, PosterSettings, SequenceSettings};
+pub use gizmos::Gizmos;
+pub use events::{CollisionEnded, CollisionStarted, ContactForce, JointBroken, TriggerEnter, TriggerExit};
+pub use components::{ColliderShape, GravityZone, Joint, JointKind, JointMotor, PhysicsMaterial, ShaderParam, TriggerVolume, ZoneGravity, ZoneShape};
+pub use physics_world::{PhysicsSettings, PhysicsTimestep, WorldBounds};
+pub use renderers::{DebugView, PostEffect, PostProcess, Sketch, SketchMode};
+pub use renderers::graph::{self, BufferDesc, GraphPass, GraphResources, PassBuilder, RenderContext, RenderGraph, TextureDesc};
+
 use cfg_if::cfg_if;
 #[cfg(target_arch="wasm32")]
 use wasm_bindgen::prelude::*;
@@ -1,4 +1,27 @@
-pub fn init_logging() {
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+
+// Keeps the trace output alive. Dropping it flushes any pending spans
+// (on desktop this is what finalizes the Chrome trace file).
+pub struct TraceGuard {
+    #[cfg(not(target_arch = "wasm32"))]
+    chrome: Option<tracing_chrome::FlushGuard>,
+}
+
+impl TraceGuard {
+    pub fn flush(&self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(guard) = &self.chrome {
+            guard.flush();
+        }
+    }
+}
+
+pub fn init_logging() -> TraceGuard {
     cfg_if::cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
             std::panic::set_hook(Box::new(console_error_panic_hook::hook));
@@ -8,8 +31,130 @@ pub fn init_logging() {
             env_logger::init();
         }
     }
+    init_tracing()
 }
 
 pub fn printlog(log_str: &str) {
     log::warn!("{}", log_str);
 }
+
+// Tracing is opt-in. On desktop set DREAMSCAPE_TRACE to the output path of the
+// Chrome trace (open it in chrome://tracing or ui.perfetto.dev). An empty value
+// writes to ./trace-<timestamp>.json.
+#[cfg(not(target_arch = "wasm32"))]
+fn init_tracing() -> TraceGuard {
+    use tracing_chrome::{ChromeLayerBuilder, EventOrSpan};
+
+    let Some(path) = std::env::var_os("DREAMSCAPE_TRACE") else {
+        return TraceGuard { chrome: None };
+    };
+
+    let mut builder = ChromeLayerBuilder::new()
+        .include_args(true)
+        .name_fn(Box::new(|event_or_span| match event_or_span {
+            EventOrSpan::Event(event) => event.metadata().name().to_string(),
+            EventOrSpan::Span(span) => span_label(span),
+        }));
+    if !path.is_empty() {
+        builder = builder.file(path);
+    }
+    let (chrome_layer, guard) = builder.build();
+
+    let subscriber = tracing_subscriber::registry()
+        .with(SpanLabelLayer)
+        .with(chrome_layer);
+    tracing::subscriber::set_global_default(subscriber)
+        .expect("Couldn't set tracing subscriber");
+
+    TraceGuard { chrome: Some(guard) }
+}
+
+// On the web, add `?trace` to the page url. Spans are then emitted as
+// console.timeStamp markers which show up in the browser's Performance panel.
+#[cfg(target_arch = "wasm32")]
+fn init_tracing() -> TraceGuard {
+    let enabled = web_sys::window()
+        .and_then(|win| win.location().search().ok())
+        .map_or(false, |search| search.contains("trace"));
+    if enabled {
+        let subscriber = tracing_subscriber::registry()
+            .with(SpanLabelLayer)
+            .with(ConsoleTimeStampLayer);
+        tracing::subscriber::set_global_default(subscriber)
+            .expect("Couldn't set tracing subscriber");
+    }
+    TraceGuard {}
+}
+
+// Label shown for a span in the trace output.
+// Bevy names all of its spans "schedule"/"system" and stores the actual name
+// in a `name` field, so we fold that into the label, e.g. "system: update_physics".
+struct SpanLabel(String);
+
+struct SpanLabelLayer;
+
+impl<S> Layer<S> for SpanLabelLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = NameVisitor(None);
+        attrs.record(&mut visitor);
+        let label = match visitor.0 {
+            Some(name) => format!("{}: {}", attrs.metadata().name(), name),
+            None => attrs.metadata().name().to_string(),
+        };
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanLabel(label));
+        }
+    }
+}
+
+struct NameVisitor(Option<String>);
+
+impl Visit for NameVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "name" {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+fn span_label<S>(span: &SpanRef<'_, S>) -> String
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    span.extensions()
+        .get::<SpanLabel>()
+        .map(|label| label.0.clone())
+        .unwrap_or_else(|| span.name().to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+struct ConsoleTimeStampLayer;
+
+#[cfg(target_arch = "wasm32")]
+impl<S> Layer<S> for ConsoleTimeStampLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let label = format!("{} begin", span_label(&span));
+            web_sys::console::time_stamp_with_data(&wasm_bindgen::JsValue::from_str(&label));
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let label = format!("{} end", span_label(&span));
+            web_sys::console::time_stamp_with_data(&wasm_bindgen::JsValue::from_str(&label));
+        }
+    }
+}
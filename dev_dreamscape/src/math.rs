@@ -10,6 +10,7 @@ use rapier3d::prelude::Real;
 // Float specializations
 pub type Vec2f = na::Vector2<f32>;
 pub type Vec3f = na::Vector3<f32>;
+pub type Vec4f = na::Vector4<f32>;
 pub type Point3f = na::Point3<f32>;
 pub type Mat3f = na::Matrix3<f32>;
 pub type Mat4f = na::Matrix4<f32>;
@@ -20,6 +21,7 @@ pub type UnitVec3f = na::UnitVector3<f32>;
 // Generic
 pub type Vec2<T> = na::Vector2<T>;
 pub type Vec3<T> = na::Vector3<T>;
+pub type Vec4<T> = na::Vector4<T>;
 pub type Point3<T> = na::Point3<T>;
 pub type Mat3<T> = na::Matrix3<T>;
 pub type Mat4<T> = na::Matrix4<T>;
@@ -33,6 +35,7 @@ pub fn to_point(v3: Vec3f) -> Point3<Real> {
     Point3::origin().add(v3)
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Rect {
    pub x: f32,
    pub y: f32,
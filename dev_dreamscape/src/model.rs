@@ -79,12 +79,18 @@ pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
+    // Triangle edges as a line list, used for wireframe rendering when
+    // the device doesn't support PolygonMode::Line (e.g. WebGL)
+    pub edge_index_buffer: wgpu::Buffer,
+    pub num_edge_elements: u32,
     pub material: usize,
 }
 
 pub struct Model {
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
+    // Radius of a sphere around the model origin containing all vertices, for culling
+    pub bounding_radius: f32,
 }
 
 pub trait DrawModel<'a> {
@@ -107,6 +113,11 @@ pub trait DrawModel<'a> {
         model: &'a Model,
         instances: Range<u32>
     );
+    fn draw_model_edges_instanced(
+        &mut self,
+        model: &'a Model,
+        instances: Range<u32>
+    );
 }
 
 impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a>
@@ -149,4 +160,16 @@ where
             );
         }
     }
+
+    fn draw_model_edges_instanced(
+        &mut self,
+        model: &'b Model,
+        instances: Range<u32>
+    ) {
+        for mesh in &model.meshes {
+            self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            self.set_index_buffer(mesh.edge_index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            self.draw_indexed(0..mesh.num_edge_elements, 0, instances.clone());
+        }
+    }
 }
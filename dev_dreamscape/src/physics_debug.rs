@@ -0,0 +1,92 @@
+use bevy_ecs::prelude::*;
+use rapier3d::pipeline::{
+    DebugRenderBackend, DebugRenderMode, DebugRenderObject, DebugRenderPipeline, DebugRenderStyle,
+};
+use rapier3d::prelude::{Point, Real};
+use winit::event::VirtualKeyCode;
+
+use crate::events::KeyboardEvent;
+use crate::gizmos::{GizmoPainter, Gizmos};
+use crate::physics_world::PhysicsWorld;
+
+// Draws the rapier colliders, their AABBs, contacts and joints as lines.
+// Toggled with F2, drawn through the gizmos.
+#[derive(Resource)]
+pub struct PhysicsDebugRender {
+    pub enabled: bool,
+    // Off by default so colliders inside render meshes are still visible
+    pub depth_test: bool,
+    pipeline: DebugRenderPipeline,
+}
+
+impl Default for PhysicsDebugRender {
+    fn default() -> Self {
+        let style = DebugRenderStyle {
+            // Desaturate sleeping bodies to grey so they stand out from the active ones
+            sleep_color_multiplier: [1.0, 0.0, 1.0, 1.0],
+            ..Default::default()
+        };
+        let mode = DebugRenderMode::COLLIDER_SHAPES
+            | DebugRenderMode::COLLIDER_AABBS
+            | DebugRenderMode::CONTACTS
+            | DebugRenderMode::JOINTS;
+
+        Self {
+            enabled: false,
+            depth_test: false,
+            pipeline: DebugRenderPipeline::new(style, mode),
+        }
+    }
+}
+
+impl PhysicsDebugRender {
+    pub fn toggle(mut debug_render: ResMut<PhysicsDebugRender>, mut keyboard_events: EventReader<KeyboardEvent>) {
+        for _ in keyboard_events
+            .iter()
+            .filter(|e| e.code == VirtualKeyCode::F2 && e.pressed)
+        {
+            debug_render.enabled = !debug_render.enabled;
+            log::warn!("Physics debug render: {}", debug_render.enabled);
+        }
+    }
+
+    pub fn update(
+        mut debug_render: ResMut<PhysicsDebugRender>,
+        physics: Res<PhysicsWorld>,
+        mut gizmos: Gizmos,
+    ) {
+        if !debug_render.enabled {
+            return;
+        }
+        let painter = if debug_render.depth_test { gizmos.depth_tested() } else { gizmos.overlay() };
+        let mut backend = GizmoBackend { painter };
+        physics.debug_render(&mut debug_render.pipeline, &mut backend);
+    }
+}
+
+struct GizmoBackend<'a> {
+    painter: GizmoPainter<'a>,
+}
+
+impl DebugRenderBackend for GizmoBackend<'_> {
+    fn draw_line(&mut self, _object: DebugRenderObject, a: Point<Real>, b: Point<Real>, color: [f32; 4]) {
+        self.painter.line(a.coords, b.coords, hsla_to_rgba(color));
+    }
+}
+
+// Rapier hands out colors as HSLA with the hue in degrees
+fn hsla_to_rgba([h, s, l, a]: [f32; 4]) -> [f32; 4] {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h = (h / 60.0).rem_euclid(6.0);
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    [r + m, g + m, b + m, a]
+}
@@ -1,8 +1,111 @@
-use bevy_ecs::prelude::Resource;
+use std::collections::{HashMap, HashSet};
+
+use bevy_ecs::prelude::{Entity, Resource};
 use crate::math::Vec3f;
+use rapier3d::crossbeam::channel::{unbounded, Receiver};
 use rapier3d::control::{EffectiveCharacterMovement, KinematicCharacterController};
+use rapier3d::pipeline::{DebugRenderBackend, DebugRenderPipeline};
 use rapier3d::prelude::*;
 
+// Steps the simulation at a fixed rate, so results don't depend on the frame rate.
+// Frame time accumulates and is consumed in steps of 1 / rate.
+#[derive(Resource)]
+pub struct PhysicsTimestep {
+    // Steps per second
+    pub rate: f32,
+    // Caps the steps per frame so that a slow frame doesn't make the next one slower still.
+    // Time beyond that is dropped and the simulation falls behind.
+    pub max_substeps: u32,
+    // Multiplies the frame time, e.g. 0.25 for slow motion and 0 to pause
+    pub time_scale: f32,
+    accumulator: f32,
+}
+
+impl Default for PhysicsTimestep {
+    fn default() -> Self {
+        Self {
+            rate: 60.0,
+            max_substeps: 4,
+            time_scale: 1.0,
+            accumulator: 0.0,
+        }
+    }
+}
+
+impl PhysicsTimestep {
+    pub fn dt(&self) -> f32 {
+        1.0 / self.rate
+    }
+
+    // Adds a frame's time and returns the number of steps to take
+    pub fn advance(&mut self, frame_delta: f32) -> u32 {
+        let dt = self.dt();
+        self.accumulator += frame_delta * self.time_scale;
+        let steps = ((self.accumulator / dt) as u32).min(self.max_substeps);
+        self.accumulator = (self.accumulator - steps as f32 * dt).min(dt);
+        steps
+    }
+
+    // How far the frame is between the previous and the current step, from 0 to 1.
+    // Rendered transforms are interpolated by it.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.dt()).clamp(0.0, 1.0)
+    }
+}
+
+// Simulation parameters, read at every step so they can be changed at runtime
+#[derive(Resource, Clone, Debug)]
+pub struct PhysicsSettings {
+    // Outside of gravity zones
+    pub gravity: Vec3f,
+    // Solver iterations per step. More are stiffer and more accurate, but slower.
+    pub velocity_iterations: usize,
+    pub friction_iterations: usize,
+    pub stabilization_iterations: usize,
+    // Continuous collision detection for the dynamic bodies, so fast bodies such as thrown
+    // boxes don't tunnel through thin colliders. Bodies created with PhysicsBodyParams::ccd
+    // keep their own setting.
+    pub ccd: bool,
+    // How far colliders may overlap before being pushed apart
+    pub allowed_penetration: f32,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        let defaults = IntegrationParameters::default();
+        Self {
+            gravity: Vec3f::new(0.0, -9.81, 0.0),
+            velocity_iterations: defaults.max_velocity_iterations,
+            friction_iterations: defaults.max_velocity_friction_iterations,
+            stabilization_iterations: defaults.max_stabilization_iterations,
+            ccd: false,
+            allowed_penetration: defaults.allowed_linear_error,
+        }
+    }
+}
+
+// Dynamic bodies that leave this box are despawned, so objects that fall off the world
+// don't keep being simulated. Bounds are only checked while the resource exists.
+#[derive(Resource, Clone, Debug)]
+pub struct WorldBounds {
+    pub min: Vec3f,
+    pub max: Vec3f,
+}
+
+impl WorldBounds {
+    // Kill plane: unbounded apart from the height
+    pub fn below(min_y: f32) -> Self {
+        Self {
+            min: Vec3f::new(f32::NEG_INFINITY, min_y, f32::NEG_INFINITY),
+            max: Vec3f::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        }
+    }
+
+    pub fn contains(&self, pos: &Vec3f) -> bool {
+        (0..3).all(|i| pos[i] >= self.min[i] && pos[i] <= self.max[i])
+    }
+}
+
 #[derive(Resource)]
 pub struct PhysicsWorld {
     pub bodies: RigidBodySet,
@@ -16,6 +119,29 @@ pub struct PhysicsWorld {
     multibody_joints: MultibodyJointSet,
     ccd_solver: CCDSolver,
     char_controller: KinematicCharacterController,
+    // Collects rapier's events during steps, to be sent on as ECS events
+    event_collector: ChannelEventCollector,
+    collision_events: Receiver<CollisionEvent>,
+    contact_force_events: Receiver<ContactForceEvent>,
+    // Entity each collider belongs to, for the events
+    collider_entities: HashMap<ColliderHandle, Entity>,
+    // Colliders of TriggerVolumes, to tell the trigger from the other entity in the events
+    trigger_colliders: HashSet<ColliderHandle>,
+    // Colliders removed since the last step, and the ones removed before a step that has run
+    // since. A step reports the ended collisions of the colliders removed before it, so their
+    // entities are kept until those events have been sent, see forget_removed_colliders.
+    removed_colliders: Vec<ColliderHandle>,
+    stale_colliders: Vec<ColliderHandle>,
+    // Bodies of the PhysicsBody components, by entity, to remove them once the component is gone
+    body_handles: HashMap<Entity, RigidBodyHandle>,
+    // Colliders of the TriggerVolume components, by entity
+    trigger_handles: HashMap<Entity, ColliderHandle>,
+    // Joints of the Joint components, by entity
+    joint_handles: HashMap<Entity, ImpulseJointHandle>,
+    // Forces added to bodies to replace the world gravity with another, see override_gravity
+    gravity_forces: HashMap<RigidBodyHandle, Vector<Real>>,
+    // Bodies that opted in or out of CCD themselves, which PhysicsSettings::ccd leaves alone
+    ccd_overrides: HashSet<RigidBodyHandle>,
 }
 
 impl PhysicsWorld {
@@ -31,6 +157,9 @@ impl PhysicsWorld {
         let multibody_joints = MultibodyJointSet::new();
         let ccd_solver = CCDSolver::new();
         let char_controller = KinematicCharacterController::default();
+        let (collision_sender, collision_events) = unbounded();
+        let (contact_force_sender, contact_force_events) = unbounded();
+        let event_collector = ChannelEventCollector::new(collision_sender, contact_force_sender);
 
         Self {
             bodies,
@@ -44,6 +173,18 @@ impl PhysicsWorld {
             multibody_joints,
             ccd_solver,
             char_controller,
+            event_collector,
+            collision_events,
+            contact_force_events,
+            collider_entities: HashMap::new(),
+            trigger_colliders: HashSet::new(),
+            removed_colliders: vec![],
+            stale_colliders: vec![],
+            body_handles: HashMap::new(),
+            trigger_handles: HashMap::new(),
+            joint_handles: HashMap::new(),
+            gravity_forces: HashMap::new(),
+            ccd_overrides: HashSet::new(),
         }
     }
 
@@ -58,6 +199,116 @@ impl PhysicsWorld {
         (body_handle, collider_handle)
     }
 
+    // Keeps the body's CCD setting when PhysicsSettings::ccd changes
+    pub fn keep_ccd(&mut self, body: RigidBodyHandle) {
+        self.ccd_overrides.insert(body);
+    }
+
+    pub fn set_collider_entity(&mut self, collider: ColliderHandle, entity: Entity) {
+        self.collider_entities.insert(collider, entity);
+    }
+
+    pub fn collider_entity(&self, collider: ColliderHandle) -> Option<Entity> {
+        self.collider_entities.get(&collider).copied()
+    }
+
+    pub fn set_trigger_entity(&mut self, collider: ColliderHandle, entity: Entity) {
+        self.collider_entities.insert(collider, entity);
+        self.trigger_colliders.insert(collider);
+        self.trigger_handles.insert(entity, collider);
+    }
+
+    pub fn is_trigger(&self, collider: ColliderHandle) -> bool {
+        self.trigger_colliders.contains(&collider)
+    }
+
+    // Removes the collider of the entity's trigger volume
+    pub fn remove_trigger(&mut self, entity: Entity) {
+        let Some(handle) = self.trigger_handles.remove(&entity) else {
+            return;
+        };
+        if self.colliders.remove(handle, &mut self.island_manager, &mut self.bodies, true).is_some() {
+            self.removed_colliders.push(handle);
+        }
+    }
+
+    // Forgets the entities of the colliders removed before the last step, once the events
+    // of that step have been sent
+    pub fn forget_removed_colliders(&mut self) {
+        for collider in self.stale_colliders.drain(..) {
+            self.collider_entities.remove(&collider);
+            self.trigger_colliders.remove(&collider);
+        }
+    }
+
+    pub fn set_body_entity(&mut self, body: RigidBodyHandle, entity: Entity) {
+        self.body_handles.insert(entity, body);
+    }
+
+    // Removes the entity's body along with its colliders and the joints attached to it.
+    // Returns the entities of the removed joints.
+    pub fn remove_body(&mut self, entity: Entity) -> Vec<Entity> {
+        let Some(handle) = self.body_handles.remove(&entity) else {
+            return vec![];
+        };
+        let Some(body) = self.bodies.remove(
+            handle,
+            &mut self.island_manager,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            true,
+        ) else {
+            return vec![];
+        };
+        self.removed_colliders.extend_from_slice(body.colliders());
+        self.gravity_forces.remove(&handle);
+        self.ccd_overrides.remove(&handle);
+
+        let joints = &self.impulse_joints;
+        let removed_joints: Vec<Entity> = self.joint_handles.iter()
+            .filter(|(_, &joint)| !joints.contains(joint))
+            .map(|(&entity, _)| entity)
+            .collect();
+        for entity in &removed_joints {
+            self.joint_handles.remove(entity);
+        }
+        removed_joints
+    }
+
+    pub fn add_joint(&mut self, entity: Entity, body1: RigidBodyHandle, body2: RigidBodyHandle, joint: GenericJoint) {
+        let handle = self.impulse_joints.insert(body1, body2, joint, true);
+        if let Some(old) = self.joint_handles.insert(entity, handle) {
+            self.impulse_joints.remove(old, true);
+        }
+    }
+
+    pub fn joint_mut(&mut self, entity: Entity) -> Option<&mut ImpulseJoint> {
+        let handle = *self.joint_handles.get(&entity)?;
+        self.impulse_joints.get_mut(handle)
+    }
+
+    pub fn remove_joint(&mut self, entity: Entity) {
+        if let Some(handle) = self.joint_handles.remove(&entity) {
+            self.impulse_joints.remove(handle, true);
+        }
+    }
+
+    // Linear force the joint applied in the last step of length dt
+    pub fn joint_force(&self, entity: Entity, dt: f32) -> Option<f32> {
+        let joint = self.impulse_joints.get(*self.joint_handles.get(&entity)?)?;
+        Some(joint.impulses.fixed_rows::<3>(0).norm() / dt)
+    }
+
+    // Events from the steps since the last call
+    pub fn drain_collision_events(&self) -> impl Iterator<Item = CollisionEvent> + '_ {
+        self.collision_events.try_iter()
+    }
+
+    pub fn drain_contact_force_events(&self) -> impl Iterator<Item = ContactForceEvent> + '_ {
+        self.contact_force_events.try_iter()
+    }
+
     pub fn move_character(
         &self,
         dt: f32,
@@ -78,7 +329,8 @@ impl PhysicsWorld {
                 collider_shape,
                 collider_pos,
                 desired_translation,
-                QueryFilter::default().exclude_collider(collider_handle),
+                // Trigger volumes don't block the player
+                QueryFilter::default().exclude_collider(collider_handle).exclude_sensors(),
                 |_| {},
             );
 
@@ -120,15 +372,72 @@ impl PhysicsWorld {
         None
     }
 
-    pub fn update(&mut self, dt: f32) {
-        let gravity = vector![0.0, -9.81, 0.0];
+    pub fn debug_render(&self, pipeline: &mut DebugRenderPipeline, backend: &mut impl DebugRenderBackend) {
+        pipeline.render(
+            backend,
+            &self.bodies,
+            &self.colliders,
+            &self.impulse_joints,
+            &self.multibody_joints,
+            &self.narrow_phase,
+        );
+    }
+
+    // Gives the dynamic bodies the gravity returned for their position instead of the world
+    // gravity, scaled by their gravity scale. Bodies it returns None for get the world gravity.
+    // The difference is applied as a force, which stays until the next call.
+    pub fn override_gravity(&mut self, world_gravity: Vec3f, gravity_at: impl Fn(&Vec3f) -> Option<Vec3f>) {
+        // Bodies are only borrowed mutably when their force changes, as rapier treats
+        // mutably borrowed bodies as modified
+        let mut changes = vec![];
+        for (handle, body) in self.bodies.iter().filter(|(_, body)| body.is_dynamic()) {
+            let force = gravity_at(body.translation())
+                .map(|gravity| (gravity - world_gravity) * body.mass() * body.gravity_scale())
+                .unwrap_or_else(Vector::zeros);
+            let applied = self.gravity_forces.get(&handle).copied().unwrap_or_else(Vector::zeros);
+            if force != applied {
+                changes.push((handle, force, applied));
+            }
+        }
+        for (handle, force, applied) in changes {
+            self.bodies[handle].add_force(force - applied, true);
+            if force == Vector::zeros() {
+                self.gravity_forces.remove(&handle);
+            } else {
+                self.gravity_forces.insert(handle, force);
+            }
+        }
+        // Forget removed bodies
+        let bodies = &self.bodies;
+        self.gravity_forces.retain(|&handle, _| bodies.contains(handle));
+    }
+
+    pub fn update(&mut self, dt: f32, settings: &PhysicsSettings) {
         let integration_parameters = IntegrationParameters {
             dt,
+            max_velocity_iterations: settings.velocity_iterations,
+            max_velocity_friction_iterations: settings.friction_iterations,
+            max_stabilization_iterations: settings.stabilization_iterations,
+            allowed_linear_error: settings.allowed_penetration,
             ..IntegrationParameters::default()
         };
 
+        // This step reports the ended collisions of the colliders removed before it
+        self.stale_colliders.append(&mut self.removed_colliders);
+
+        let ccd_overrides = &self.ccd_overrides;
+        let ccd_changes: Vec<RigidBodyHandle> = self.bodies.iter()
+            .filter(|(handle, body)| {
+                body.is_dynamic() && body.is_ccd_enabled() != settings.ccd && !ccd_overrides.contains(handle)
+            })
+            .map(|(handle, _)| handle)
+            .collect();
+        for handle in ccd_changes {
+            self.bodies[handle].enable_ccd(settings.ccd);
+        }
+
         self.physics_pipeline.step(
-            &gravity,
+            &settings.gravity,
             &integration_parameters,
             &mut self.island_manager,
             &mut self.broad_phase,
@@ -140,9 +449,95 @@ impl PhysicsWorld {
             &mut self.ccd_solver,
             None,
             &(),
-            &(),
+            &self.event_collector,
         );
 
         self.query_pipeline.update(&self.bodies, &self.colliders);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{ColliderShape, PhysicsBody, PhysicsBodyParams, PhysicsMaterial};
+
+    fn timestep() -> PhysicsTimestep {
+        PhysicsTimestep { rate: 50.0, max_substeps: 3, ..Default::default() }
+    }
+
+    #[test]
+    fn short_frames_accumulate_into_steps() {
+        let mut timestep = timestep();
+        assert_eq!(timestep.advance(0.012), 0);
+        assert!((timestep.alpha() - 0.6).abs() < 1e-4);
+        assert_eq!(timestep.advance(0.012), 1);
+        assert!((timestep.alpha() - 0.2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn long_frames_take_several_steps() {
+        let mut timestep = timestep();
+        assert_eq!(timestep.advance(0.05), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn steps_are_capped_and_the_rest_dropped() {
+        let mut timestep = timestep();
+        assert_eq!(timestep.advance(1.0), 3);
+        // At most one step's worth is kept
+        assert_eq!(timestep.alpha(), 1.0);
+        assert_eq!(timestep.advance(0.0), 1);
+        assert_eq!(timestep.advance(0.0), 0);
+    }
+
+    #[test]
+    fn time_scale_slows_and_pauses() {
+        let mut timestep = PhysicsTimestep { time_scale: 0.5, ..timestep() };
+        assert_eq!(timestep.advance(0.04), 1);
+        timestep.time_scale = 0.0;
+        let alpha = timestep.alpha();
+        assert_eq!(timestep.advance(1.0), 0);
+        assert_eq!(timestep.alpha(), alpha);
+    }
+
+    #[test]
+    fn alpha_stays_between_zero_and_one() {
+        let mut timestep = timestep();
+        for delta in [0.0, 0.003, 0.021, 0.5, 0.019] {
+            timestep.advance(delta);
+            assert!((0.0..=1.0).contains(&timestep.alpha()));
+        }
+    }
+
+    fn ball(physics: &mut PhysicsWorld, ccd: Option<bool>) -> RigidBodyHandle {
+        PhysicsBody::new(PhysicsBodyParams {
+            pos: Vec3f::zeros(),
+            scale: Vec3f::from_element(1.0),
+            rotation_angle: 0.0,
+            rotation_axis: Vec3f::y(),
+            movable: true,
+            shape: ColliderShape::Ball { radius: 0.5 },
+            material: PhysicsMaterial::default(),
+            gravity_scale: None,
+            lin_vel: None,
+            ang_vel: None,
+            contact_force_threshold: None,
+            ccd,
+        }, physics).body_handle()
+    }
+
+    #[test]
+    fn ccd_setting_leaves_bodies_with_their_own_alone() {
+        let mut physics = PhysicsWorld::new();
+        let default = ball(&mut physics, None);
+        let opted_in = ball(&mut physics, Some(true));
+        let opted_out = ball(&mut physics, Some(false));
+        for ccd in [true, false] {
+            physics.update(1.0 / 60.0, &PhysicsSettings { ccd, ..Default::default() });
+            assert_eq!(physics.bodies[default].is_ccd_enabled(), ccd);
+            assert!(physics.bodies[opted_in].is_ccd_enabled());
+            assert!(!physics.bodies[opted_out].is_ccd_enabled());
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use bevy_ecs::prelude::*;
+
+/// Selects what the lit pass outputs. Everything except `Shaded` is rendered with
+/// debug_view.wgsl instead of the phong shader.
+#[derive(Resource, Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DebugView {
+    #[default]
+    Shaded,
+    Wireframe,
+    // World space normals from the vertex data (not the normal map)
+    Normals,
+    // World space tangents, handy for spotting issues in the tangent generation in load_model
+    Tangents,
+    Uvs,
+    // Distance to the camera
+    Depth,
+    // Additive, no depth test. Brighter means more fragments were shaded.
+    Overdraw,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 7] = [
+        DebugView::Shaded,
+        DebugView::Wireframe,
+        DebugView::Normals,
+        DebugView::Tangents,
+        DebugView::Uvs,
+        DebugView::Depth,
+        DebugView::Overdraw,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&v| v == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // Value of the DEBUG_VIEW shader def in debug_view.wgsl
+    pub fn shader_def(self) -> i32 {
+        self as i32
+    }
+
+    // Whether the skybox is drawn behind the scene in this mode
+    pub fn draws_skybox(self) -> bool {
+        matches!(self, DebugView::Shaded | DebugView::Wireframe)
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    assets::Assets,
+    components::{Camera, Light, Skybox, Transform},
+    device::Device,
+    frame_time::FrameTime,
+    math::Rect,
+};
+
+use super::{DebugLines, DebugView, HdrPipeline, MaterialNode, PhongNode, PostProcess, Sketch};
+
+// Textures provided to every frame's graph. Passes may read and write them without creating them.
+// The hdr colour and depth textures of HdrPipeline, sized to the viewport
+pub const HDR: &str = "hdr";
+pub const DEPTH: &str = "depth";
+// The texture the view is rendered to
+pub const OUTPUT: &str = "output";
+
+// Everything passes get to see of the view being rendered
+pub struct RenderContext<'a> {
+    pub device: &'a Device,
+    pub assets: &'a Assets,
+    pub hdr_pipeline: &'a HdrPipeline,
+    pub camera: (&'a Camera, &'a Transform),
+    pub skybox: &'a Skybox,
+    // Visible models, grouped for instancing
+    pub nodes: &'a [PhongNode<'a>],
+    pub material_nodes: &'a [MaterialNode<'a>],
+    pub lights: &'a [(&'a Light, &'a Transform)],
+    pub debug_view: DebugView,
+    pub debug_lines: &'a DebugLines,
+    pub sketch: &'a Sketch,
+    pub post_process: &'a PostProcess,
+    pub frame_time: &'a FrameTime,
+    pub output: &'a wgpu::Texture,
+    // Part of the output to draw to, and whether the output is cleared first
+    pub viewport: Option<Rect>,
+    pub clear: bool,
+}
+
+impl RenderContext<'_> {
+    // Size of the hdr and depth textures
+    pub fn view_size(&self) -> (u32, u32) {
+        let texture = self.hdr_pipeline.texture();
+        (texture.width(), texture.height())
+    }
+
+    // Size of the full frame in pixels and the top left corner of this view in it. They
+    // differ from the view size and zero for a camera seeing part of the frame, e.g. a
+    // poster tile, so fullscreen effects can work in full frame coordinates.
+    pub fn frame_view(&self) -> ([f32; 2], [f32; 2]) {
+        let (width, height) = self.view_size();
+        let rect = self.camera.0.sub_view_rect();
+        let frame = [width as f32 / rect.w, height as f32 / rect.h];
+        (frame, [rect.x * frame[0], rect.y * frame[1]])
+    }
+
+    // Whether the 3D scene is drawn, rather than replaced by a sketch
+    pub fn draws_scene(&self) -> bool {
+        !self.sketch.replaces_scene()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferDesc {
+    pub size: u64,
+    pub usage: wgpu::BufferUsages,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ResourceDesc {
+    Texture(TextureDesc),
+    Buffer(BufferDesc),
+}
+
+// The resources a pass uses this frame, declared in GraphPass::setup
+#[derive(Default)]
+pub struct PassBuilder {
+    reads: Vec<String>,
+    writes: Vec<String>,
+    creates: Vec<(String, ResourceDesc)>,
+}
+
+impl PassBuilder {
+    pub fn read(&mut self, name: &str) -> &mut Self {
+        self.reads.push(name.to_string());
+        self
+    }
+
+    // Also used for read-modify-write, e.g. drawing over what earlier passes drew
+    pub fn write(&mut self, name: &str) -> &mut Self {
+        self.writes.push(name.to_string());
+        self
+    }
+
+    // Transient texture that lives until the last pass reading it. Its contents are
+    // undefined at the start, as the texture may be shared with other transient resources.
+    pub fn create_texture(&mut self, name: &str, desc: TextureDesc) -> &mut Self {
+        self.creates.push((name.to_string(), ResourceDesc::Texture(desc)));
+        self
+    }
+
+    pub fn create_buffer(&mut self, name: &str, desc: BufferDesc) -> &mut Self {
+        self.creates.push((name.to_string(), ResourceDesc::Buffer(desc)));
+        self
+    }
+}
+
+// A step of the render graph. Passes that write the same resource run in the order they
+// were added to the graph, see RenderGraph::add_pass_before.
+pub trait GraphPass: Send + Sync {
+    fn name(&self) -> &str;
+
+    // Disabled passes are left out of this frame's graph
+    fn enabled(&self, _ctx: &RenderContext) -> bool {
+        true
+    }
+
+    fn setup(&self, ctx: &RenderContext, builder: &mut PassBuilder);
+
+    fn run(&mut self, ctx: &RenderContext, resources: &GraphResources) -> Vec<wgpu::CommandBuffer>;
+
+    // Called once per frame after all views are rendered, e.g. to keep state across frames
+    fn end_frame(&mut self) {}
+}
+
+enum GraphResource {
+    Texture { texture: wgpu::Texture, view: wgpu::TextureView },
+    Buffer(wgpu::Buffer),
+}
+
+struct PooledResource {
+    desc: ResourceDesc,
+    resource: GraphResource,
+    // Assigned to a transient by any view since the last end_frame
+    used: bool,
+}
+
+// The resources passes can use while running, by name
+pub struct GraphResources<'a> {
+    textures: HashMap<&'a str, (&'a wgpu::Texture, &'a wgpu::TextureView)>,
+    buffers: HashMap<&'a str, &'a wgpu::Buffer>,
+}
+
+impl GraphResources<'_> {
+    // Panics if the pass didn't declare the texture
+    pub fn texture(&self, name: &str) -> &wgpu::Texture {
+        self.textures.get(name).unwrap_or_else(|| panic!("Texture {} not in the render graph", name)).0
+    }
+
+    pub fn view(&self, name: &str) -> &wgpu::TextureView {
+        self.textures.get(name).unwrap_or_else(|| panic!("Texture {} not in the render graph", name)).1
+    }
+
+    pub fn buffer(&self, name: &str) -> &wgpu::Buffer {
+        self.buffers.get(name).unwrap_or_else(|| panic!("Buffer {} not in the render graph", name))
+    }
+}
+
+// Ordered passes that render a view. Each frame the passes declare what they read and write,
+// the graph works out the dependencies between them, drops the passes whose results aren't
+// used, and allocates the transient resources, sharing them between passes where their
+// lifetimes don't overlap.
+#[derive(Default)]
+pub struct RenderGraph {
+    passes: Vec<Box<dyn GraphPass>>,
+    // Transient resources, kept between frames and shared by the views of a frame
+    pool: Vec<PooledResource>,
+}
+
+impl RenderGraph {
+    pub fn add_pass(&mut self, pass: impl GraphPass + 'static) {
+        self.passes.push(Box::new(pass));
+    }
+
+    // Adds the pass before the named one, or at the end if there's no such pass
+    pub fn add_pass_before(&mut self, before: &str, pass: impl GraphPass + 'static) {
+        let index = self.position(before).unwrap_or(self.passes.len());
+        self.passes.insert(index, Box::new(pass));
+    }
+
+    // Adds the pass after the named one, or at the end if there's no such pass
+    pub fn add_pass_after(&mut self, after: &str, pass: impl GraphPass + 'static) {
+        let index = self.position(after).map_or(self.passes.len(), |index| index + 1);
+        self.passes.insert(index, Box::new(pass));
+    }
+
+    pub fn remove_pass(&mut self, name: &str) -> Option<Box<dyn GraphPass>> {
+        self.position(name).map(|index| self.passes.remove(index))
+    }
+
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        let position = self.passes.iter().position(|pass| pass.name() == name);
+        if position.is_none() {
+            log::warn!("No render pass named {}", name);
+        }
+        position
+    }
+
+    // Runs the enabled passes for a view and returns their command buffers in submission order
+    pub fn execute(&mut self, ctx: &RenderContext) -> Vec<wgpu::CommandBuffer> {
+        let imported = [HDR, DEPTH, OUTPUT];
+
+        // Setup
+        let mut builders: Vec<(usize, PassBuilder)> = vec![];
+        for (index, pass) in self.passes.iter().enumerate() {
+            if pass.enabled(ctx) {
+                let mut builder = PassBuilder::default();
+                pass.setup(ctx, &mut builder);
+                builders.push((index, builder));
+            }
+        }
+
+        // Dependencies. A pass depends on the last pass writing each resource it uses, and a
+        // pass writing a resource also on the passes reading it since. These always point to
+        // earlier passes, so the order passes were added in is a valid execution order.
+        let mut dependencies: Vec<HashSet<usize>> = vec![HashSet::new(); builders.len()];
+        let mut last_writer: HashMap<&str, usize> = HashMap::new();
+        let mut readers: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, (index, builder)) in builders.iter().enumerate() {
+            for name in &builder.reads {
+                match last_writer.get(name.as_str()) {
+                    Some(&writer) => { dependencies[i].insert(writer); }
+                    None if !imported.contains(&name.as_str()) => {
+                        log::warn!("Render pass {} reads {}, which no earlier pass writes", self.passes[*index].name(), name);
+                    }
+                    None => {}
+                }
+                readers.entry(name).or_default().push(i);
+            }
+            let written = builder.writes.iter().chain(builder.creates.iter().map(|(name, _)| name));
+            for name in written {
+                dependencies[i].extend(last_writer.get(name.as_str()));
+                dependencies[i].extend(readers.remove(name.as_str()).unwrap_or_default().into_iter().filter(|&r| r != i));
+                last_writer.insert(name, i);
+            }
+        }
+
+        // Culling. The imported textures are used outside the graph, so the passes leading
+        // to their final contents are needed. Passes only feeding unused resources are dropped.
+        let mut needed = vec![false; builders.len()];
+        let mut stack: Vec<usize> = imported.iter().filter_map(|name| last_writer.get(name).copied()).collect();
+        while let Some(i) = stack.pop() {
+            if !needed[i] {
+                needed[i] = true;
+                stack.extend(dependencies[i].iter().copied());
+            }
+        }
+        let order: Vec<usize> = (0..builders.len()).filter(|&i| needed[i]).collect();
+
+        // Transient lifetimes, as (first, last) positions in the execution order
+        let mut transients: Vec<(&str, &ResourceDesc, usize, usize)> = vec![];
+        for (position, &i) in order.iter().enumerate() {
+            let builder = &builders[i].1;
+            for (name, desc) in &builder.creates {
+                transients.push((name, desc, position, position));
+            }
+            for name in builder.reads.iter().chain(&builder.writes) {
+                if let Some(transient) = transients.iter_mut().find(|t| t.0 == name) {
+                    transient.3 = position;
+                }
+            }
+        }
+
+        // Allocation. Each transient takes a pooled resource with the same description that's
+        // free by then, or a new one. Pooled resources are released in end_frame.
+        let mut busy_until: Vec<Option<usize>> = vec![None; self.pool.len()];
+        let mut assigned: Vec<(&str, usize)> = vec![];
+        for &(name, desc, first, last) in &transients {
+            let free = (0..self.pool.len()).find(|&p| {
+                self.pool[p].desc == *desc && busy_until[p].is_none_or(|until| until < first)
+            });
+            let p = free.unwrap_or_else(|| {
+                self.pool.push(PooledResource { desc: desc.clone(), resource: create_resource(ctx.device, name, desc), used: false });
+                busy_until.push(None);
+                self.pool.len() - 1
+            });
+            busy_until[p] = Some(last);
+            self.pool[p].used = true;
+            assigned.push((name, p));
+        }
+
+        // Execution
+        let hdr_view = ctx.hdr_pipeline.texture().create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = ctx.hdr_pipeline.depth_texture().create_view(&wgpu::TextureViewDescriptor::default());
+        let output_view = ctx.output.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut resources = GraphResources {
+            textures: HashMap::from([
+                (HDR, (ctx.hdr_pipeline.texture(), &hdr_view)),
+                (DEPTH, (ctx.hdr_pipeline.depth_texture(), &depth_view)),
+                (OUTPUT, (ctx.output, &output_view)),
+            ]),
+            buffers: HashMap::new(),
+        };
+        for &(name, p) in &assigned {
+            match &self.pool[p].resource {
+                GraphResource::Texture { texture, view } => { resources.textures.insert(name, (texture, view)); }
+                GraphResource::Buffer(buffer) => { resources.buffers.insert(name, buffer); }
+            }
+        }
+
+        let mut command_buffers = vec![];
+        for &i in &order {
+            let pass = &mut self.passes[builders[i].0];
+            let _span = tracing::info_span!("render_pass", name = pass.name()).entered();
+            command_buffers.extend(pass.run(ctx, &resources));
+        }
+        drop(resources);
+        command_buffers
+    }
+
+    // Releases the pooled resources that no view used since the previous call, e.g. after
+    // a pass was disabled or the window was resized. Called once per frame, after all views
+    // are rendered, so views with different sizes don't free each other's resources.
+    pub fn end_frame(&mut self) {
+        self.pool.retain_mut(|resource| std::mem::take(&mut resource.used));
+        for pass in &mut self.passes {
+            pass.end_frame();
+        }
+    }
+}
+
+fn create_resource(device: &Device, name: &str, desc: &ResourceDesc) -> GraphResource {
+    match desc {
+        ResourceDesc::Texture(desc) => {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(name),
+                size: wgpu::Extent3d {
+                    width: desc.width,
+                    height: desc.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: desc.format,
+                usage: desc.usage,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            GraphResource::Texture { texture, view }
+        }
+        ResourceDesc::Buffer(desc) => GraphResource::Buffer(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(name),
+            size: desc.size,
+            usage: desc.usage,
+            mapped_at_creation: false,
+        })),
+    }
+}
@@ -6,16 +6,23 @@ use crate::{math::Rect, texture};
 
 use super::{shader_utils, utils};
 
-/// Owns the render texture and controls tonemapping
+// The colour and depth textures for one target size
+struct HdrTargets {
+    texture: texture::Texture,
+    depth_texture: texture::Texture,
+    bind_group: wgpu::BindGroup,
+    // Selected since the last end_frame
+    used: bool,
+}
+
+/// Owns the render textures and controls tonemapping
 pub struct HdrPipeline {
     pipeline: wgpu::RenderPipeline,
-    bind_group: wgpu::BindGroup,
-    texture: texture::Texture,
-    width: u32,
-    height: u32,
     layout: wgpu::BindGroupLayout,
-
-    depth_texture: texture::Texture 
+    // One set of textures per target size, so cameras rendering to targets of
+    // different sizes in the same frame don't reallocate them on every switch
+    targets: HashMap<(u32, u32), HdrTargets>,
+    size: (u32, u32),
 }
 
 impl HdrPipeline {
@@ -27,7 +34,6 @@ impl HdrPipeline {
         webxr: bool
     ) -> Self {
 
-        let texture = Self::create_color_texture(device, width, height);
         let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Hdr::layout"),
             entries: &[
@@ -50,21 +56,6 @@ impl HdrPipeline {
                 },
             ],
         });
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Hdr::bind_group"),
-            layout: &layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
-                },
-            ],
-        });
-
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
             bind_group_layouts: &[&layout],
@@ -94,17 +85,35 @@ impl HdrPipeline {
             )
         };
 
-        let depth_texture = texture::Texture::create_depth_texture(&device, width, height, "depth_texture");
-
-        Self {
+        let mut hdr = Self {
             pipeline,
-            bind_group,
             layout,
-            texture,
-            width,
-            height,
-            depth_texture
-        }
+            targets: HashMap::new(),
+            size: (width, height),
+        };
+        hdr.resize(device, width, height);
+        hdr
+    }
+
+    fn create_targets(&self, device: &wgpu::Device, width: u32, height: u32) -> HdrTargets {
+        log::info!("Creating hdr textures: {}x{}", width, height);
+        let texture = Self::create_color_texture(device, width, height);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Hdr::bind_group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+        });
+        let depth_texture = texture::Texture::create_depth_texture(device, width, height, "depth_texture");
+        HdrTargets { texture, depth_texture, bind_group, used: true }
     }
 
     fn create_color_texture(device: &wgpu::Device, width: u32, height: u32) -> texture::Texture {
@@ -116,58 +125,53 @@ impl HdrPipeline {
             width,
             height,
             format,
-            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            // COPY_SRC so captures can read back the untonemapped frame
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
             wgpu::FilterMode::Nearest,
             Some("Hdr::texture")
         )
     }
 
-    // Resize the colour and depth textures if needed
+    // Select the colour and depth textures for this size, creating them the first time it's seen
     pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
-        if self.texture.texture.width() != width || self.texture.texture.height() != height {
-            log::warn!("Resizing hdr color texture: {}x{}", width, height);
-            self.texture = Self::create_color_texture(device, width, height);
-            self.bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("Hdr::bind_group"),
-                layout: &self.layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&self.texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&self.texture.sampler),
-                    },
-                ],
-            });
-        }
-        if self.depth_texture.texture.width() != width || self.depth_texture.texture.height() != height {  
-            log::warn!("Resizing hdr depth texture: {}x{}", width, height);
-            self.depth_texture = texture::Texture::create_depth_texture(&device, width, height, "depth_texture");
+        if !self.targets.contains_key(&(width, height)) {
+            let targets = self.create_targets(device, width, height);
+            self.targets.insert((width, height), targets);
         }
-        self.width = width;
-        self.height = height;
+        self.targets.get_mut(&(width, height)).unwrap().used = true;
+        self.size = (width, height);
+    }
+
+    // Drop the textures of sizes that no camera rendered to since the last call.
+    // Called once per frame, after all views are rendered.
+    pub fn end_frame(&mut self) {
+        let size = self.size;
+        self.targets.retain(|key, targets| std::mem::take(&mut targets.used) || *key == size);
+    }
+
+    fn current(&self) -> &HdrTargets {
+        &self.targets[&self.size]
     }
 
     /// Exposes the HDR texture
     pub fn texture(&self) -> &wgpu::Texture {
-        &self.texture.texture
+        &self.current().texture.texture
     }
 
     /// The format of the HDR texture
     pub fn format(&self) -> wgpu::TextureFormat {
-        self.texture.texture.format()
+        self.current().texture.texture.format()
     }
 
     /// Exposes the HDR texture
     pub fn depth_texture(&self) -> &wgpu::Texture {
-        &self.depth_texture.texture
+        &self.current().depth_texture.texture
     }
 
     // This renders the internal HDR texture to the supplied TextureView
-    // The viewport is supplied in WebXR mode
-    pub fn process(&self, device: &wgpu::Device, output: &wgpu::TextureView, viewport: Option<Rect>) -> wgpu::CommandBuffer {
+    // The viewport is supplied in WebXR mode, and for cameras that render to part of their target.
+    // With clear the whole output is cleared first, otherwise what's outside the viewport is kept.
+    pub fn process(&self, device: &wgpu::Device, output: &wgpu::TextureView, viewport: Option<Rect>, clear: bool) -> wgpu::CommandBuffer {
 
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Hdr::command_encoder"),
@@ -180,7 +184,7 @@ impl HdrPipeline {
                     view: &output,
                     resolve_target: None,
                     ops: Operations {
-                        load: wgpu::LoadOp::Load,
+                        load: if clear { wgpu::LoadOp::Clear(wgpu::Color::BLACK) } else { wgpu::LoadOp::Load },
                         store: true,
                     },
                 })],
@@ -193,7 +197,7 @@ impl HdrPipeline {
             };
 
             pass.set_pipeline(&self.pipeline);
-            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.set_bind_group(0, &self.current().bind_group, &[]);
             pass.draw(0..3, 0..1);
         }
 
@@ -0,0 +1,263 @@
+use bevy_ecs::prelude::*;
+
+use crate::{
+    components::{Camera, Transform},
+    device::Device,
+    math::{Mat4, Vec3f},
+    model::Vertex,
+    texture,
+};
+
+use super::{
+    graph,
+    graph::{GraphPass, GraphResources, PassBuilder, RenderContext},
+    shader_utils,
+    skybox::new_uniform_bind_group,
+};
+
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+impl Vertex for LineVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+/// Lines to draw this frame. Anything can push lines here during the update,
+/// they're drawn by the LinePass after the phong pass and cleared at the start of the next frame.
+#[derive(Resource, Default)]
+pub struct DebugLines {
+    // Hidden behind scene geometry
+    pub depth_tested: Vec<LineVertex>,
+    // Always drawn on top
+    pub overlay: Vec<LineVertex>,
+}
+
+impl DebugLines {
+    pub fn add(&mut self, a: Vec3f, b: Vec3f, color: [f32; 4], depth_test: bool) {
+        let lines = if depth_test { &mut self.depth_tested } else { &mut self.overlay };
+        lines.push(LineVertex { position: a.into(), color });
+        lines.push(LineVertex { position: b.into(), color });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.depth_tested.is_empty() && self.overlay.is_empty()
+    }
+
+    pub fn clear(mut lines: ResMut<DebugLines>) {
+        lines.depth_tested.clear();
+        lines.overlay.clear();
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Uniform {
+    view_proj: [[f32; 4]; 4],
+}
+
+pub struct LinePass {
+    depth_tested_pipeline: wgpu::RenderPipeline,
+    overlay_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    vertex_buffer: Option<wgpu::Buffer>,
+}
+
+impl LinePass {
+    pub fn new(
+        device: &Device,
+        color_format: wgpu::TextureFormat,
+    ) -> Self {
+        let uniform = Uniform { view_proj: Mat4::identity().into() };
+        let (uniform_bind_group_layout, uniform_bind_group, uniform_buffer) =
+            new_uniform_bind_group(device, bytemuck::cast_slice(&[uniform]));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("[Lines] Pipeline"),
+            bind_group_layouts: &[&uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let mut shader_composer = shader_utils::init_composer();
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Lines Shader"),
+            source: wgpu::ShaderSource::Naga(std::borrow::Cow::Owned(
+                shader_utils::load_shader!(&mut shader_composer, "lines.wgsl", None)
+            )),
+        });
+
+        let create_pipeline = |label, depth_compare| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[LineVertex::desc()],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..Default::default()
+                },
+                // Lines test against the scene depth but don't write to it
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                multisample: Default::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            })
+        };
+
+        let depth_tested_pipeline = create_pipeline("[Lines] Depth tested", wgpu::CompareFunction::LessEqual);
+        let overlay_pipeline = create_pipeline("[Lines] Overlay", wgpu::CompareFunction::Always);
+
+        Self {
+            depth_tested_pipeline,
+            overlay_pipeline,
+            uniform_buffer,
+            uniform_bind_group,
+            vertex_buffer: None,
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        color_view: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+        device: &Device,
+        camera: (&Camera, &Transform),
+        lines: &DebugLines,
+    ) -> wgpu::CommandBuffer {
+        let uniform = Uniform { view_proj: camera.0.view_proj(camera.1).into() };
+        device.queue().write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        // Both line lists share one vertex buffer, depth tested lines first
+        let num_depth_tested = lines.depth_tested.len() as u32;
+        let num_overlay = lines.overlay.len() as u32;
+        if num_depth_tested + num_overlay > 0 {
+            let vertex_size = std::mem::size_of::<LineVertex>() as wgpu::BufferAddress;
+            let required_size = vertex_size * (num_depth_tested + num_overlay) as u64;
+            // Reallocate the vertex buffer if it's too small
+            if !matches!(&self.vertex_buffer, Some(b) if b.size() >= required_size) {
+                self.vertex_buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("[Lines] Vertex Buffer"),
+                    size: required_size,
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }));
+            }
+            let vertex_buffer = self.vertex_buffer.as_ref().unwrap();
+            if num_depth_tested > 0 {
+                device.queue().write_buffer(vertex_buffer, 0, bytemuck::cast_slice(&lines.depth_tested));
+            }
+            if num_overlay > 0 {
+                device.queue().write_buffer(
+                    vertex_buffer,
+                    vertex_size * num_depth_tested as u64,
+                    bytemuck::cast_slice(&lines.overlay),
+                );
+            }
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Lines Encoder"),
+        });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Lines Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: color_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            if let Some(vertex_buffer) = &self.vertex_buffer {
+                render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                if num_depth_tested > 0 {
+                    render_pass.set_pipeline(&self.depth_tested_pipeline);
+                    render_pass.draw(0..num_depth_tested, 0..1);
+                }
+                if num_overlay > 0 {
+                    render_pass.set_pipeline(&self.overlay_pipeline);
+                    render_pass.draw(num_depth_tested..num_depth_tested + num_overlay, 0..1);
+                }
+            }
+        }
+        encoder.finish()
+    }
+}
+
+// Debug lines on top of the scene, before tonemapping
+impl GraphPass for LinePass {
+    fn name(&self) -> &str {
+        "lines"
+    }
+
+    fn enabled(&self, ctx: &RenderContext) -> bool {
+        ctx.draws_scene() && !ctx.debug_lines.is_empty()
+    }
+
+    fn setup(&self, _ctx: &RenderContext, builder: &mut PassBuilder) {
+        builder.write(graph::HDR).read(graph::DEPTH);
+    }
+
+    fn run(&mut self, ctx: &RenderContext, resources: &GraphResources) -> Vec<wgpu::CommandBuffer> {
+        vec![self.draw(
+            resources.view(graph::HDR),
+            resources.view(graph::DEPTH),
+            ctx.device,
+            ctx.camera,
+            ctx.debug_lines,
+        )]
+    }
+}
@@ -0,0 +1,625 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    assets::Assets,
+    components::{Camera, ShaderMaterial, Transform},
+    device::Device,
+    frame_time::FrameTime,
+    model,
+    model::{DrawModel, Model, Vertex},
+    texture,
+};
+
+use super::{
+    graph,
+    graph::{GraphPass, GraphResources, PassBuilder, RenderContext},
+    instance,
+    instance::InstanceRaw,
+    phong::{camera_uniform, CameraUniform},
+    shader_utils,
+};
+
+// Declarations appended to every material shader. Besides these, shaders can #import utils.
+const MATERIAL_PRELUDE: &str = r#"
+struct MaterialCamera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> camera: MaterialCamera;
+
+struct MaterialGlobals {
+    // Seconds since the start
+    time: f32,
+    delta_time: f32,
+    frame: u32,
+    // Size of the render target in pixels
+    resolution: vec2<f32>,
+}
+@group(0) @binding(1)
+var<uniform> globals: MaterialGlobals;
+
+@group(1) @binding(1)
+var material_sampler: sampler;
+
+struct MaterialVertex {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
+}
+
+struct MaterialInstance {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    @location(9) normal_matrix_0: vec3<f32>,
+    @location(10) normal_matrix_1: vec3<f32>,
+    @location(11) normal_matrix_2: vec3<f32>,
+}
+
+struct MaterialVertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+fn model_matrix(instance: MaterialInstance) -> mat4x4<f32> {
+    return mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+}
+
+fn normal_matrix(instance: MaterialInstance) -> mat3x3<f32> {
+    return mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+}
+
+// The standard vertex transform, for shaders that only customise the fragment stage
+fn material_vertex(vertex: MaterialVertex, instance: MaterialInstance) -> MaterialVertexOutput {
+    let world_position = model_matrix(instance) * vec4<f32>(vertex.position, 1.0);
+    var out: MaterialVertexOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    out.world_normal = normalize(normal_matrix(instance) * vertex.normal);
+    out.uv = vertex.tex_coords;
+    return out;
+}
+"#;
+
+// Used when the shader doesn't have its own vs_main
+const DEFAULT_VERTEX_SHADER: &str = r#"
+@vertex
+fn vs_main(vertex: MaterialVertex, instance: MaterialInstance) -> MaterialVertexOutput {
+    return material_vertex(vertex, instance);
+}
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct GlobalsUniform {
+    time: f32,
+    delta_time: f32,
+    frame: u32,
+    _padding: u32,
+    resolution: [f32; 2],
+    _padding2: [f32; 2],
+}
+
+// Instances of a model to draw with a material
+pub struct MaterialNode<'a> {
+    pub model: &'a Model,
+    pub material: &'a ShaderMaterial,
+    pub transforms: Vec<&'a Transform>,
+}
+
+struct MaterialPipeline {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+}
+
+// Draws models with ShaderMaterials. The pipeline for each material layout (shader, params and
+// texture slots, see ShaderMaterial::layout_key) is built the first time it's drawn and cached.
+//
+// Bindings, in addition to the generated declarations in MATERIAL_PRELUDE:
+//   group 0: camera, globals
+//   group 1: material (a struct with the params, in a 16 byte slot each), material_sampler,
+//            then one texture_2d<f32> per texture slot, named after the slot
+//   vertex buffers: MaterialVertex and MaterialInstance
+// The entry points are vs_main (optional) and fs_main.
+pub struct MaterialPass {
+    color_format: wgpu::TextureFormat,
+    camera_buffer: wgpu::Buffer,
+    globals_buffer: wgpu::Buffer,
+    globals_bind_group_layout: wgpu::BindGroupLayout,
+    globals_bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    pipelines: HashMap<String, MaterialPipeline>,
+    // Layouts whose shader is missing or didn't compile, so the error is only logged once
+    failed: HashSet<String>,
+    // Keyed by layout and texture names
+    bind_groups: HashMap<String, wgpu::BindGroup>,
+    // All materials' params for a frame, at dynamic offsets
+    params_buffer: Option<wgpu::Buffer>,
+    instance_buffer: Option<wgpu::Buffer>,
+}
+
+impl MaterialPass {
+    pub fn new(
+        device: &Device,
+        color_format: wgpu::TextureFormat,
+    ) -> Self {
+        let uniform_entry = |binding, size| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(size),
+            },
+            count: None,
+        };
+        let camera_size = std::mem::size_of::<CameraUniform>() as wgpu::BufferAddress;
+        let globals_size = std::mem::size_of::<GlobalsUniform>() as wgpu::BufferAddress;
+        let globals_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("[Material] Globals"),
+            entries: &[uniform_entry(0, camera_size), uniform_entry(1, globals_size)],
+        });
+
+        let create_uniform_buffer = |label, size| device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_buffer = create_uniform_buffer("[Material] Camera", camera_size);
+        let globals_buffer = create_uniform_buffer("[Material] Globals", globals_size);
+        let globals_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("[Material] Globals"),
+            layout: &globals_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: globals_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("[Material] sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            color_format,
+            camera_buffer,
+            globals_buffer,
+            globals_bind_group_layout,
+            globals_bind_group,
+            sampler,
+            pipelines: Default::default(),
+            failed: Default::default(),
+            bind_groups: Default::default(),
+            params_buffer: None,
+            instance_buffer: None,
+        }
+    }
+
+    // Updates the camera and the globals the shaders see, before drawing a view
+    pub fn write_globals(&self, device: &Device, camera: (&Camera, &Transform), frame_time: &FrameTime, resolution: (u32, u32)) {
+        let queue = device.queue();
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera_uniform(camera.0, camera.1)]));
+        let globals = GlobalsUniform {
+            time: frame_time.elapsed,
+            delta_time: frame_time.delta,
+            frame: frame_time.frame_count,
+            resolution: [resolution.0 as f32, resolution.1 as f32],
+            ..Default::default()
+        };
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::cast_slice(&[globals]));
+    }
+
+    pub fn draw(
+        &mut self,
+        color_view: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+        device: &Device,
+        assets: &Assets,
+        nodes: &[MaterialNode],
+    ) -> wgpu::CommandBuffer {
+        let queue = device.queue();
+
+        // Skip nodes whose pipeline can't be built
+        let nodes: Vec<&MaterialNode> = nodes.iter()
+            .filter(|node| self.prepare_pipeline(device, assets, node.material))
+            .collect();
+
+        // Params of all nodes go into one buffer, each at an aligned offset with room for the largest
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let max_params_size = nodes.iter()
+            .map(|node| params_size(node.material))
+            .max()
+            .unwrap_or(0);
+        let stride = max_params_size.div_ceil(alignment).max(1) * alignment;
+        let params_offsets: Vec<u64> = (0..nodes.len() as u64).map(|i| i * stride).collect();
+        let mut params_data = vec![0u8; (stride * nodes.len() as u64) as usize];
+        for (node, &offset) in nodes.iter().zip(&params_offsets) {
+            let data = shader_utils::params_data(&node.material.params);
+            let bytes: &[u8] = bytemuck::cast_slice(&data);
+            params_data[offset as usize..offset as usize + bytes.len()].copy_from_slice(bytes);
+        }
+        let required_params_size = (params_data.len() as u64).max(alignment);
+        // Reallocate the params buffer if it's too small. The cached bind groups point at the old one.
+        if !matches!(&self.params_buffer, Some(b) if b.size() >= required_params_size) {
+            self.params_buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("[Material] Params"),
+                size: required_params_size,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+            self.bind_groups.clear();
+        }
+        if !params_data.is_empty() {
+            queue.write_buffer(self.params_buffer.as_ref().unwrap(), 0, &params_data);
+        }
+
+        // Instances of all nodes go into one buffer too
+        let instance_data: Vec<InstanceRaw> = nodes.iter()
+            .flat_map(|node| node.transforms.iter().map(instance::instance_raw))
+            .collect();
+        let instance_size = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+        let required_instance_size = instance_size * instance_data.len() as u64;
+        if !matches!(&self.instance_buffer, Some(b) if b.size() >= required_instance_size) {
+            self.instance_buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("[Material] Instance Buffer"),
+                size: required_instance_size,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        if !instance_data.is_empty() {
+            queue.write_buffer(self.instance_buffer.as_ref().unwrap(), 0, bytemuck::cast_slice(&instance_data));
+        }
+
+        let bind_group_keys: Vec<Option<String>> = nodes.iter()
+            .map(|node| self.prepare_bind_group(device, assets, node.material))
+            .collect();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Materials Encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Materials Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: color_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_bind_group(0, &self.globals_bind_group, &[]);
+
+            let mut first_instance = 0;
+            for ((node, offset), bind_group_key) in nodes.iter().zip(&params_offsets).zip(&bind_group_keys) {
+                let instances = first_instance..first_instance + node.transforms.len() as u32;
+                first_instance = instances.end;
+                let Some(bind_group_key) = bind_group_key else {
+                    continue;
+                };
+                let pipeline = &self.pipelines[&node.material.layout_key()];
+                render_pass.set_pipeline(&pipeline.pipeline);
+                render_pass.set_bind_group(1, &self.bind_groups[bind_group_key], &[*offset as u32]);
+                render_pass.set_vertex_buffer(1, self.instance_buffer.as_ref().unwrap().slice(..));
+                render_pass.draw_model_instanced(node.model, instances);
+            }
+        }
+        encoder.finish()
+    }
+
+    // Builds the pipeline for the material's layout if needed. Returns false if it can't be built.
+    fn prepare_pipeline(&mut self, device: &Device, assets: &Assets, material: &ShaderMaterial) -> bool {
+        let layout_key = material.layout_key();
+        if self.pipelines.contains_key(&layout_key) {
+            return true;
+        }
+        if self.failed.contains(&layout_key) {
+            return false;
+        }
+        let _span = tracing::info_span!("build_material_pipeline", shader = material.shader).entered();
+        match self.create_pipeline(device, assets, material) {
+            Ok(pipeline) => {
+                self.pipelines.insert(layout_key, pipeline);
+                true
+            }
+            Err(e) => {
+                log::error!("Couldn't build material shader {}: {}", material.shader, e);
+                self.failed.insert(layout_key);
+                false
+            }
+        }
+    }
+
+    fn create_pipeline(&self, device: &Device, assets: &Assets, material: &ShaderMaterial) -> anyhow::Result<MaterialPipeline> {
+        let source = assets.shader_store.get(&material.shader)
+            .ok_or_else(|| anyhow::anyhow!("not in the asset shader store"))?;
+        let source = material_source(source, material);
+
+        let module = shader_utils::try_make_module(&material.shader, &source, Default::default())?;
+
+        // Naga accepts modules that still can't be used here, e.g. without fs_main or with
+        // bindings that don't match the layout. Catch those instead of the device panicking.
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let texture_entries = (0..material.textures.len() as u32).map(|i| wgpu::BindGroupLayoutEntry {
+            binding: 2 + i,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        });
+        let entries: Vec<_> = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(params_size(material)),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ].into_iter().chain(texture_entries).collect();
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("[Material] Locals"),
+            entries: &entries,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("[Material] Pipeline"),
+            bind_group_layouts: &[&self.globals_bind_group_layout, &layout],
+            push_constant_ranges: &[],
+        });
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&material.shader),
+            source: wgpu::ShaderSource::Naga(std::borrow::Cow::Owned(module)),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("[Material] Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[model::ModelVertex::desc(), InstanceRaw::desc()],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: Default::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: self.color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+        // Resolves immediately with the wgpu-core backends
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            anyhow::bail!("{}", error);
+        }
+        Ok(MaterialPipeline { pipeline, layout })
+    }
+
+    // Creates the bind group for the material's textures if needed and returns its key,
+    // or None if one of the textures is missing
+    fn prepare_bind_group(&mut self, device: &Device, assets: &Assets, material: &ShaderMaterial) -> Option<String> {
+        let key = format!("{}|{:?}", material.layout_key(), material.textures);
+        if self.bind_groups.contains_key(&key) {
+            return Some(key);
+        }
+        let textures = material.textures.iter()
+            .map(|(_, name)| assets.texture_store.get(name).ok_or(name))
+            .collect::<Result<Vec<_>, _>>();
+        let textures = match textures {
+            Ok(textures) => textures,
+            Err(name) => {
+                log::warn!("Texture {} for material {} not found", name, material.shader);
+                return None;
+            }
+        };
+        let params_buffer = self.params_buffer.as_ref().unwrap();
+        let entries: Vec<_> = [
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: params_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(params_size(material)),
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ].into_iter().chain(textures.iter().enumerate().map(|(i, texture)| wgpu::BindGroupEntry {
+            binding: 2 + i as u32,
+            resource: wgpu::BindingResource::TextureView(&texture.view),
+        })).collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("[Material] Locals"),
+            layout: &self.pipelines[&material.layout_key()].layout,
+            entries: &entries,
+        });
+        self.bind_groups.insert(key.clone(), bind_group);
+        Some(key)
+    }
+}
+
+// Custom shader materials, depth tested against the phong models
+impl GraphPass for MaterialPass {
+    fn name(&self) -> &str {
+        "materials"
+    }
+
+    fn enabled(&self, ctx: &RenderContext) -> bool {
+        ctx.draws_scene() && !ctx.material_nodes.is_empty()
+    }
+
+    fn setup(&self, _ctx: &RenderContext, builder: &mut PassBuilder) {
+        builder.write(graph::HDR).write(graph::DEPTH);
+    }
+
+    fn run(&mut self, ctx: &RenderContext, resources: &GraphResources) -> Vec<wgpu::CommandBuffer> {
+        self.write_globals(ctx.device, ctx.camera, ctx.frame_time, ctx.view_size());
+        vec![self.draw(
+            resources.view(graph::HDR),
+            resources.view(graph::DEPTH),
+            ctx.device,
+            ctx.assets,
+            ctx.material_nodes,
+        )]
+    }
+}
+
+fn params_size(material: &ShaderMaterial) -> u64 {
+    (shader_utils::params_data(&material.params).len() * std::mem::size_of::<[f32; 4]>()) as u64
+}
+
+// The shader source with the prelude and the declarations for the material's params and textures
+fn material_source(source: &str, material: &ShaderMaterial) -> String {
+    let textures: Vec<String> = material.textures.iter().enumerate()
+        .map(|(i, (slot, _))| format!("@group(1) @binding({})\nvar {}: texture_2d<f32>;", 2 + i, slot))
+        .collect();
+
+    let mut source = format!(
+        "{}\n{}\n{}@group(1) @binding(0)\nvar<uniform> material: MaterialParams;\n{}\n",
+        source,
+        MATERIAL_PRELUDE,
+        shader_utils::params_struct("MaterialParams", &material.params),
+        textures.join("\n"),
+    );
+    if !source.contains("fn vs_main") {
+        source.push_str(DEFAULT_VERTEX_SHADER);
+    }
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::BUILTIN_CUBE;
+    use crate::components::ShaderParam;
+    use crate::math::{Vec3f, Vec4f};
+    use crate::testing::headless_device;
+
+    const COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    // None without an adapter, skipping the test
+    fn device_and_assets() -> Option<(Device, Assets)> {
+        let device = headless_device()?;
+        let mut assets = Assets::builtin(&device);
+        assets.shader_store.insert("flat.wgsl".to_string(), r#"
+            @fragment
+            fn fs_main(in: MaterialVertexOutput) -> @location(0) vec4<f32> {
+                return material.p0;
+            }"#.to_string());
+        // Passes naga validation, but a material pipeline needs a fragment stage
+        assets.shader_store.insert("no_fragment.wgsl".to_string(), "fn unused() {}".to_string());
+        Some((device, assets))
+    }
+
+    #[test]
+    fn unusable_shader_is_skipped() {
+        let Some((device, assets)) = device_and_assets() else {
+            return;
+        };
+        let mut pass = MaterialPass::new(&device, COLOR_FORMAT);
+        let material = ShaderMaterial::new("no_fragment.wgsl");
+        assert!(!pass.prepare_pipeline(&device, &assets, &material));
+        assert!(pass.failed.contains(&material.layout_key()));
+        assert!(pass.prepare_pipeline(&device, &assets, &ShaderMaterial::new("flat.wgsl")
+            .with_param("p0", ShaderParam::Vec4(Vec4f::zeros()))));
+    }
+
+    #[test]
+    fn params_larger_than_the_alignment_dont_overlap() {
+        let Some((device, assets)) = device_and_assets() else {
+            return;
+        };
+        let mut pass = MaterialPass::new(&device, COLOR_FORMAT);
+        // More than min_uniform_buffer_offset_alignment bytes of params per material
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as usize;
+        let materials: Vec<ShaderMaterial> = (0..3).map(|i| {
+            (0..alignment / 16 + 1).fold(ShaderMaterial::new("flat.wgsl"), |material, p| {
+                material.with_param(&format!("p{}", p), ShaderParam::Vec4(Vec4f::from_element(i as f32)))
+            })
+        }).collect();
+        let model = &assets.model_store[BUILTIN_CUBE];
+        let transform = Transform::from_position(Vec3f::new(0.0, 0.0, 3.0));
+        let nodes: Vec<MaterialNode> = materials.iter()
+            .map(|material| MaterialNode { model, material, transforms: vec![&transform] })
+            .collect();
+
+        let color = texture::Texture::create_2d_texture(&device, 64, 64, COLOR_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT, wgpu::FilterMode::Nearest, None);
+        let depth = texture::Texture::create_depth_texture(&device, 64, 64, "depth");
+        let camera = Camera::new(64, 64, 1.0, 0.1, 100.0);
+        pass.write_globals(&device, (&camera, &Transform::from_position(Vec3f::zeros())), &FrameTime::new(), (64, 64));
+        let command_buffer = pass.draw(&color.view, &depth.view, &device, &assets, &nodes);
+        device.queue().submit([command_buffer]);
+
+        let stride = pass.params_buffer.as_ref().unwrap().size() / 3;
+        assert!(stride >= params_size(&materials[0]));
+        assert_eq!(stride % alignment as u64, 0);
+    }
+}
@@ -1,10 +1,22 @@
+mod debug_view;
+pub mod graph;
 mod hdr;
 mod instance;
+mod lines;
+mod material;
 mod phong;
+mod post_process;
 mod shader_utils;
+mod sketch;
 mod skybox;
 mod utils;
 
+pub use debug_view::DebugView;
+pub use graph::{RenderContext, RenderGraph};
 pub use hdr::HdrPipeline;
-pub use phong::{PhongConfig, PhongPass};
+pub use lines::{DebugLines, LinePass};
+pub use material::{MaterialNode, MaterialPass};
+pub use phong::{PhongNode, PhongPass};
+pub use post_process::{PostEffect, PostProcess, PostProcessPass};
+pub use sketch::{Sketch, SketchMode, SketchPass};
 pub use skybox::SkyboxPass;
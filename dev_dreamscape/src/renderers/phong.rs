@@ -12,9 +12,12 @@ use crate::{
 };
 
 use super::{
+    graph,
+    graph::{GraphPass, GraphResources, PassBuilder, RenderContext},
     shader_utils,
     instance,
     instance::InstanceRaw,
+    DebugView,
 };
 
 
@@ -55,8 +58,21 @@ pub fn light_uniform(light: &Light, transform: &Transform) -> LightUniform {
     }
 }
 
-pub struct PhongConfig {
-    pub wireframe: bool,
+// Instances of a model to draw, optionally with its diffuse texture replaced
+pub struct PhongNode<'a> {
+    pub model: &'a Model,
+    pub modelname: &'a String,
+    pub diffuse_texture: Option<(&'a String, &'a texture::Texture)>,
+    pub transforms: Vec<&'a Transform>,
+}
+
+impl PhongNode<'_> {
+    fn bind_group_key(&self) -> String {
+        match self.diffuse_texture {
+            Some((texture_name, _)) => format!("{}#{}", self.modelname, texture_name),
+            None => self.modelname.to_string(),
+        }
+    }
 }
 
 pub struct PhongPass {
@@ -73,6 +89,10 @@ pub struct PhongPass {
     // TODO: make ModelSpec / Material id the key
     phong_local_bind_groups: HashMap<String, wgpu::BindGroup>,
     pub phong_render_pipeline: wgpu::RenderPipeline,
+    // Debug view pipelines - keyed by view, all except DebugView::Shaded
+    debug_render_pipelines: HashMap<DebugView, wgpu::RenderPipeline>,
+    // Wireframe falls back to drawing the mesh edge lists when PolygonMode::Line is unavailable
+    wireframe_uses_edges: bool,
     // Light pipeline
     pub light_global_bind_group_layout: BindGroupLayout,
     pub light_global_bind_group: wgpu::BindGroup,
@@ -81,7 +101,6 @@ pub struct PhongPass {
 
 impl PhongPass {
     pub fn new(
-        phong_config: &PhongConfig,
         device: &Device,
         color_format: wgpu::TextureFormat,
     ) -> Self {
@@ -281,15 +300,8 @@ impl PhongPass {
             bias: Default::default(),
         });
 
-        // Enable/disable wireframe mode
-        let topology = if phong_config.wireframe {
-            wgpu::PrimitiveTopology::LineList
-        } else {
-            wgpu::PrimitiveTopology::TriangleList
-        };
-
         let primitive = wgpu::PrimitiveState {
-            topology,
+            topology: wgpu::PrimitiveTopology::TriangleList,
             front_face: wgpu::FrontFace::Ccw,
             cull_mode: Some(wgpu::Face::Back),
             ..Default::default()
@@ -338,6 +350,23 @@ impl PhongPass {
             })
         };
 
+        let wireframe_uses_edges = !device.features().contains(wgpu::Features::POLYGON_MODE_LINE);
+        let debug_render_pipelines = DebugView::ALL
+            .into_iter()
+            .filter(|&view| view != DebugView::Shaded)
+            .map(|view| {
+                let pipeline = create_debug_render_pipeline(
+                    device,
+                    &phong_pipeline_layout,
+                    &mut shader_composer,
+                    view,
+                    wireframe_uses_edges,
+                    color_format,
+                );
+                (view, pipeline)
+            })
+            .collect();
+
         let light_render_pipeline = {
             let shader_desc = wgpu::ShaderModuleDescriptor {
                 label: Some("Light Shader"),
@@ -384,6 +413,8 @@ impl PhongPass {
             phong_local_bind_group_layout,
             phong_local_bind_groups: Default::default(),
             phong_render_pipeline,
+            debug_render_pipelines,
+            wireframe_uses_edges,
             
             light_global_bind_group,
             light_global_bind_group_layout,
@@ -397,10 +428,11 @@ impl PhongPass {
         depth_view: &wgpu::TextureView,
         device: &Device,
         queue: &Queue,
-        nodes: &Vec<(&Model, &String, Vec<&Transform>)>,
+        nodes: &[PhongNode],
         camera: (&Camera, &Transform),
-        lights: &Vec<(&Light, &Transform)>,
+        lights: &[(&Light, &Transform)],
         light_model: &Model,
+        debug_view: DebugView,
         clear_color: bool,
         clear_depth: bool
     ) -> wgpu::CommandBuffer {
@@ -461,18 +493,21 @@ impl PhongPass {
             // instance buffers per node to send to shader
             // This is separate loop from the render because of Rust ownership
             // (can prob wrap in block instead to limit mutable use)
-            for (model_index, (model, modelname, transforms)) in nodes.iter().enumerate() {
+            for (model_index, node) in nodes.iter().enumerate() {
+                let (model, transforms) = (node.model, &node.transforms);
                 // We create a bind group for each model's local uniform data
                 // and store it in a hash map to look up later
                 
                 //
                 // Bindgroup  management
 
-                // Bindgroups are indexed by modelname as we currently assume models have a fixed material
-                // (ideally we should index by a Material id)
+                // Bindgroups are indexed by modelname (and diffuse texture override) as we currently
+                // assume models have a fixed material (ideally we should index by a Material id)
                 let phong_local_bind_group_layout = &self.phong_local_bind_group_layout;
+                let diffuse_texture = node.diffuse_texture
+                    .map_or(&model.materials[0].diffuse_texture, |(_, texture)| texture);
                 self.phong_local_bind_groups
-                    .entry(modelname.to_string())
+                    .entry(node.bind_group_key())
                     .or_insert_with(|| {
                         device.create_bind_group(&wgpu::BindGroupDescriptor {
                             label: Some("[Phong] Locals"),
@@ -481,7 +516,7 @@ impl PhongPass {
                                 wgpu::BindGroupEntry {
                                     binding: 0,
                                     resource: wgpu::BindingResource::TextureView(
-                                        &model.materials[0].diffuse_texture.view,
+                                        &diffuse_texture.view,
                                     ),
                                 },
                                 wgpu::BindGroupEntry {
@@ -541,26 +576,161 @@ impl PhongPass {
             // to Lights array uniform buffer in the shader.
             render_pass.draw_model_instanced(light_model, 0..lights.len() as u32);
             
-            // Setup phong pipeline
-            render_pass.set_pipeline(&self.phong_render_pipeline);
+            // Setup phong pipeline, or the debug view pipeline which shares its layout
+            match debug_view {
+                DebugView::Shaded => render_pass.set_pipeline(&self.phong_render_pipeline),
+                _ => render_pass.set_pipeline(&self.debug_render_pipelines[&debug_view]),
+            }
             render_pass.set_bind_group(0, &self.phong_global_bind_group, &[]);
+            let draw_edges = debug_view == DebugView::Wireframe && self.wireframe_uses_edges;
 
             // Draw all node models
-            for (model_index, (model, modelname, transforms)) in nodes.iter().enumerate() {
+            for (model_index, node) in nodes.iter().enumerate() {
+                let (model, transforms) = (node.model, &node.transforms);
                 let required_instance_buffer_size = instance_size * transforms.len() as u64;
                 let instance_buffer = self.instance_buffers.get(&model_index).unwrap();
                 // It looks like we don't need to limit the bounds of the instance buffer slice,
                 // (probably because instance range passed to draw_model_instanced defines how much of the 
                 // buffer is read, but doing it anyway for sanity purposes.
                 render_pass.set_vertex_buffer(1, instance_buffer.slice(0..required_instance_buffer_size));
-                render_pass.set_bind_group(1, &self.phong_local_bind_groups[*modelname], &[]);
+                render_pass.set_bind_group(1, &self.phong_local_bind_groups[&node.bind_group_key()], &[]);
                 // Draw all the model instances
-                render_pass.draw_model_instanced(
-                    &model,
-                    0..transforms.len() as u32
-                );
+                if draw_edges {
+                    render_pass.draw_model_edges_instanced(
+                        model,
+                        0..transforms.len() as u32
+                    );
+                } else {
+                    render_pass.draw_model_instanced(
+                        model,
+                        0..transforms.len() as u32
+                    );
+                }
             }
         }
         encoder.finish()
     }
 }
+
+impl GraphPass for PhongPass {
+    fn name(&self) -> &str {
+        "phong"
+    }
+
+    fn enabled(&self, ctx: &RenderContext) -> bool {
+        ctx.draws_scene()
+    }
+
+    fn setup(&self, _ctx: &RenderContext, builder: &mut PassBuilder) {
+        builder.write(graph::HDR).write(graph::DEPTH);
+    }
+
+    fn run(&mut self, ctx: &RenderContext, resources: &GraphResources) -> Vec<wgpu::CommandBuffer> {
+        // TODO: don't hardcode. We rely on the same mode for all lights for instancing atm.
+        let light_model = ctx.assets.model_store.get("sphere.obj").unwrap();
+        vec![self.draw(
+            resources.view(graph::HDR),
+            resources.view(graph::DEPTH),
+            ctx.device,
+            &ctx.device.queue(),
+            ctx.nodes,
+            ctx.camera,
+            ctx.lights,
+            light_model,
+            ctx.debug_view,
+            !ctx.debug_view.draws_skybox(),
+            true,
+        )]
+    }
+}
+
+// Pipeline for one of the debug views. It uses the phong pipeline layout and vertex buffers
+// so it can be swapped in without touching the bind groups.
+fn create_debug_render_pipeline(
+    device: &Device,
+    layout: &wgpu::PipelineLayout,
+    shader_composer: &mut naga_oil::compose::Composer,
+    view: DebugView,
+    wireframe_uses_edges: bool,
+    color_format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    let shader_defs = HashMap::from([
+        ("DEBUG_VIEW".to_string(), ShaderDefValue::Int(view.shader_def()))
+    ]);
+    let shader_desc = wgpu::ShaderModuleDescriptor {
+        label: Some("Debug View Shader"),
+        source: wgpu::ShaderSource::Naga(std::borrow::Cow::Owned(
+            shader_utils::load_shader!(shader_composer, "debug_view.wgsl", Some(shader_defs))
+        ))
+    };
+    let shader_module = device.create_shader_module(shader_desc);
+
+    let primitive = match view {
+        DebugView::Wireframe if wireframe_uses_edges => wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::LineList,
+            ..Default::default()
+        },
+        DebugView::Wireframe => wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            polygon_mode: wgpu::PolygonMode::Line,
+            ..Default::default()
+        },
+        // Overdraw counts back faces too
+        DebugView::Overdraw => wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            ..Default::default()
+        },
+        _ => wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+    };
+
+    // Overdraw accumulates every fragment, so no depth testing and additive blending
+    let (depth_write_enabled, depth_compare, blend) = match view {
+        DebugView::Overdraw => (
+            false,
+            wgpu::CompareFunction::Always,
+            wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::REPLACE,
+            },
+        ),
+        _ => (true, wgpu::CompareFunction::LessEqual, wgpu::BlendState::REPLACE),
+    };
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("[Phong] Debug View Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &shader_module,
+            entry_point: "vs_main",
+            buffers: &[model::ModelVertex::desc(), InstanceRaw::desc()],
+        },
+        primitive,
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled,
+            depth_compare,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }),
+        multisample: Default::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader_module,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        multiview: None,
+    })
+}
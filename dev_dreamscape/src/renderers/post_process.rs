@@ -0,0 +1,650 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy_ecs::prelude::*;
+use naga_oil::compose::ShaderDefValue;
+
+use crate::{
+    assets::Assets,
+    components::ShaderParam,
+    device::Device,
+    math::Rect,
+};
+
+use super::{
+    graph,
+    graph::{GraphPass, GraphResources, PassBuilder, RenderContext, TextureDesc},
+    shader_utils,
+};
+
+// Transient textures in the render graph. Before tonemapping the chain ends in the hdr
+// texture, so one more hdr texture is enough. After tonemapping the chain starts from the
+// tonemapped image and ends in the output.
+const HDR_SCRATCH: &str = "post_process.hdr_scratch";
+const OUTPUT_SCRATCH: [&str; 2] = ["post_process.output_scratch_a", "post_process.output_scratch_b"];
+
+// Declarations appended to every effect shader, which provides effect(uv)
+const EFFECT_PRELUDE: &str = r#"
+struct PostGlobals {
+    // Size of the input in pixels
+    resolution: vec2<f32>,
+    // Size of the full frame in pixels, and where the input's top left corner is in it.
+    // The input is only part of the frame for poster tiles.
+    frame_resolution: vec2<f32>,
+    frame_offset: vec2<f32>,
+    // Seconds since the start
+    time: f32,
+}
+@group(0) @binding(0)
+var<uniform> post: PostGlobals;
+
+// Converts uv from the input to the full frame, and back
+fn frame_uv(uv: vec2<f32>) -> vec2<f32> {
+    return (uv * post.resolution + post.frame_offset) / post.frame_resolution;
+}
+fn input_uv(frame_uv: vec2<f32>) -> vec2<f32> {
+    return (frame_uv * post.frame_resolution - post.frame_offset) / post.resolution;
+}
+
+// Output of the previous effect in the chain
+@group(0) @binding(1)
+var input_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var input_sampler: sampler;
+
+@group(0) @binding(3)
+var<uniform> params: EffectParams;
+
+struct PostVertexOutput {
+    @location(0) uv: vec2<f32>,
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vi: u32) -> PostVertexOutput {
+    var out: PostVertexOutput;
+    // Generate a triangle that covers the whole screen
+    out.uv = vec2<f32>(f32((vi << 1u) & 2u), f32(vi & 2u));
+    out.clip_position = vec4<f32>(out.uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv.y = 1.0 - out.uv.y;
+    return out;
+}
+
+@fragment
+fn fs_main(in: PostVertexOutput) -> @location(0) vec4<f32> {
+    return effect(in.uv);
+}
+"#;
+
+// A post-processing effect. The fields are the effect's params, which can be changed every
+// frame to animate them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostEffect {
+    // Darkens by up to intensity (0..1) outside radius from the centre, in units of the
+    // image height, fading in over smoothness
+    Vignette { intensity: f32, radius: f32, smoothness: f32 },
+    // Separates red and blue towards the edges, by up to strength pixels
+    ChromaticAberration { strength: f32 },
+    // Animated noise
+    FilmGrain { intensity: f32 },
+    // Blocks of size pixels
+    Pixelate { size: f32 },
+    // Repeats a wedge of the image around the centre
+    Kaleidoscope { segments: f32, rotation: f32 },
+    // Dark outlines along edges in the luminance
+    EdgeDetect { strength: f32 },
+    // WGSL file in the asset shader store, providing
+    //     fn effect(uv: vec2<f32>) -> vec4<f32>
+    // which can sample input_texture with input_sampler, and read post.resolution,
+    // post.time and the params as params.<name>. Effects that depend on the position in the
+    // image should use frame_uv(uv) and post.frame_resolution, so poster tiles match.
+    Custom { shader: String, params: Vec<(String, ShaderParam)> },
+}
+
+impl PostEffect {
+    // Value of the EFFECT shader def in post_effects.wgsl, None for custom effects
+    fn shader_def(&self) -> Option<i32> {
+        match self {
+            PostEffect::Vignette { .. } => Some(1),
+            PostEffect::ChromaticAberration { .. } => Some(2),
+            PostEffect::FilmGrain { .. } => Some(3),
+            PostEffect::Pixelate { .. } => Some(4),
+            PostEffect::Kaleidoscope { .. } => Some(5),
+            PostEffect::EdgeDetect { .. } => Some(6),
+            PostEffect::Custom { .. } => None,
+        }
+    }
+
+    pub fn params(&self) -> Vec<(String, ShaderParam)> {
+        let float = |name: &str, value: f32| (name.to_string(), ShaderParam::Float(value));
+        match self {
+            PostEffect::Vignette { intensity, radius, smoothness } => vec![
+                float("intensity", *intensity),
+                float("radius", *radius),
+                float("smoothness", *smoothness),
+            ],
+            PostEffect::ChromaticAberration { strength } => vec![float("strength", *strength)],
+            PostEffect::FilmGrain { intensity } => vec![float("intensity", *intensity)],
+            PostEffect::Pixelate { size } => vec![float("size", *size)],
+            PostEffect::Kaleidoscope { segments, rotation } => vec![
+                float("segments", *segments),
+                float("rotation", *rotation),
+            ],
+            PostEffect::EdgeDetect { strength } => vec![float("strength", *strength)],
+            PostEffect::Custom { params, .. } => params.clone(),
+        }
+    }
+
+    // Identifies the shader: the built-in effect, or the custom shader and its params' types
+    fn layout_key(&self) -> String {
+        match self {
+            PostEffect::Custom { shader, params } => {
+                let params = params.iter()
+                    .map(|(name, param)| format!("{}:{}", name, param.wgsl_type()))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("{}|{}", shader, params)
+            }
+            _ => format!("effect {}", self.shader_def().unwrap()),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            PostEffect::Vignette { .. } => "vignette",
+            PostEffect::ChromaticAberration { .. } => "chromatic_aberration",
+            PostEffect::FilmGrain { .. } => "film_grain",
+            PostEffect::Pixelate { .. } => "pixelate",
+            PostEffect::Kaleidoscope { .. } => "kaleidoscope",
+            PostEffect::EdgeDetect { .. } => "edge_detect",
+            PostEffect::Custom { shader, .. } => shader,
+        }
+    }
+}
+
+// Post-processing effects, applied in order. The ones before tonemapping work on the linear
+// hdr colours, the ones after on the final colours.
+#[derive(Resource, Default)]
+pub struct PostProcess {
+    pub before_tonemap: Vec<PostEffect>,
+    pub after_tonemap: Vec<PostEffect>,
+}
+
+impl PostProcess {
+    pub fn is_empty(&self) -> bool {
+        self.before_tonemap.is_empty() && self.after_tonemap.is_empty()
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostGlobalsUniform {
+    resolution: [f32; 2],
+    frame_resolution: [f32; 2],
+    frame_offset: [f32; 2],
+    time: f32,
+    _padding: f32,
+}
+
+// Where an effect pass draws
+struct EffectTarget<'a> {
+    view: &'a wgpu::TextureView,
+    format: wgpu::TextureFormat,
+    viewport: Option<Rect>,
+    clear: bool,
+}
+
+impl<'a> EffectTarget<'a> {
+    // The whole texture, cleared first
+    fn new(view: &'a wgpu::TextureView, format: wgpu::TextureFormat) -> Self {
+        Self { view, format, viewport: None, clear: true }
+    }
+}
+
+// The tonemapping pass, with the PostProcess effects around it. Each effect is a fullscreen
+// pass from the previous effect's output into a scratch texture, ping-ponging between two.
+pub struct PostProcessPass {
+    layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    globals_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    // Keyed by the effect's layout key and the output format
+    pipelines: HashMap<String, wgpu::RenderPipeline>,
+    // Effects whose shader is missing or didn't compile, so the error is only logged once
+    failed: HashSet<String>,
+    // All effects' params for a frame, at dynamic offsets
+    params_buffer: Option<wgpu::Buffer>,
+}
+
+impl PostProcessPass {
+    pub fn new(device: &Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("[PostProcess] Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("[PostProcess] Pipeline"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("[PostProcess] Globals"),
+            size: std::mem::size_of::<PostGlobalsUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // Clamped, so effects sampling around the edges don't wrap
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("[PostProcess] sampler"),
+            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            layout,
+            pipeline_layout,
+            globals_buffer,
+            sampler,
+            pipelines: Default::default(),
+            failed: Default::default(),
+            params_buffer: None,
+        }
+    }
+
+    // Writes each effect's params at an aligned offset and returns the offsets
+    fn write_params<'a>(&mut self, device: &Device, effects: impl Iterator<Item = &'a PostEffect>) -> Vec<u32> {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as usize;
+        let mut data: Vec<u8> = vec![];
+        let mut offsets = vec![];
+        for effect in effects {
+            let offset = data.len().div_ceil(alignment) * alignment;
+            data.resize(offset, 0);
+            data.extend_from_slice(bytemuck::cast_slice(&shader_utils::params_data(&effect.params())));
+            offsets.push(offset as u32);
+        }
+        if data.is_empty() {
+            return offsets;
+        }
+        if !matches!(&self.params_buffer, Some(b) if b.size() >= data.len() as u64) {
+            self.params_buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("[PostProcess] Params"),
+                size: data.len() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        device.queue().write_buffer(self.params_buffer.as_ref().unwrap(), 0, &data);
+        offsets
+    }
+
+    fn draw_effect(
+        &self,
+        device: &Device,
+        encoder: &mut wgpu::CommandEncoder,
+        effect: &PostEffect,
+        params_offset: u32,
+        input: &wgpu::TextureView,
+        target: EffectTarget,
+    ) {
+        let params_size = (shader_utils::params_data(&effect.params()).len() * std::mem::size_of::<[f32; 4]>()) as u64;
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("[PostProcess] Bind Group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.globals_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: self.params_buffer.as_ref().unwrap(),
+                        offset: 0,
+                        size: wgpu::BufferSize::new(params_size),
+                    }),
+                },
+            ],
+        });
+
+        let _span = tracing::info_span!("post_effect", effect = effect.name()).entered();
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Post Process Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: if target.clear { wgpu::LoadOp::Clear(wgpu::Color::BLACK) } else { wgpu::LoadOp::Load },
+                    store: true,
+                }
+            })],
+            depth_stencil_attachment: None,
+        });
+        if let Some(v) = target.viewport {
+            render_pass.set_viewport(v.x, v.y, v.w, v.h, 0.0, 1.0);
+        }
+        render_pass.set_pipeline(&self.pipelines[&pipeline_key(effect, target.format)]);
+        render_pass.set_bind_group(0, &bind_group, &[params_offset]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    // Builds the pipeline for an effect if needed. Returns false if it can't be built.
+    fn prepare_pipeline(&mut self, device: &Device, assets: &Assets, effect: &PostEffect, format: wgpu::TextureFormat) -> bool {
+        let key = pipeline_key(effect, format);
+        if self.pipelines.contains_key(&key) {
+            return true;
+        }
+        if self.failed.contains(&key) {
+            return false;
+        }
+        let _span = tracing::info_span!("build_post_effect_pipeline", effect = effect.name()).entered();
+        match self.create_pipeline(device, assets, effect, format) {
+            Ok(pipeline) => {
+                self.pipelines.insert(key, pipeline);
+                true
+            }
+            Err(e) => {
+                log::error!("Couldn't build post effect {}: {}", effect.name(), e);
+                self.failed.insert(key);
+                false
+            }
+        }
+    }
+
+    fn create_pipeline(&self, device: &Device, assets: &Assets, effect: &PostEffect, format: wgpu::TextureFormat) -> anyhow::Result<wgpu::RenderPipeline> {
+        let (path, source, shader_defs) = match effect {
+            PostEffect::Custom { shader, .. } => {
+                let source = assets.shader_store.get(shader)
+                    .ok_or_else(|| anyhow::anyhow!("not in the asset shader store"))?;
+                (shader.as_str(), source.as_str(), HashMap::new())
+            }
+            _ => (
+                "shaders/post_effects.wgsl",
+                include_str!("shaders/post_effects.wgsl"),
+                HashMap::from([("EFFECT".to_string(), ShaderDefValue::Int(effect.shader_def().unwrap()))]),
+            ),
+        };
+        let source = format!(
+            "{}\n{}\n{}",
+            source,
+            EFFECT_PRELUDE,
+            shader_utils::params_struct("EffectParams", &effect.params()),
+        );
+        let module = shader_utils::try_make_module(path, &source, shader_defs)?;
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(effect.name()),
+            source: wgpu::ShaderSource::Naga(std::borrow::Cow::Owned(module)),
+        });
+        Ok(device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("[PostProcess] Pipeline"),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        }))
+    }
+}
+
+impl GraphPass for PostProcessPass {
+    fn name(&self) -> &str {
+        "tonemap"
+    }
+
+    fn setup(&self, ctx: &RenderContext, builder: &mut PassBuilder) {
+        builder.read(graph::HDR).write(graph::OUTPUT);
+        let (width, height) = ctx.view_size();
+        let usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_DST;
+        if !ctx.post_process.before_tonemap.is_empty() {
+            let format = ctx.hdr_pipeline.format();
+            builder.write(graph::HDR).create_texture(HDR_SCRATCH, TextureDesc { width, height, format, usage });
+        }
+        if !ctx.post_process.after_tonemap.is_empty() {
+            let format = ctx.output.format();
+            for name in OUTPUT_SCRATCH {
+                builder.create_texture(name, TextureDesc { width, height, format, usage });
+            }
+        }
+    }
+
+    // Runs the effects before tonemapping, the hdr pass and the effects after it, into the
+    // output. The viewport and clear are as for HdrPipeline::process.
+    fn run(&mut self, ctx: &RenderContext, resources: &GraphResources) -> Vec<wgpu::CommandBuffer> {
+        let device = ctx.device;
+        let hdr_pipeline = ctx.hdr_pipeline;
+        let (width, height) = ctx.view_size();
+        let output_format = ctx.output.format();
+        let output_view = resources.view(graph::OUTPUT);
+
+        // Skip effects whose pipeline can't be built
+        let before: Vec<&PostEffect> = ctx.post_process.before_tonemap.iter()
+            .filter(|effect| self.prepare_pipeline(device, ctx.assets, effect, hdr_pipeline.format()))
+            .collect();
+        let after: Vec<&PostEffect> = ctx.post_process.after_tonemap.iter()
+            .filter(|effect| self.prepare_pipeline(device, ctx.assets, effect, output_format))
+            .collect();
+        if before.is_empty() && after.is_empty() {
+            return vec![hdr_pipeline.process(device, output_view, ctx.viewport, ctx.clear)];
+        }
+
+        let (frame_resolution, frame_offset) = ctx.frame_view();
+        let globals = PostGlobalsUniform {
+            resolution: [width as f32, height as f32],
+            frame_resolution,
+            frame_offset,
+            time: ctx.frame_time.elapsed,
+            ..Default::default()
+        };
+        device.queue().write_buffer(&self.globals_buffer, 0, bytemuck::cast_slice(&[globals]));
+        let params_offsets = self.write_params(device, before.iter().chain(&after).copied());
+        let (before_offsets, after_offsets) = params_offsets.split_at(before.len());
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Post Process Encoder"),
+        });
+
+        if !before.is_empty() {
+            let hdr_texture = resources.texture(graph::HDR);
+            // Alternate so that the last effect writes the hdr texture. With an odd number
+            // of effects the first one reads a copy of it instead.
+            if before.len() % 2 == 1 {
+                encoder.copy_texture_to_texture(
+                    hdr_texture.as_image_copy(),
+                    resources.texture(HDR_SCRATCH).as_image_copy(),
+                    hdr_texture.size(),
+                );
+            }
+            let views = [resources.view(graph::HDR), resources.view(HDR_SCRATCH)];
+            for (i, (effect, &offset)) in before.iter().zip(before_offsets).enumerate() {
+                let output_index = (before.len() - i + 1) % 2;
+                let target = EffectTarget::new(views[output_index], hdr_pipeline.format());
+                self.draw_effect(device, &mut encoder, effect, offset, views[1 - output_index], target);
+            }
+        }
+
+        if after.is_empty() {
+            return vec![encoder.finish(), hdr_pipeline.process(device, output_view, ctx.viewport, ctx.clear)];
+        }
+
+        // Tonemap into a scratch texture, then chain through the effects into the output
+        let scratch = OUTPUT_SCRATCH.map(|name| resources.view(name));
+        let tonemap_cmd_buffer = hdr_pipeline.process(device, scratch[0], None, true);
+        let mut effects_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Post Process After Tonemap Encoder"),
+        });
+        for (i, (effect, &offset)) in after.iter().zip(after_offsets).enumerate() {
+            let target = if i + 1 == after.len() {
+                EffectTarget { viewport: ctx.viewport, clear: ctx.clear, ..EffectTarget::new(output_view, output_format) }
+            } else {
+                EffectTarget::new(scratch[(i + 1) % 2], output_format)
+            };
+            self.draw_effect(device, &mut effects_encoder, effect, offset, scratch[i % 2], target);
+        }
+        vec![encoder.finish(), tonemap_cmd_buffer, effects_encoder.finish()]
+    }
+}
+
+fn pipeline_key(effect: &PostEffect, format: wgpu::TextureFormat) -> String {
+    format!("{}|{:?}", effect.layout_key(), format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+    use crate::capture::{image_ops, TextureReadback};
+    use crate::renderers::Sketch;
+    use crate::systems::Renderers;
+    use crate::testing::{headless_device, skip_without_adapter};
+    use std::time::Duration;
+
+    #[test]
+    fn builtin_effects_build() {
+        let Some(device) = headless_device() else {
+            return;
+        };
+        let assets = Assets::builtin(&device);
+        let mut pass = PostProcessPass::new(&device);
+        let effects = [
+            PostEffect::Vignette { intensity: 0.5, radius: 0.5, smoothness: 0.2 },
+            PostEffect::ChromaticAberration { strength: 2.0 },
+            PostEffect::FilmGrain { intensity: 0.1 },
+            PostEffect::Pixelate { size: 4.0 },
+            PostEffect::Kaleidoscope { segments: 6.0, rotation: 0.0 },
+            PostEffect::EdgeDetect { strength: 1.0 },
+        ];
+        for effect in &effects {
+            assert!(pass.prepare_pipeline(&device, &assets, effect, wgpu::TextureFormat::Rgba16Float), "{}", effect.name());
+        }
+    }
+
+    fn add(amount: f32) -> PostEffect {
+        PostEffect::Custom { shader: "add.wgsl".to_string(), params: vec![("amount".to_string(), ShaderParam::Float(amount))] }
+    }
+
+    fn scale(factor: f32) -> PostEffect {
+        PostEffect::Custom { shader: "scale.wgsl".to_string(), params: vec![("factor".to_string(), ShaderParam::Float(factor))] }
+    }
+
+    // Renders a solid grey of 0.1 through the effects, returning the hdr buffer after the
+    // effects before tonemapping, and the output
+    fn render(app: &mut App, post_process: PostProcess) -> (image::Rgba32FImage, image::RgbaImage) {
+        app.world.insert_resource(post_process);
+        let output = app.render_to_image(Duration::ZERO);
+        let hdr_texture = app.world.resource::<Renderers>().hdr_pipeline.texture();
+        let device = app.world.resource::<Device>();
+        let hdr = TextureReadback::new(device, hdr_texture).wait_hdr(device);
+        (hdr, output)
+    }
+
+    #[test]
+    fn effect_chains_run_in_order() {
+        let Some(mut app) = skip_without_adapter(pollster::block_on(App::try_new_headless(16, 16, true))) else {
+            return;
+        };
+        app.load_reference_scene();
+        app.add_shader("solid.wgsl", r#"
+            fn main_image(frag_coord: vec2<f32>, resolution: vec2<f32>, time: f32, mouse: vec4<f32>) -> vec4<f32> {
+                return vec4<f32>(0.1, 0.1, 0.1, 1.0);
+            }"#.to_string());
+        app.add_shader("add.wgsl", r#"
+            fn effect(uv: vec2<f32>) -> vec4<f32> {
+                return textureSample(input_texture, input_sampler, uv) + vec4<f32>(vec3<f32>(params.amount), 0.0);
+            }"#.to_string());
+        app.add_shader("scale.wgsl", r#"
+            fn effect(uv: vec2<f32>) -> vec4<f32> {
+                return textureSample(input_texture, input_sampler, uv) * vec4<f32>(vec3<f32>(params.factor), 1.0);
+            }"#.to_string());
+        app.world.resource_mut::<Sketch>().image = Some("solid.wgsl".to_string());
+
+        // An odd number of effects starts from a copy of the hdr texture, an even number
+        // from the texture itself. Either way the last one writes the hdr texture.
+        for (before_tonemap, expected) in [
+            (vec![scale(2.0)], 0.2),
+            (vec![add(0.1), scale(2.0)], 0.4),
+            (vec![add(0.1), scale(2.0), add(0.05)], 0.45),
+        ] {
+            let count = before_tonemap.len();
+            let (hdr, _) = render(&mut app, PostProcess { before_tonemap, after_tonemap: vec![] });
+            for pixel in hdr.pixels() {
+                assert!((pixel[0] - expected).abs() < 1e-3, "{} effects: {:?}", count, pixel);
+            }
+        }
+
+        // After tonemapping the chain alternates between two scratch textures into the output
+        let (_, tonemapped) = render(&mut app, PostProcess { before_tonemap: vec![scale(2.0)], after_tonemap: vec![] });
+        let after_tonemap = vec![scale(0.5), add(0.2), scale(0.5)];
+        let (_, output) = render(&mut app, PostProcess { before_tonemap: vec![scale(2.0)], after_tonemap });
+        let mut expected = image_ops::to_linear(&tonemapped);
+        for pixel in expected.pixels_mut() {
+            for c in 0..3 {
+                pixel[c] = (pixel[c] * 0.5 + 0.2) * 0.5;
+            }
+        }
+        let expected = image_ops::to_srgb(&expected);
+        for (pixel, expected) in output.pixels().zip(expected.pixels()) {
+            for c in 0..3 {
+                assert!((pixel[c] as i32 - expected[c] as i32).abs() <= 2, "{:?} != {:?}", pixel, expected);
+            }
+        }
+    }
+}
@@ -5,6 +5,8 @@ use naga_oil::compose::{
     ComposableModuleDescriptor, Composer, NagaModuleDescriptor, ShaderDefValue
 };
 
+use crate::components::ShaderParam;
+
 
 macro_rules! load_shader {
     ($composer:expr, $path:literal, $shader_defs:expr) => {{
@@ -36,6 +38,49 @@ pub fn make_module(
     .unwrap()
 }
 
+// Composes and validates a shader from user provided source, returning errors rather than
+// panicking, as wgpu treats an invalid module as a fatal error
+pub fn try_make_module(
+    shader_path: &str,
+    shader_source: &str,
+    shader_defs: HashMap<String, ShaderDefValue>,
+) -> anyhow::Result<naga::Module> {
+    let mut composer = init_composer();
+    let module = composer
+        .make_naga_module(NagaModuleDescriptor {
+            source: shader_source,
+            file_path: shader_path,
+            shader_defs,
+            ..Default::default()
+        })
+        .map_err(|e| anyhow::anyhow!("{}", e.emit_to_string(&composer)))?;
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+        .validate(&module)
+        .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+    Ok(module)
+}
+
+// WGSL struct for named params, each in a 16 byte slot to match params_data.
+// WGSL structs can't be empty, so without params it has a dummy member.
+pub fn params_struct(name: &str, params: &[(String, ShaderParam)]) -> String {
+    let mut members: Vec<String> = params.iter()
+        .map(|(param_name, param)| format!("    @size(16) {}: {},", param_name, param.wgsl_type()))
+        .collect();
+    if members.is_empty() {
+        members.push("    _unused: vec4<f32>,".to_string());
+    }
+    format!("struct {} {{\n{}\n}}\n", name, members.join("\n"))
+}
+
+// Uniform data for params_struct
+pub fn params_data(params: &[(String, ShaderParam)]) -> Vec<[f32; 4]> {
+    let mut slots: Vec<[f32; 4]> = params.iter().map(|(_, param)| param.to_slot()).collect();
+    if slots.is_empty() {
+        slots.push([0.0; 4]);
+    }
+    slots
+}
+
 pub fn init_composer() -> Composer {
     let mut composer = Composer::default();
 
@@ -0,0 +1,95 @@
+#import utils
+
+// Debug visualisation of the lit pass. Uses the same vertex/instance layout and
+// bind groups as phong.wgsl, the output is selected with the DEBUG_VIEW def:
+// 1 = wireframe, 2 = normals, 3 = tangents, 4 = uvs, 5 = depth, 6 = overdraw
+
+// Vertex shader
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
+}
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    @location(9) normal_matrix_0: vec3<f32>,
+    @location(10) normal_matrix_1: vec3<f32>,
+    @location(11) normal_matrix_2: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) world_normal: vec3<f32>,
+    @location(3) world_tangent: vec3<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.tex_coords = model.tex_coords;
+    out.world_position = world_position.xyz;
+    out.world_normal = normal_matrix * model.normal;
+    out.world_tangent = normal_matrix * model.tangent;
+    return out;
+}
+
+// Fragment shader
+
+// Maps a unit vector to a displayable colour
+fn vector_color(v: vec3<f32>) -> vec3<f32> {
+    return normalize(v) * 0.5 + 0.5;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+#if DEBUG_VIEW == 1
+    let color = vec3<f32>(0.9, 0.9, 0.9);
+#else if DEBUG_VIEW == 2
+    let color = vector_color(in.world_normal);
+#else if DEBUG_VIEW == 3
+    let color = vector_color(in.world_tangent);
+#else if DEBUG_VIEW == 4
+    let color = vec3<f32>(fract(in.tex_coords), 0.0);
+#else if DEBUG_VIEW == 5
+    // Distance to the camera, compressed so near detail is still visible
+    let distance = length(in.world_position - camera.view_pos.xyz);
+    let color = vec3<f32>(1.0 - exp(-distance / 20.0));
+#else
+    // Accumulated additively, so each shaded fragment adds a bit of heat
+    let color = vec3<f32>(0.1, 0.04, 0.02);
+#endif
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,76 @@
+// Built-in post-processing effects, selected with the EFFECT def:
+// 1 = vignette, 2 = chromatic aberration, 3 = film grain, 4 = pixelate, 5 = kaleidoscope,
+// 6 = edge detect
+// Each one provides effect(uv), the bindings, params and entry points are added by PostProcessPass.
+// Positions are taken in the full frame (frame_uv), so the tiles of a poster line up.
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+#if EFFECT == 1
+// Darkens towards the corners, starting at radius from the centre (in units of the height)
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    let color = textureSample(input_texture, input_sampler, uv);
+    let aspect = post.frame_resolution.x / post.frame_resolution.y;
+    let distance = length((frame_uv(uv) - 0.5) * vec2<f32>(aspect, 1.0));
+    let falloff = smoothstep(params.radius, params.radius + params.smoothness, distance);
+    return vec4<f32>(color.rgb * (1.0 - params.intensity * falloff), color.a);
+}
+#else if EFFECT == 2
+// Splits red and blue apart towards the edges, by up to strength pixels
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    let offset = (frame_uv(uv) - 0.5) * 2.0 * params.strength / post.resolution;
+    let color = textureSample(input_texture, input_sampler, uv);
+    let r = textureSample(input_texture, input_sampler, uv + offset).r;
+    let b = textureSample(input_texture, input_sampler, uv - offset).b;
+    return vec4<f32>(r, color.g, b, color.a);
+}
+#else if EFFECT == 3
+fn hash(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2<f32>(12.9898, 78.233))) * 43758.5453);
+}
+
+// Per pixel noise that changes every frame, stronger in the darks like film
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    let color = textureSample(input_texture, input_sampler, uv);
+    let pixel = floor(frame_uv(uv) * post.frame_resolution);
+    let noise = hash(pixel + fract(post.time * 7.31) * 1000.0) - 0.5;
+    let response = 1.0 - 0.5 * clamp(luminance(color.rgb), 0.0, 1.0);
+    return vec4<f32>(color.rgb + noise * params.intensity * response, color.a);
+}
+#else if EFFECT == 4
+// Blocks of size by size pixels
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    let block = max(params.size, 1.0) / post.frame_resolution;
+    let center = (floor(frame_uv(uv) / block) + 0.5) * block;
+    return textureSample(input_texture, input_sampler, input_uv(center));
+}
+#else if EFFECT == 5
+// Mirrors a wedge of the image around the centre, segments times. In a poster tile the
+// mirrored part can be outside the tile, which then shows the tile's edge.
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    let aspect = post.frame_resolution.x / post.frame_resolution.y;
+    let p = (frame_uv(uv) - 0.5) * vec2<f32>(aspect, 1.0);
+    let wedge = 6.2831853 / max(params.segments, 1.0);
+    let angle = atan2(p.y, p.x) + params.rotation;
+    let folded = abs(angle - wedge * floor(angle / wedge) - wedge * 0.5);
+    let q = length(p) * vec2<f32>(cos(folded), sin(folded));
+    return textureSample(input_texture, input_sampler, input_uv(q / vec2<f32>(aspect, 1.0) + 0.5));
+}
+#else if EFFECT == 6
+// Darkens edges found with a Sobel filter on the luminance
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    let texel = 1.0 / post.resolution;
+    var samples: array<f32, 9>;
+    for (var i = 0; i < 9; i++) {
+        let offset = vec2<f32>(f32(i % 3 - 1), f32(i / 3 - 1)) * texel;
+        samples[i] = luminance(textureSampleLevel(input_texture, input_sampler, uv + offset, 0.0).rgb);
+    }
+    let gx = samples[2] + 2.0 * samples[5] + samples[8] - samples[0] - 2.0 * samples[3] - samples[6];
+    let gy = samples[6] + 2.0 * samples[7] + samples[8] - samples[0] - 2.0 * samples[1] - samples[2];
+    let edge = clamp(length(vec2<f32>(gx, gy)) * params.strength, 0.0, 1.0);
+    let color = textureSample(input_texture, input_sampler, uv);
+    return vec4<f32>(color.rgb * (1.0 - edge), color.a);
+}
+#endif
@@ -12,7 +12,7 @@ struct VertexOutput {
     clip_position: vec4<f32>,
 
     @location(0)
-    uv: vec3<f32>,
+    ndc: vec2<f32>,
 }
 
 struct Camera {
@@ -30,7 +30,7 @@ fn vs_main(@builtin(vertex_index) id: u32,) -> VertexOutput {
     ));
     var out: VertexOutput;
     out.clip_position = vec4(uv * 4.0 - 1.0, 1.0, 1.0);
-    out.uv = (camera.view_proj_inv * out.clip_position).xyz;
+    out.ndc = out.clip_position.xy;
     return out;
 }
 
@@ -44,5 +44,10 @@ var cube_sampler: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(cube_texture, cube_sampler, in.uv);
+    // The view ray through this pixel, from two points on it. Unlike using the far point
+    // as the direction, this also works for orthographic and off-centre projections.
+    let far = camera.view_proj_inv * vec4(in.ndc, 1.0, 1.0);
+    let near = camera.view_proj_inv * vec4(in.ndc, 0.0, 1.0);
+    let dir = far.xyz / far.w - near.xyz / near.w;
+    return textureSample(cube_texture, cube_sampler, dir);
 }
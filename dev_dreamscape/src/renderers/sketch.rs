@@ -0,0 +1,575 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy_ecs::prelude::*;
+
+use crate::{
+    assets::Assets,
+    components::RenderTarget,
+    device::Device,
+    input::Input,
+    math::Vec4f,
+    texture,
+};
+
+use super::{
+    graph,
+    graph::{GraphPass, GraphResources, PassBuilder, RenderContext},
+    shader_utils,
+};
+
+// Shadertoy has four buffers too, and a fixed number keeps a single bind group layout
+pub const MAX_SKETCH_BUFFERS: usize = 4;
+
+// Declarations appended to every sketch shader, which provides main_image
+const SKETCH_PRELUDE: &str = r#"
+struct SketchGlobals {
+    // Size of the output in pixels
+    resolution: vec2<f32>,
+    // Size of the full frame in pixels, and where the output's top left corner is in it.
+    // The output is only part of the frame for poster tiles.
+    frame_resolution: vec2<f32>,
+    frame_offset: vec2<f32>,
+    // Seconds since the start
+    time: f32,
+    time_delta: f32,
+    // xy: cursor position while the left button is down, zw: where it was pressed,
+    // negated while the button is up
+    mouse: vec4<f32>,
+    frame: u32,
+}
+@group(0) @binding(0)
+var<uniform> sketch: SketchGlobals;
+
+@group(0) @binding(1)
+var buffer_sampler: sampler;
+@group(0) @binding(2)
+var buffer_a: texture_2d<f32>;
+@group(0) @binding(3)
+var buffer_b: texture_2d<f32>;
+@group(0) @binding(4)
+var buffer_c: texture_2d<f32>;
+@group(0) @binding(5)
+var buffer_d: texture_2d<f32>;
+
+// Pixel of the output from the top left, as frag_coord is in the full frame from the bottom left
+fn output_pixel(frag_coord: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(frag_coord.x, sketch.frame_resolution.y - frag_coord.y) - sketch.frame_offset;
+}
+
+// Texture coordinates of a pixel in the buffers
+fn buffer_uv(frag_coord: vec2<f32>) -> vec2<f32> {
+    return output_pixel(frag_coord) / sketch.resolution;
+}
+
+// Exact texel of a buffer at a pixel, like texelFetch
+fn buffer_texel(buffer: texture_2d<f32>, frag_coord: vec2<f32>) -> vec4<f32> {
+    return textureLoad(buffer, vec2<i32>(output_pixel(frag_coord)), 0);
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vi: u32) -> @builtin(position) vec4<f32> {
+    // Generate a triangle that covers the whole screen
+    let uv = vec2<f32>(f32((vi << 1u) & 2u), f32(vi & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = position.xy + sketch.frame_offset;
+    let frag_coord = vec2<f32>(pixel.x, sketch.frame_resolution.y - pixel.y);
+    return main_image(frag_coord, sketch.frame_resolution, sketch.time, sketch.mouse);
+}
+"#;
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum SketchMode {
+    // The sketch is drawn instead of the 3D scene
+    #[default]
+    Replace,
+    // The sketch is alpha blended over the scene
+    Overlay,
+}
+
+// Fullscreen fragment shader sketch, Shadertoy style. Each shader provides
+//     fn main_image(frag_coord: vec2<f32>, resolution: vec2<f32>, time: f32, mouse: vec4<f32>) -> vec4<f32>
+// with frag_coord in pixels from the bottom left of the full frame, also for poster tiles. The buffer shaders run first, in order, each
+// into its own texture (buffer_a to buffer_d). Every pass can read all buffers: the ones written
+// earlier in the frame have this frame's contents, the others (including its own) last frame's.
+// The image shader then writes the hdr target, which gets tonemapped as usual.
+#[derive(Resource, Default)]
+pub struct Sketch {
+    // Shader paths in the asset shader store, up to MAX_SKETCH_BUFFERS
+    pub buffers: Vec<String>,
+    // Sketching is off without an image shader
+    pub image: Option<String>,
+    pub mode: SketchMode,
+    mouse: Vec4f,
+    mouse_down: bool,
+}
+
+impl Sketch {
+    pub fn is_active(&self) -> bool {
+        self.image.is_some()
+    }
+
+    // Whether the 3D scene is skipped
+    pub fn replaces_scene(&self) -> bool {
+        self.is_active() && self.mode == SketchMode::Replace
+    }
+
+    // Tracks the mouse the way Shadertoy's iMouse does
+    pub fn update_mouse(mut sketch: ResMut<Sketch>, input: Res<Input>, device: Res<Device>) {
+        let height = device.surface_size().height as f32;
+        let (x, y) = (input.cursor_position.0, height - input.cursor_position.1);
+        if input.lmb_down {
+            if !sketch.mouse_down {
+                sketch.mouse.z = x;
+                sketch.mouse.w = y;
+            }
+            sketch.mouse.x = x;
+            sketch.mouse.y = y;
+        } else if sketch.mouse_down {
+            sketch.mouse.z = -sketch.mouse.z;
+            sketch.mouse.w = -sketch.mouse.w;
+        }
+        sketch.mouse_down = input.lmb_down;
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct SketchUniform {
+    resolution: [f32; 2],
+    frame_resolution: [f32; 2],
+    frame_offset: [f32; 2],
+    time: f32,
+    time_delta: f32,
+    mouse: [f32; 4],
+    frame: u32,
+    _padding: [u32; 3],
+}
+
+// Draws the Sketch resource into the hdr target
+pub struct SketchPass {
+    color_format: wgpu::TextureFormat,
+    layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    uniform_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    // Bound to the buffer slots the sketch doesn't use
+    empty_texture: texture::Texture,
+    // Keyed by shader path and whether it's the image pass, which may blend
+    pipelines: HashMap<String, wgpu::RenderPipeline>,
+    // Shaders that are missing or didn't compile, so the error is only logged once
+    failed: HashSet<String>,
+    // Keyed by the camera's target and the view size, so each view keeps its own buffers
+    view_buffers: HashMap<ViewKey, ViewBuffers>,
+}
+
+type ViewKey = (RenderTarget, u32, u32);
+
+// Ping-pong pair for each buffer of a view. The one at the buffer's index in `current` has
+// last frame's contents, and the other one is written this frame. They swap at the end of
+// the frame, so views rendered more than once a frame (e.g. both WebXR eyes) see the same.
+struct ViewBuffers {
+    pairs: Vec<[texture::Texture; 2]>,
+    current: Vec<usize>,
+    // Buffers written this frame, which swap at the end of it
+    written: Vec<bool>,
+    // Rendered this frame. The others are freed.
+    used: bool,
+}
+
+impl ViewBuffers {
+    fn new(device: &Device, format: wgpu::TextureFormat, count: usize, width: u32, height: u32) -> Self {
+        Self {
+            pairs: (0..count)
+                .map(|_| [0, 1].map(|_| SketchPass::create_buffer_texture(device, format, width, height)))
+                .collect(),
+            current: vec![0; count],
+            written: vec![false; count],
+            used: false,
+        }
+    }
+
+    // The latest contents of a buffer, given which ones this view has written so far
+    fn latest(&self, i: usize, written: &[bool]) -> &texture::Texture {
+        let index = if written[i] { 1 - self.current[i] } else { self.current[i] };
+        &self.pairs[i][index]
+    }
+
+    fn end_frame(&mut self) {
+        for (current, written) in self.current.iter_mut().zip(&mut self.written) {
+            if std::mem::take(written) {
+                *current = 1 - *current;
+            }
+        }
+    }
+}
+
+impl SketchPass {
+    pub fn new(
+        device: &Device,
+        color_format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture_entries = (0..MAX_SKETCH_BUFFERS as u32).map(|i| wgpu::BindGroupLayoutEntry {
+            binding: 2 + i,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        });
+        let entries: Vec<_> = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ].into_iter().chain(texture_entries).collect();
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("[Sketch] Layout"),
+            entries: &entries,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("[Sketch] Pipeline"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("[Sketch] Uniform"),
+            size: std::mem::size_of::<SketchUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("[Sketch] sampler"),
+            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let empty_texture = Self::create_buffer_texture(device, color_format, 1, 1);
+
+        Self {
+            color_format,
+            layout,
+            pipeline_layout,
+            uniform_buffer,
+            sampler,
+            empty_texture,
+            pipelines: Default::default(),
+            failed: Default::default(),
+            view_buffers: Default::default(),
+        }
+    }
+
+    // Buffers have the hdr format, so they can hold unclamped values
+    fn create_buffer_texture(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) -> texture::Texture {
+        texture::Texture::create_2d_texture(
+            device,
+            width,
+            height,
+            format,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            wgpu::FilterMode::Linear,
+            Some("[Sketch] Buffer"),
+        )
+    }
+
+    // Takes a view's buffers out while drawing, as the pipelines and bind groups borrow self.
+    // They're recreated when their number changes, which clears them.
+    fn take_view_buffers(&mut self, device: &Device, key: &ViewKey, count: usize) -> ViewBuffers {
+        match self.view_buffers.remove(key) {
+            Some(buffers) if buffers.pairs.len() == count => buffers,
+            _ => ViewBuffers::new(device, self.color_format, count, key.1, key.2),
+        }
+    }
+
+    // Runs the buffer passes and then the image pass into the hdr texture. When the sketch
+    // replaces the scene the target is cleared first, otherwise the image is blended over it.
+    pub fn draw(&mut self, hdr_texture: &wgpu::Texture, ctx: &RenderContext) -> wgpu::CommandBuffer {
+        let (device, assets, sketch, frame_time) = (ctx.device, ctx.assets, ctx.sketch, ctx.frame_time);
+        let clear = !ctx.draws_scene();
+        let (frame_resolution, frame_offset) = ctx.frame_view();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Sketch Encoder"),
+        });
+        let Some(image) = &sketch.image else {
+            return encoder.finish();
+        };
+        if sketch.buffers.len() > MAX_SKETCH_BUFFERS {
+            log::warn!("Sketch has {} buffers, only the first {} are used", sketch.buffers.len(), MAX_SKETCH_BUFFERS);
+        }
+        let buffer_count = sketch.buffers.len().min(MAX_SKETCH_BUFFERS);
+        let (width, height) = (hdr_texture.width(), hdr_texture.height());
+
+        let uniform = SketchUniform {
+            resolution: [width as f32, height as f32],
+            frame_resolution,
+            frame_offset,
+            time: frame_time.elapsed,
+            time_delta: frame_time.delta,
+            mouse: sketch.mouse.into(),
+            frame: frame_time.frame_count,
+            ..Default::default()
+        };
+        device.queue().write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        let key = (ctx.camera.0.target.clone(), width, height);
+        let mut buffers = self.take_view_buffers(device, &key, buffer_count);
+        buffers.used = true;
+        // Buffers this view has written, which later passes read this frame's contents of
+        let mut written = vec![false; buffer_count];
+        for (i, shader) in sketch.buffers.iter().take(buffer_count).enumerate() {
+            if !self.prepare_pipeline(device, assets, shader, false) {
+                continue;
+            }
+            let bind_group = self.create_bind_group(device, &buffers, &written);
+            let target = &buffers.pairs[i][1 - buffers.current[i]].view;
+            let pipeline = &self.pipelines[&pipeline_key(shader, false)];
+            Self::draw_fullscreen(&mut encoder, target, pipeline, &bind_group, true);
+            written[i] = true;
+            buffers.written[i] = true;
+        }
+
+        let blend = sketch.mode == SketchMode::Overlay;
+        if self.prepare_pipeline(device, assets, image, blend) {
+            let bind_group = self.create_bind_group(device, &buffers, &written);
+            let pipeline = &self.pipelines[&pipeline_key(image, blend)];
+            let hdr_view = hdr_texture.create_view(&wgpu::TextureViewDescriptor::default());
+            Self::draw_fullscreen(&mut encoder, &hdr_view, pipeline, &bind_group, clear);
+        }
+        self.view_buffers.insert(key, buffers);
+        encoder.finish()
+    }
+
+    fn draw_fullscreen(
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        pipeline: &wgpu::RenderPipeline,
+        bind_group: &wgpu::BindGroup,
+        clear: bool,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Sketch Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: if clear { wgpu::LoadOp::Clear(wgpu::Color::BLACK) } else { wgpu::LoadOp::Load },
+                    store: true,
+                }
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    // Binds the latest contents of each buffer of the view
+    fn create_bind_group(&self, device: &Device, buffers: &ViewBuffers, written: &[bool]) -> wgpu::BindGroup {
+        let buffer_views = (0..MAX_SKETCH_BUFFERS).map(|i| match i < buffers.pairs.len() {
+            true => &buffers.latest(i, written).view,
+            false => &self.empty_texture.view,
+        });
+        let entries: Vec<_> = [
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: self.uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ].into_iter().chain(buffer_views.enumerate().map(|(i, view)| wgpu::BindGroupEntry {
+            binding: 2 + i as u32,
+            resource: wgpu::BindingResource::TextureView(view),
+        })).collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("[Sketch] Bind Group"),
+            layout: &self.layout,
+            entries: &entries,
+        })
+    }
+
+    // Builds the pipeline for a shader if needed. Returns false if it can't be built.
+    fn prepare_pipeline(&mut self, device: &Device, assets: &Assets, shader: &str, blend: bool) -> bool {
+        let key = pipeline_key(shader, blend);
+        if self.pipelines.contains_key(&key) {
+            return true;
+        }
+        if self.failed.contains(&key) {
+            return false;
+        }
+        let _span = tracing::info_span!("build_sketch_pipeline", shader).entered();
+        match self.create_pipeline(device, assets, shader, blend) {
+            Ok(pipeline) => {
+                self.pipelines.insert(key, pipeline);
+                true
+            }
+            Err(e) => {
+                log::error!("Couldn't build sketch shader {}: {}", shader, e);
+                self.failed.insert(key);
+                false
+            }
+        }
+    }
+
+    fn create_pipeline(&self, device: &Device, assets: &Assets, shader: &str, blend: bool) -> anyhow::Result<wgpu::RenderPipeline> {
+        let source = assets.shader_store.get(shader)
+            .ok_or_else(|| anyhow::anyhow!("not in the asset shader store"))?;
+        let source = format!("{}\n{}", source, SKETCH_PRELUDE);
+
+        let module = shader_utils::try_make_module(shader, &source, Default::default())?;
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(shader),
+            source: wgpu::ShaderSource::Naga(std::borrow::Cow::Owned(module)),
+        });
+        let blend = if blend { wgpu::BlendState::ALPHA_BLENDING } else { wgpu::BlendState::REPLACE };
+        Ok(device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("[Sketch] Pipeline"),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: self.color_format,
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        }))
+    }
+}
+
+// Draws over the scene, or instead of it
+impl GraphPass for SketchPass {
+    fn name(&self) -> &str {
+        "sketch"
+    }
+
+    fn enabled(&self, ctx: &RenderContext) -> bool {
+        ctx.sketch.is_active()
+    }
+
+    fn setup(&self, _ctx: &RenderContext, builder: &mut PassBuilder) {
+        builder.write(graph::HDR);
+    }
+
+    fn run(&mut self, ctx: &RenderContext, resources: &GraphResources) -> Vec<wgpu::CommandBuffer> {
+        vec![self.draw(resources.texture(graph::HDR), ctx)]
+    }
+
+    // Swaps the buffers written this frame and frees those of views that weren't rendered
+    fn end_frame(&mut self) {
+        self.view_buffers.retain(|_, buffers| std::mem::take(&mut buffers.used));
+        for buffers in self.view_buffers.values_mut() {
+            buffers.end_frame();
+        }
+    }
+}
+
+fn pipeline_key(shader: &str, blend: bool) -> String {
+    format!("{}|{}", shader, blend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+    use crate::capture::TextureReadback;
+    use crate::components::{Camera, Transform};
+    use crate::systems::Renderers;
+    use crate::testing::{headless_device, skip_without_adapter};
+    use std::time::Duration;
+
+    #[test]
+    fn prelude_builds() {
+        let Some(device) = headless_device() else {
+            return;
+        };
+        let mut assets = Assets::builtin(&device);
+        assets.shader_store.insert("feedback.wgsl".to_string(), r#"
+            fn main_image(frag_coord: vec2<f32>, resolution: vec2<f32>, time: f32, mouse: vec4<f32>) -> vec4<f32> {
+                let previous = textureSample(buffer_a, buffer_sampler, buffer_uv(frag_coord));
+                return 0.5 * (previous + buffer_texel(buffer_b, frag_coord)) + vec4<f32>(frag_coord / resolution, 0.0, 1.0);
+            }"#.to_string());
+        let mut pass = SketchPass::new(&device, wgpu::TextureFormat::Rgba16Float);
+        assert!(pass.prepare_pipeline(&device, &assets, "feedback.wgsl", false));
+        assert!(pass.prepare_pipeline(&device, &assets, "feedback.wgsl", true));
+    }
+
+    fn hdr_red(app: &App) -> f32 {
+        let device = app.world.resource::<Device>();
+        let hdr_texture = app.world.resource::<Renderers>().hdr_pipeline.texture();
+        TextureReadback::new(device, hdr_texture).wait_hdr(device).get_pixel(0, 0)[0]
+    }
+
+    #[test]
+    fn each_view_steps_its_buffers_once_a_frame() {
+        let Some(mut app) = skip_without_adapter(pollster::block_on(App::try_new_headless(16, 16, true))) else {
+            return;
+        };
+        app.load_reference_scene();
+        // Counts frames in buffer_a
+        app.add_shader("count.wgsl", r#"
+            fn main_image(frag_coord: vec2<f32>, resolution: vec2<f32>, time: f32, mouse: vec4<f32>) -> vec4<f32> {
+                return buffer_texel(buffer_a, frag_coord) + vec4<f32>(0.125, 0.0, 0.0, 0.0);
+            }"#.to_string());
+        app.add_shader("show.wgsl", r#"
+            fn main_image(frag_coord: vec2<f32>, resolution: vec2<f32>, time: f32, mouse: vec4<f32>) -> vec4<f32> {
+                return buffer_texel(buffer_a, frag_coord);
+            }"#.to_string());
+        {
+            let mut sketch = app.world.resource_mut::<Sketch>();
+            sketch.buffers = vec!["count.wgsl".to_string()];
+            sketch.image = Some("show.wgsl".to_string());
+        }
+        // Another view of the same size, rendered before the window each frame
+        app.world.resource_scope(|world, mut assets: Mut<Assets>| {
+            assets.add_render_target(world.resource::<Device>(), "mirror", 16, 16);
+        });
+        app.world.spawn((
+            Camera::new(16, 16, 1.0, 0.1, 100.0).with_target(RenderTarget::Texture("mirror".to_string())),
+            Transform::default(),
+        ));
+
+        for frame in 1..=3 {
+            app.render_to_hdr_image(Duration::ZERO);
+            assert_eq!(hdr_red(&app), 0.125 * frame as f32);
+        }
+        // Rendering the window again in the same frame, like the second WebXR eye
+        let texture = texture::Texture::create_2d_texture(
+            app.world.resource::<Device>(), 16, 16, wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureUsages::RENDER_ATTACHMENT, wgpu::FilterMode::Nearest, None);
+        app.render_to_texture(&texture.texture, None, true);
+        assert_eq!(hdr_red(&app), 0.375);
+        app.render_to_hdr_image(Duration::ZERO);
+        assert_eq!(hdr_red(&app), 0.5);
+    }
+}
@@ -5,7 +5,11 @@ use crate::{
     components::{Camera,Transform},
     device::Device,
     math::Mat4,
-    renderers::shader_utils,
+    renderers::{
+        graph,
+        graph::{GraphPass, GraphResources, PassBuilder, RenderContext},
+        shader_utils,
+    },
     texture::Texture,
 };
 
@@ -181,6 +185,27 @@ impl SkyboxPass {
     }
 }
 
+impl GraphPass for SkyboxPass {
+    fn name(&self) -> &str {
+        "skybox"
+    }
+
+    // Most debug views skip it and let the phong pass clear instead
+    fn enabled(&self, ctx: &RenderContext) -> bool {
+        ctx.draws_scene() && ctx.debug_view.draws_skybox()
+    }
+
+    fn setup(&self, _ctx: &RenderContext, builder: &mut PassBuilder) {
+        builder.write(graph::HDR);
+    }
+
+    fn run(&mut self, ctx: &RenderContext, resources: &GraphResources) -> Vec<wgpu::CommandBuffer> {
+        let texture_name = &ctx.skybox.texture_name;
+        let texture = ctx.assets.texture_store.get(texture_name).unwrap();
+        vec![self.draw(resources.view(graph::HDR), ctx.device, ctx.camera, (texture_name, texture), true)]
+    }
+}
+
 pub fn new_texture_bind_group_layout(
     device: &Device,
     view_dimension: wgpu::TextureViewDimension,
@@ -221,7 +246,7 @@ pub fn new_uniform_bind_group(
     let group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[wgpu::BindGroupLayoutEntry {
             binding: 0,
-            visibility: wgpu::ShaderStages::VERTEX,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
             ty: wgpu::BindingType::Buffer {
                 ty: wgpu::BufferBindingType::Uniform,
                 has_dynamic_offset: false,
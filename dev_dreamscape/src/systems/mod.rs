@@ -4,16 +4,18 @@ mod update_input_state;
 mod schedules;
 
 use crate::device::Device;
-use crate::events::{KeyboardEvent, WindowResizeEvent, FrameTimeEvent};
-use crate::physics_world::PhysicsWorld;
+use crate::events::{KeyboardEvent, WindowResizeEvent, FrameTimeEvent, CollisionStarted, CollisionEnded, ContactForce, TriggerEnter, TriggerExit};
+use crate::physics_world::{PhysicsSettings, PhysicsTimestep, PhysicsWorld};
+use crate::components::PhysicsBody;
 use crate::app::AppState;
+use crate::renderers::DebugView;
 use bevy_ecs::prelude::*;
 use winit::event::VirtualKeyCode;
 
-pub use render::{Renderers,render,render_to_texture};
+pub use render::{Renderers,SceneParams,render,render_to_texture,render_texture_targets,render_views_for_readback};
 pub use update_input_state::update_input_state;
 //pub use grab_cursor::grab_cursor;
-pub use schedules::{new_spawn_scene_schedule,new_preupdate_schedule,new_hand_update_schedule,
+pub use schedules::{new_spawn_scene_schedule,new_reference_scene_schedule,new_preupdate_schedule,new_hand_update_schedule,
                     new_camera_update_schedule,new_update_schedule,new_render_schedule};
 pub use schedules::{SpawnLabel, PreupdateLabel, UpdateLabel, HandUpdateLabel, CameraUpdateLabel, RenderLabel};
 use crate::frame_time::FrameTime;
@@ -33,10 +35,75 @@ pub fn escape_on_exit(mut appstate: ResMut<AppState>, mut keyboard_events: Event
     }
 }
 
-pub fn update_physics(mut physics: ResMut<PhysicsWorld>, frame_time: Res<FrameTime>) {
-    physics.update(frame_time.delta);
+pub fn cycle_debug_view(mut debug_view: ResMut<DebugView>, mut keyboard_events: EventReader<KeyboardEvent>) {
+    for _ in keyboard_events
+        .iter()
+        .filter(|e| e.code == VirtualKeyCode::F1 && e.pressed)
+    {
+        *debug_view = debug_view.next();
+        log::warn!("Debug view: {:?}", *debug_view);
+    }
 }
 
+// Steps the physics at its fixed rate. The unfiltered frame time is used so that the
+// simulated time matches the real time.
+pub fn update_physics(
+    mut physics: ResMut<PhysicsWorld>,
+    mut timestep: ResMut<PhysicsTimestep>,
+    settings: Res<PhysicsSettings>,
+    frame_time: Res<FrameTime>,
+    mut bodies: Query<&mut PhysicsBody>,
+) {
+    let steps = timestep.advance(frame_time.raw_delta);
+    for _ in 0..steps {
+        for mut body in bodies.iter_mut() {
+            body.save_previous_pose(&physics);
+        }
+        physics.update(timestep.dt(), &settings);
+    }
+}
+
+// Sends on the collision events of the last physics steps, for the colliders that belong
+// to entities. The entities of removed colliders are forgotten once their events are sent.
+pub fn send_collision_events(
+    mut physics: ResMut<PhysicsWorld>,
+    mut started_events: EventWriter<CollisionStarted>,
+    mut ended_events: EventWriter<CollisionEnded>,
+    mut force_events: EventWriter<ContactForce>,
+    mut enter_events: EventWriter<TriggerEnter>,
+    mut exit_events: EventWriter<TriggerExit>,
+) {
+    for event in physics.drain_collision_events() {
+        let (Some(a), Some(b)) = (physics.collider_entity(event.collider1()), physics.collider_entity(event.collider2())) else {
+            continue;
+        };
+        if event.sensor() {
+            let (trigger, other) = if physics.is_trigger(event.collider1()) { (a, b) } else { (b, a) };
+            if event.started() {
+                enter_events.send(TriggerEnter { trigger, other });
+            } else {
+                exit_events.send(TriggerExit { trigger, other });
+            }
+        } else if event.started() {
+            started_events.send(CollisionStarted { a, b });
+        } else {
+            ended_events.send(CollisionEnded { a, b });
+        }
+    }
+    for event in physics.drain_contact_force_events() {
+        let (Some(a), Some(b)) = (physics.collider_entity(event.collider1), physics.collider_entity(event.collider2)) else {
+            continue;
+        };
+        force_events.send(ContactForce {
+            a,
+            b,
+            total_force: event.total_force,
+            max_force_magnitude: event.max_force_magnitude,
+            max_force_direction: event.max_force_direction,
+        });
+    }
+    physics.forget_removed_colliders();
+}
 
 pub fn update_frame_time(appstate: Res<AppState>,
     mut frame_time: ResMut<FrameTime>, mut frametime_events: EventReader<FrameTimeEvent>) {
@@ -53,3 +120,126 @@ pub fn update_frame_time(appstate: Res<AppState>,
 
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{ColliderShape, PhysicsBodyParams, PhysicsMaterial, Transform, TriggerVolume};
+    use crate::math::Vec3f;
+
+    // Just the physics systems, with frames long enough for two steps each
+    struct PhysicsApp {
+        world: World,
+        schedule: Schedule,
+    }
+
+    impl PhysicsApp {
+        fn new() -> Self {
+            let mut world = World::new();
+            world.insert_resource(PhysicsWorld::new());
+            world.insert_resource(PhysicsTimestep::default());
+            world.insert_resource(PhysicsSettings { gravity: Vec3f::zeros(), ..Default::default() });
+            world.insert_resource(FrameTime::new());
+            world.init_resource::<Events<CollisionStarted>>();
+            world.init_resource::<Events<CollisionEnded>>();
+            world.init_resource::<Events<ContactForce>>();
+            world.init_resource::<Events<TriggerEnter>>();
+            world.init_resource::<Events<TriggerExit>>();
+            let mut schedule = Schedule::default();
+            schedule.add_systems((
+                (
+                    PhysicsBody::register_entities,
+                    PhysicsBody::remove_deleted,
+                    TriggerVolume::register_entities,
+                    TriggerVolume::remove_deleted,
+                ).before(update_physics),
+                update_physics,
+                send_collision_events.after(update_physics),
+            ));
+            Self { world, schedule }
+        }
+
+        fn frame(&mut self) {
+            let dt = 2.5 / self.world.resource::<PhysicsTimestep>().rate;
+            self.world.resource_mut::<FrameTime>().update(Some(std::time::Duration::from_secs_f32(dt)));
+            self.schedule.run(&mut self.world);
+            self.world.clear_trackers();
+        }
+
+        fn spawn_trigger(&mut self) -> Entity {
+            let transform = Transform::from_position(Vec3f::zeros());
+            let trigger = TriggerVolume::new(ColliderShape::Ball { radius: 2.0 }, &transform, &mut self.world.resource_mut());
+            self.world.spawn((trigger, transform)).id()
+        }
+
+        fn spawn_ball(&mut self) -> Entity {
+            let body = PhysicsBody::new(PhysicsBodyParams {
+                pos: Vec3f::zeros(),
+                scale: Vec3f::from_element(1.0),
+                rotation_angle: 0.0,
+                rotation_axis: Vec3f::y(),
+                movable: true,
+                shape: ColliderShape::Ball { radius: 0.5 },
+                material: PhysicsMaterial::default(),
+                gravity_scale: None,
+                lin_vel: None,
+                ang_vel: None,
+                contact_force_threshold: None,
+                ccd: None,
+            }, &mut self.world.resource_mut());
+            self.world.spawn(body).id()
+        }
+
+        fn events<E: Event + Copy>(&mut self) -> Vec<E> {
+            self.world.resource_mut::<Events<E>>().drain().collect()
+        }
+    }
+
+    fn pairs(events: &[TriggerExit]) -> Vec<(Entity, Entity)> {
+        events.iter().map(|e| (e.trigger, e.other)).collect()
+    }
+
+    #[test]
+    fn removed_body_exits_the_trigger() {
+        let mut app = PhysicsApp::new();
+        let trigger = app.spawn_trigger();
+        let ball = app.spawn_ball();
+        app.frame();
+        let entered: Vec<TriggerEnter> = app.events();
+        assert_eq!(entered.iter().map(|e| (e.trigger, e.other)).collect::<Vec<_>>(), [(trigger, ball)]);
+
+        app.world.despawn(ball);
+        app.frame();
+        assert_eq!(pairs(&app.events()), [(trigger, ball)]);
+        app.frame();
+        assert!(app.events::<TriggerExit>().is_empty());
+    }
+
+    #[test]
+    fn removed_trigger_reports_what_was_inside() {
+        let mut app = PhysicsApp::new();
+        let trigger = app.spawn_trigger();
+        let ball = app.spawn_ball();
+        app.frame();
+        app.world.despawn(trigger);
+        app.frame();
+        assert_eq!(pairs(&app.events()), [(trigger, ball)]);
+        assert_eq!(app.world.resource::<PhysicsWorld>().colliders.len(), 1);
+    }
+
+    #[test]
+    fn removal_is_reported_after_a_frame_without_steps() {
+        let mut app = PhysicsApp::new();
+        let trigger = app.spawn_trigger();
+        let ball = app.spawn_ball();
+        app.frame();
+        app.world.despawn(ball);
+        // Too short for a step, so the removal is only seen by the next one
+        app.world.resource_mut::<FrameTime>().update(Some(std::time::Duration::ZERO));
+        app.schedule.run(&mut app.world);
+        app.world.clear_trackers();
+        assert!(app.events::<TriggerExit>().is_empty());
+        app.frame();
+        assert_eq!(pairs(&app.events()), [(trigger, ball)]);
+    }
+}
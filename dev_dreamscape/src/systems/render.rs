@@ -1,21 +1,24 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::math::Rect;
-use crate::components::{Camera, Light, ModelSpec, Player, Skybox, Transform};
+use crate::components::{Camera, Light, ModelSpec, Player, RenderTarget, ShaderMaterial, Skybox, Transform};
 use crate::assets::Assets;
-use crate::model::Model;
-use crate::renderers::{HdrPipeline, SkyboxPass, PhongConfig, PhongPass};
+use crate::renderers::{DebugLines, DebugView, HdrPipeline, LinePass, MaterialNode, MaterialPass, PostProcess, PostProcessPass, RenderContext, RenderGraph, Sketch, SketchPass, SkyboxPass, PhongNode, PhongPass};
 
+use crate::capture::{PanoramaSettings, Screenshots, TextureReadback};
 use crate::device::Device;
+use crate::frame_time::FrameTime;
 use bevy_ecs::prelude::*;
+use bevy_ecs::system::SystemParam;
 
 
 // TODO Load also shaders, meshes, etc.
 #[derive(Resource)]
 pub struct Renderers {
-    pub skybox_renderer: SkyboxPass,
-    pub phong_renderer: PhongPass,
     pub hdr_pipeline: HdrPipeline,
+    // Passes rendering each view into the hdr texture and then the view's target.
+    // Other passes can be added to it, e.g. before "tonemap" to work on the hdr image.
+    pub graph: RenderGraph,
 }
 
 impl Renderers {
@@ -30,72 +33,121 @@ impl Renderers {
         );
     
         let color_format = hdr_pipeline.format();
-    
-        let skybox_renderer = SkyboxPass::new(
-            &device,
-            color_format,
-        );
-    
-        let phong_renderer = PhongPass::new(
-            &PhongConfig { wireframe: false },
-            &device,
-            color_format,
-        );
+
+        let mut graph = RenderGraph::default();
+        graph.add_pass(SkyboxPass::new(device, color_format));
+        graph.add_pass(PhongPass::new(device, color_format));
+        graph.add_pass(MaterialPass::new(device, color_format));
+        graph.add_pass(LinePass::new(device, color_format));
+        graph.add_pass(SketchPass::new(device, color_format));
+        graph.add_pass(PostProcessPass::new(device));
 
         Self {
-            skybox_renderer, 
-            phong_renderer,
             hdr_pipeline,
+            graph,
         }
     }
+
+    // Frees the hdr textures and graph resources no view used since the previous call.
+    // Called once per frame.
+    pub fn end_frame(&mut self) {
+        self.hdr_pipeline.end_frame();
+        self.graph.end_frame();
+    }
+}
+
+// The scene and the resources it's rendered with, shared by the render system and the
+// App's offscreen captures
+#[derive(SystemParam)]
+pub struct SceneParams<'w, 's> {
+    pub device: Res<'w, Device>,
+    pub assets: Res<'w, Assets>,
+    pub cameras: Query<'w, 's, (&'static Camera, &'static Transform)>,
+    pub player: Query<'w, 's, (&'static Camera, &'static Transform), With<Player>>,
+    pub skyboxes: Query<'w, 's, &'static Skybox>,
+    pub meshes: Query<'w, 's, (&'static ModelSpec, &'static Transform), Without<ShaderMaterial>>,
+    pub materials: Query<'w, 's, (&'static ModelSpec, &'static Transform, &'static ShaderMaterial)>,
+    pub lights: Query<'w, 's, (&'static Light, &'static Transform)>,
+    pub frame_time: Res<'w, FrameTime>,
+    pub debug_view: Res<'w, DebugView>,
+    pub debug_lines: Res<'w, DebugLines>,
+    pub sketch: Res<'w, Sketch>,
+    pub post_process: Res<'w, PostProcess>,
 }
 
 pub fn render_to_texture(
-    device: &Device,
-    assets: Res<Assets>,
-    mut renderers: ResMut<Renderers>,
-    camera_qry: Query<(&Camera, &Transform), With<Player>>,
-    skybox_qry: Query<&Skybox>,
-    meshes_qry: Query<(&ModelSpec, &Transform)>,
-    lights_qry: Query<(&Light, &Transform)>,
+    renderers: &mut Renderers,
+    scene: &SceneParams,
+    camera: (&Camera, &Transform),
+    post_process: &PostProcess,
     color_texture: &wgpu::Texture,
     viewport: Option<Rect>,
     clear: bool) {
 
-    let camera = camera_qry.single();
-    let skybox = skybox_qry.single();
-    
-    // Get skybox texture
-    let skybox_texture =  assets.texture_store.get(&skybox.texture_name).unwrap();
+    let device = &*scene.device;
+    let assets = &*scene.assets;
 
     //
     // Gather models to render
     //
 
-    // Group by ModelSpec
+    // Group by ModelSpec, skipping instances outside the view
     // TODO: use ModelSpec as key?
-    let mut instances: HashMap<&String, Vec<&Transform>> = HashMap::new();        
-    for (model_spec, transform) in meshes_qry.iter() {
-        instances.entry(&model_spec.modelname)
+    let frustum = camera.0.frustum(camera.1);
+    let mut instances: HashMap<(&String, Option<&String>), Vec<&Transform>> = HashMap::new();
+    for (model_spec, transform) in scene.meshes.iter() {
+        let model = assets.model_store.get(&model_spec.modelname).unwrap();
+        let radius = model.bounding_radius * transform.scale().amax();
+        if !frustum.intersects_sphere(&transform.position(), radius) {
+            continue;
+        }
+        instances.entry((&model_spec.modelname, model_spec.diffuse_texture.as_ref()))
             .or_insert_with(Vec::new)
             .push(transform);
     }
 
-    // Lookup Model from ModelSpec and flatten to vector
-    let mut nodes: Vec<(&Model, &String, Vec<&Transform>)> = vec![];
-    for (modelname, transforms) in instances.into_iter() {
+    // Lookup Model (and texture override) from ModelSpec and flatten to vector
+    let mut nodes: Vec<PhongNode> = vec![];
+    for ((modelname, texture_name), transforms) in instances.into_iter() {
         //println!("nodes modelname: {:?}",modelname);
         let model =  assets.model_store.get(modelname).unwrap();
-        nodes.push((model, modelname, transforms));
+        let diffuse_texture = texture_name.and_then(|name| {
+            let texture = assets.texture_store.get(name);
+            if texture.is_none() {
+                log::warn!("Texture {} for {} not found", name, modelname);
+            }
+            texture.map(|texture| (name, texture))
+        });
+        nodes.push(PhongNode { model, modelname, diffuse_texture, transforms });
     }
 
+    // Group material instances by model and material. Materials differing only in
+    // param values are separate nodes, as each node has a single params uniform.
+    let mut material_instances: HashMap<(&String, String), (&ShaderMaterial, Vec<&Transform>)> = HashMap::new();
+    for (model_spec, transform, material) in scene.materials.iter() {
+        let model = assets.model_store.get(&model_spec.modelname).unwrap();
+        let radius = model.bounding_radius * transform.scale().amax();
+        if !frustum.intersects_sphere(&transform.position(), radius) {
+            continue;
+        }
+        let material_key = format!("{}|{:?}|{:?}", material.layout_key(), material.uniform_data(), material.textures);
+        material_instances.entry((&model_spec.modelname, material_key))
+            .or_insert_with(|| (material, vec![]))
+            .1.push(transform);
+    }
+    let material_nodes: Vec<MaterialNode> = material_instances.into_iter()
+        .map(|((modelname, _), (material, transforms))| MaterialNode {
+            model: assets.model_store.get(modelname).unwrap(),
+            material,
+            transforms,
+        })
+        .collect();
+
     // Gather light models
     let mut lights: Vec<(&Light, &Transform)> = vec![];
-    for (light, transform) in lights_qry.iter() {
+    for (light, transform) in scene.lights.iter() {
         lights.push((light, transform));
     }
-    // TODO: don't hardcode. We rely on the same mode for all lights for instancing atm.
-    let light_model = assets.model_store.get("sphere.obj").unwrap();
 
     //
     // Render passes
@@ -114,70 +166,138 @@ pub fn render_to_texture(
         renderers.hdr_pipeline.resize(device, target_width, target_height);
     }
 
-    // Need to create new views as the borrow checker complains about about multiple refs.
-    // TODO: find a better solution
-    let hdr_view = renderers.hdr_pipeline.texture().create_view(&wgpu::TextureViewDescriptor::default());
-    let color_view = color_texture.create_view(&wgpu::TextureViewDescriptor::default());
-    let depth_view = renderers.hdr_pipeline.depth_texture().create_view(&wgpu::TextureViewDescriptor::default());
-
-    // Skypass pass
-    // TODO: Use Skybox Query to make skybox config dynamic
-    let skybox_cmd_buffer = renderers.skybox_renderer.draw(
-        &hdr_view,
-        &device,
-        camera,
-        (&skybox.texture_name, &skybox_texture),
-        true,
-    );
-
-    // Phong pass
-    let phong_cmd_buffer = renderers.phong_renderer.draw(
-        &hdr_view,
-        &depth_view,
-        &device,
-        &device.queue(),
-        &nodes,
+    let ctx = RenderContext {
+        device,
+        assets,
+        hdr_pipeline: &renderers.hdr_pipeline,
         camera,
-        &lights,
-        light_model,
-        false,
-        true,
-    );
-
-    let hdr_cmd_buffer = renderers.hdr_pipeline.process(&device, &color_view, viewport);
-
-    device.queue().submit([
-        skybox_cmd_buffer,
-        phong_cmd_buffer,
-        hdr_cmd_buffer
-    ]);
+        skybox: scene.skyboxes.single(),
+        nodes: &nodes,
+        material_nodes: &material_nodes,
+        lights: &lights,
+        debug_view: *scene.debug_view,
+        debug_lines: &scene.debug_lines,
+        sketch: &scene.sketch,
+        post_process,
+        frame_time: &scene.frame_time,
+        output: color_texture,
+        viewport,
+        clear,
+    };
+    let cmd_buffers = renderers.graph.execute(&ctx);
+
+    let _submit_span = tracing::info_span!("submit").entered();
+    device.queue().submit(cmd_buffers);
+}
+
+
+
+// Renders the cameras drawing into textures, in priority order, so materials and
+// the cameras on other targets see this frame's contents. The first camera drawing
+// to each texture clears it.
+pub fn render_texture_targets(renderers: &mut Renderers, scene: &SceneParams) {
+
+    let mut cameras: Vec<_> = scene.cameras.iter()
+        .filter(|(camera, _)| matches!(camera.target, RenderTarget::Texture(_)))
+        .collect();
+    cameras.sort_by_key(|(camera, _)| camera.priority);
+
+    let mut cleared_targets = HashSet::new();
+    for (camera, transform) in cameras {
+        let RenderTarget::Texture(name) = &camera.target else { continue };
+        let Some(texture) = scene.assets.texture_store.get(name) else {
+            log::warn!("Camera render target {} not found", name);
+            continue;
+        };
+        let color_texture = &texture.texture;
+        let viewport = camera.viewport_rect(color_texture.width(), color_texture.height());
+        let clear = cleared_targets.insert(&camera.target);
+        let _span = tracing::info_span!("camera", target = ?camera.target).entered();
+        render_to_texture(renderers, scene, (camera, transform), &scene.post_process, color_texture, viewport, clear);
+    }
 }
 
+// Renders each view into its own offscreen texture and starts reading it back, e.g. for
+// the faces of a panorama. With hdr the untonemapped buffer is read back as well.
+pub fn render_views_for_readback(
+    renderers: &mut Renderers,
+    scene: &SceneParams,
+    views: &[(Camera, Transform)],
+    post_process: &PostProcess,
+    width: u32,
+    height: u32,
+    hdr: bool) -> Vec<(TextureReadback, Option<TextureReadback>)> {
 
+    let device = &*scene.device;
+    views.iter().map(|(camera, transform)| {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("View Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: device.surface_texture_format(),
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        render_to_texture(renderers, scene, (camera, transform), post_process, &texture, None, true);
+        // Submitted after the view's passes, so it copies this view even though the
+        // hdr texture is reused for the next one
+        let color = TextureReadback::new(device, &texture);
+        let hdr = hdr.then(|| TextureReadback::new(device, renderers.hdr_pipeline.texture()));
+        (color, hdr)
+    }).collect()
+}
 
 pub fn render(
-    device: ResMut<Device>,
-    assets: Res<Assets>,
-    renderers: ResMut<Renderers>,
-    camera_qry: Query<(&Camera, &Transform), With<Player>>,
-    skybox_qry: Query<&Skybox>,
-    meshes_qry: Query<(&ModelSpec, &Transform)>,
-    lights_qry: Query<(&Light, &Transform)>,
+    mut renderers: ResMut<Renderers>,
+    scene: SceneParams,
+    mut screenshots: ResMut<Screenshots>,
 ) {
+    let device = &*scene.device;
     let surface = device.surface(); 
-    let surface_texture = surface.get_current_texture().unwrap();
+    let surface_texture = tracing::info_span!("acquire_surface_texture")
+        .in_scope(|| surface.get_current_texture().unwrap());
     
-    render_to_texture(
-                &device,
-                assets,
-                renderers,
-                camera_qry,
-                skybox_qry,
-                meshes_qry,
-                lights_qry,
-                &surface_texture.texture,
-                None,
-                true);
-
-    surface_texture.present();
+    render_texture_targets(&mut renderers, &scene);
+
+    let mut cameras: Vec<_> = scene.cameras.iter()
+        .filter(|(camera, _)| camera.target == RenderTarget::Window)
+        .collect();
+    cameras.sort_by_key(|(camera, _)| camera.priority);
+
+    // The first camera drawing to the window clears it
+    for (i, (camera, transform)) in cameras.into_iter().enumerate() {
+        let color_texture = &surface_texture.texture;
+        let viewport = camera.viewport_rect(color_texture.width(), color_texture.height());
+        let _span = tracing::info_span!("camera", target = ?camera.target).entered();
+        render_to_texture(&mut renderers, &scene, (camera, transform), &scene.post_process, color_texture, viewport, i == 0);
+    }
+
+    if screenshots.is_requested() {
+        match screenshots.panorama {
+            Some(settings) => {
+                let (camera, transform) = scene.player.single();
+                let views = settings.face_views(transform, camera.znear(), camera.zfar());
+                let face_post_process = PanoramaSettings::face_post_process(&scene.post_process);
+                let faces = render_views_for_readback(
+                    &mut renderers,
+                    &scene,
+                    &views,
+                    &face_post_process,
+                    settings.face_size(),
+                    settings.face_size(),
+                    screenshots.save_hdr);
+                screenshots.capture_panorama(settings, faces);
+            }
+            None => screenshots.capture(device, &surface_texture.texture, renderers.hdr_pipeline.texture()),
+        }
+    }
+
+    tracing::info_span!("present").in_scope(|| surface_texture.present());
+    renderers.end_frame();
 }
@@ -3,26 +3,38 @@ use bevy_ecs::schedule::ScheduleLabel;
 use crate::frame_time::FrameTime;
 use crate::math::{Vec3, Vec3f, UnitQuat};
 use crate::systems::{
+        cycle_debug_view,
         escape_on_exit,
         //grab_cursor,
         resize_device,
         update_input_state,
         update_frame_time,
         update_physics,
+        send_collision_events,
         render,
 };
 use crate::components::{
+    Camera,
     FloorBox, 
     FreeBox,
+    GravityZone,
+    Joint,
+    PhysicsMaterial,
     Light, 
     Player,
     PlayerHands,
+    ReferenceScene,
     Skybox,
     Transform,
+    TriggerVolume,
     Rock
     //PlayerTarget
 };
 use crate::components::PhysicsBody;
+use crate::capture::Screenshots;
+use crate::events::{CollisionEnded, CollisionStarted, ContactForce, JointBroken, TriggerEnter, TriggerExit};
+use crate::physics_debug::PhysicsDebugRender;
+use crate::renderers::{DebugLines, Sketch};
 
 
 #[derive(ScheduleLabel, Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -47,6 +59,17 @@ pub fn new_spawn_scene_schedule(webxr: bool) -> (Schedule, SpawnLabel) {
 }
 
 
+// Replaces the spawn schedule for the reference scene (App::load_reference_scene)
+pub fn new_reference_scene_schedule() -> (Schedule, SpawnLabel) {
+    let mut schedule = Schedule::default();
+    schedule
+        .add_systems(ReferenceScene::spawn.run_if(run_once()))
+        .add_systems(Player::spawn.run_if(run_once()))
+        .add_systems(Light::spawn.run_if(run_once()));
+    (schedule, SpawnLabel)
+}
+
+
 #[derive(ScheduleLabel, Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct PreupdateLabel;
 
@@ -55,10 +78,24 @@ pub fn new_preupdate_schedule() -> (Schedule, PreupdateLabel) {
     schedule
         .add_systems((
             escape_on_exit,
+            cycle_debug_view,
+            PhysicsDebugRender::toggle,
+            Screenshots::request_on_key,
+            Screenshots::save_finished,
+            DebugLines::clear,
             //grab_cursor,
             resize_device,
+            Camera::update_aspect.after(resize_device),
             update_input_state,
+            Sketch::update_mouse.after(update_input_state),
             update_frame_time,
+            // Other events are read every frame, but nothing has to read these
+            Events::<CollisionStarted>::update_system,
+            Events::<CollisionEnded>::update_system,
+            Events::<ContactForce>::update_system,
+            Events::<TriggerEnter>::update_system,
+            Events::<TriggerExit>::update_system,
+            Events::<JointBroken>::update_system,
         ));
     (schedule, PreupdateLabel)
 }
@@ -71,8 +108,21 @@ pub fn new_update_schedule() -> (Schedule, UpdateLabel) {
     let mut schedule = Schedule::default();
     schedule
         .add_systems(update_lights)
+        .add_systems(GravityZone::apply.before(update_physics))
+        .add_systems(PhysicsBody::register_entities.before(update_physics))
+        .add_systems(PhysicsBody::remove_deleted.before(update_physics))
+        .add_systems(TriggerVolume::register_entities.before(update_physics))
+        .add_systems(TriggerVolume::remove_deleted.before(update_physics))
+        .add_systems(TriggerVolume::follow_transform.before(update_physics))
+        .add_systems(PhysicsMaterial::update.before(update_physics))
+        .add_systems(Joint::sync.before(update_physics))
+        .add_systems(Joint::remove_deleted.before(update_physics))
         .add_systems(update_physics)
+        .add_systems(Joint::break_overloaded.after(update_physics))
+        .add_systems(send_collision_events.after(update_physics))
         .add_systems(PhysicsBody::sync.after(update_physics))
+        .add_systems(PhysicsBody::despawn_out_of_bounds.after(update_physics))
+        .add_systems(PhysicsDebugRender::update.after(update_physics))
         .add_systems(Player::update.after(update_physics))
         //.add_system(PlayerTarget::update.after(Player::update))
         //.add_system(Grab::grab_or_release.after(Player::update))
@@ -15,6 +15,7 @@ pub fn update_input_state(
     for e in mouse_events.iter() {
         match e {
             MouseEvent::Move(dx, dy) => input.on_mouse_move((*dx, *dy)),
+            MouseEvent::CursorMoved(x, y) => input.on_cursor_move((*x, *y)),
             MouseEvent::Button { button, pressed } => input.on_mouse_button(*button, *pressed),
         }
     }
@@ -0,0 +1,20 @@
+// Fixtures shared by the unit tests and the golden tests (tests/golden.rs), which render
+// on a software adapter. Without one the tests are skipped, and say so.
+
+#[cfg(test)]
+use crate::device::Device;
+
+// Passes the value through, noting that the current test is skipped when there's none
+pub fn skip_without_adapter<T>(value: Option<T>) -> Option<T> {
+    if value.is_none() {
+        let thread = std::thread::current();
+        eprintln!("Skipping {}: no software adapter", thread.name().unwrap_or("test"));
+    }
+    value
+}
+
+// A small headless device on a software adapter, or None for skipped tests
+#[cfg(test)]
+pub(crate) fn headless_device() -> Option<Device> {
+    skip_without_adapter(pollster::block_on(Device::try_new_headless(64, 64, true)))
+}
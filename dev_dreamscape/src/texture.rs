@@ -313,7 +313,11 @@ impl Texture {
             }
             imgs
         };
-        
+        Self::cubemap_from_images(images, device, queue)
+    }
+
+    // Faces in the order px, nx, py, ny, pz, nz
+    pub fn cubemap_from_images(images: Vec<RgbaImage>, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
         let width = images[0].width();
         let height = images[0].height();
 
@@ -143,6 +143,7 @@ impl WebXRApp {
         let last_frame_time = Rc::new(RefCell::new(0.));
 
         *g.borrow_mut() = Some(Closure::new(move | time: f64, frame: XrFrame| {
+            let _frame_span = tracing::info_span!("xr_frame").entered();
             let sess: XrSession = frame.session();
             let mut app = app.borrow_mut();
             let ref_space = &ref_space.borrow_mut();
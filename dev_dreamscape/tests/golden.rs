@@ -0,0 +1,154 @@
+// Golden image tests for the render pipeline.
+//
+// Each test renders the reference scene (App::load_reference_scene, built from primitives
+// so it doesn't need the git lfs assets) headlessly on a software adapter and compares it
+// against the reference PNG in tests/golden/. Without a software adapter the tests are skipped.
+//
+// When a comparison fails, the rendered image and a diff image are written to
+// target/golden/. After an intended change to the shaders, regenerate the references with
+//
+//     DREAMSCAPE_BLESS=1 cargo test --test golden
+
+use dev_dreamscape::testing::skip_without_adapter;
+use dev_dreamscape::{App, DebugView};
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 180;
+const FRAME_DURATION: Duration = Duration::from_micros(16_667);
+
+// Per pixel colour distance (YIQ, as in pixelmatch) above which a pixel counts as different
+const PIXEL_THRESHOLD: f32 = 0.1;
+// Fraction of pixels that may differ, to allow for small rasterization differences
+const MAX_DIFFERENT_PIXELS: f32 = 0.002;
+
+#[test]
+fn shaded() {
+    check_golden("shaded", DebugView::Shaded, 1);
+}
+
+#[test]
+fn shaded_after_one_second() {
+    // Lights have moved and the cube and ball have dropped onto the ground
+    check_golden("shaded_after_one_second", DebugView::Shaded, 60);
+}
+
+#[test]
+fn normals() {
+    check_golden("normals", DebugView::Normals, 1);
+}
+
+#[test]
+fn uvs() {
+    check_golden("uvs", DebugView::Uvs, 1);
+}
+
+#[test]
+fn depth() {
+    check_golden("depth", DebugView::Depth, 1);
+}
+
+// Renders `frames` frames of the reference scene and returns the last one, or None
+// without a software adapter
+fn render_scene(debug_view: DebugView, frames: u32) -> Option<RgbaImage> {
+    pollster::block_on(async {
+        let mut app = App::try_new_headless(WIDTH, HEIGHT, true).await?;
+        app.world.insert_resource(debug_view);
+        app.load_reference_scene();
+        for _ in 1..frames {
+            app.update_scene(FRAME_DURATION);
+        }
+        Some(app.render_to_image(FRAME_DURATION))
+    })
+}
+
+fn check_golden(name: &str, debug_view: DebugView, frames: u32) {
+    let Some(image) = skip_without_adapter(render_scene(debug_view, frames)) else {
+        return;
+    };
+
+    let reference_path = golden_dir().join(format!("{}.png", name));
+    if std::env::var_os("DREAMSCAPE_BLESS").is_some() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        image.save(&reference_path).unwrap();
+        return;
+    }
+
+    let reference = image::open(&reference_path)
+        .unwrap_or_else(|e| panic!(
+            "Couldn't open reference {:?} ({}). Run with DREAMSCAPE_BLESS=1 to create it.",
+            reference_path, e,
+        ))
+        .to_rgba8();
+    assert_eq!(
+        reference.dimensions(), image.dimensions(),
+        "{}: reference has a different size", name,
+    );
+
+    let (diff, num_different) = compare(&reference, &image);
+    let fraction = num_different as f32 / (WIDTH * HEIGHT) as f32;
+    if fraction > MAX_DIFFERENT_PIXELS {
+        let output_dir = output_dir();
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let actual_path = output_dir.join(format!("{}-actual.png", name));
+        let diff_path = output_dir.join(format!("{}-diff.png", name));
+        image.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+        panic!(
+            "{}: {} pixels ({:.3}%) differ from the reference, see {:?} and {:?}",
+            name, num_different, fraction * 100.0, actual_path, diff_path,
+        );
+    }
+}
+
+// Returns a diff image, with the differing pixels in red over a faded copy
+// of the reference, and the number of differing pixels.
+fn compare(reference: &RgbaImage, image: &RgbaImage) -> (RgbaImage, usize) {
+    let mut diff = RgbaImage::new(reference.width(), reference.height());
+    let mut num_different = 0;
+    for (x, y, expected) in reference.enumerate_pixels() {
+        let actual = image.get_pixel(x, y);
+        if color_distance(expected, actual) > PIXEL_THRESHOLD {
+            num_different += 1;
+            diff.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+        } else {
+            let faded = (255.0 - (255.0 - luma(expected)) * 0.2) as u8;
+            diff.put_pixel(x, y, Rgba([faded, faded, faded, 255]));
+        }
+    }
+    (diff, num_different)
+}
+
+// Perceptual distance in YIQ space, normalised to 0..1 (black vs white).
+// See "Measuring perceived color difference using YIQ NTSC transmission color space" (Kotsarenko, Ramos).
+fn color_distance(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let [ar, ag, ab] = blend_on_white(a);
+    let [br, bg, bb] = blend_on_white(b);
+    let (dr, dg, db) = (ar - br, ag - bg, ab - bb);
+    let y = dr * 0.2988953 + dg * 0.5866225 + db * 0.1144822;
+    let i = dr * 0.595978 - dg * 0.2741761 - db * 0.3218019;
+    let q = dr * 0.2114702 - dg * 0.5226171 + db * 0.3111469;
+    // Largest possible value of the weighted sum, for black vs white
+    const MAX_DELTA: f32 = 35215.0;
+    ((0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / MAX_DELTA).sqrt()
+}
+
+fn blend_on_white(color: &Rgba<u8>) -> [f32; 3] {
+    let alpha = color[3] as f32 / 255.0;
+    [0, 1, 2].map(|c| 255.0 + (color[c] as f32 - 255.0) * alpha)
+}
+
+fn luma(color: &Rgba<u8>) -> f32 {
+    let [r, g, b] = blend_on_white(color);
+    r * 0.2988953 + g * 0.5866225 + b * 0.1144822
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("golden")
+}